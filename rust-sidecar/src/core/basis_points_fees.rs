@@ -0,0 +1,88 @@
+//! Basis Point Fee Conversions
+//!
+//! Conversions between `BasisPoints` and the fee encodings used by individual
+//! protocols, kept next to the type rather than in each DEX module.
+
+use crate::core::{BasisPoints, MathError};
+use crate::dex::balancer::math::calculate_balancer_swap_fee_from_bps;
+use ethers::types::U256;
+
+impl BasisPoints {
+    /// Basis points representing 100%
+    pub const MAX: u32 = 10000;
+
+    /// Build a fee rate from a `(fee_amount, total_amount)` pair
+    ///
+    /// Standardizes the `fee_amount * 10000 / total_amount` pattern: the division
+    /// is done in `u128` (both values are shifted down together when they exceed
+    /// 2^114, which keeps the ratio exact to far below 1 bps) and rounds down.
+    ///
+    /// # Arguments
+    /// * `fee_amount` - Fee (or cost) amount
+    /// * `total_amount` - Amount the fee is charged on
+    ///
+    /// # Returns
+    /// * `Ok(BasisPoints)` - `fee_amount / total_amount` in basis points
+    /// * `Err(MathError)` - If `total_amount` is zero or the rate exceeds 100%
+    pub fn from_fee_amount(
+        fee_amount: U256,
+        total_amount: U256,
+    ) -> Result<BasisPoints, MathError> {
+        if total_amount.is_zero() {
+            return Err(MathError::DivisionByZero {
+                operation: "BasisPoints::from_fee_amount".to_string(),
+                context: format!("total_amount is zero (fee_amount={})", fee_amount),
+            });
+        }
+
+        if fee_amount > total_amount {
+            return Err(MathError::InvalidInput {
+                operation: "BasisPoints::from_fee_amount".to_string(),
+                reason: "Fee rate exceeds 10000 bps".to_string(),
+                context: format!("fee_amount={}, total_amount={}", fee_amount, total_amount),
+            });
+        }
+
+        // fee <= total < 2^114 after shifting, so fee * 10000 < 2^128
+        let shift = total_amount.bits().saturating_sub(114);
+        let fee = (fee_amount >> shift).as_u128();
+        let total = (total_amount >> shift).as_u128();
+
+        Ok(BasisPoints::new_const((fee * Self::MAX as u128 / total) as u32))
+    }
+
+    /// Convert to Balancer's 18-decimal fee format (bps * BALANCER_FEE_SCALE)
+    pub fn to_balancer_fee(&self) -> U256 {
+        calculate_balancer_swap_fee_from_bps(self.as_u32())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_basis_points_from_fee_amount() {
+        let one_eth = U256::from(1_000_000_000_000_000_000u128);
+
+        // 0.003 / 1 = 30 bps
+        let fee = one_eth * U256::from(3) / U256::from(1000);
+        let bps = BasisPoints::from_fee_amount(fee, one_eth).unwrap();
+        assert_eq!(bps.as_u32(), 30);
+
+        // Rounds down
+        let bps = BasisPoints::from_fee_amount(U256::from(2), U256::from(30000)).unwrap();
+        assert_eq!(bps.as_u32(), 0);
+
+        // Full fee is exactly MAX; amounts beyond u128 keep precision
+        let huge = U256::one() << 200;
+        assert_eq!(BasisPoints::from_fee_amount(huge, huge).unwrap().as_u32(), BasisPoints::MAX);
+        assert_eq!(
+            BasisPoints::from_fee_amount(huge / U256::from(2), huge).unwrap().as_u32(),
+            5000
+        );
+
+        assert!(BasisPoints::from_fee_amount(one_eth, U256::zero()).is_err());
+        assert!(BasisPoints::from_fee_amount(one_eth + U256::one(), one_eth).is_err());
+    }
+}
@@ -0,0 +1,230 @@
+//! MathError Context
+//!
+//! Helpers for adding context to a `MathError` as it propagates up a call
+//! stack, kept next to the error type so new variants are handled in one place.
+//!
+//! Errors from protocol-generic paths (routing, batch evaluation) carry the
+//! protocol whose math failed as a `ProtocolMathError`, so mixed multi-DEX logs
+//! read `[Curve] ...` and callers can match on the protocol directly.
+//!
+//! Tagging happens at the `PoolQuoter` / `PoolSimulator` boundary only. The
+//! per-DEX entry points (`calculate_v3_amount_out`, `calculate_dy_with_iterations`,
+//! `calculate_kyber_amount_out`, ...) still return a plain `MathError`: their
+//! caller already knows which DEX it called, and the DEX modules compose each
+//! other's functions with `?`. Callers that log errors from several DEXes side
+//! by side tag them with `MathError::with_protocol` before logging.
+//!
+//! Slippage-guarded quotes fail with a `SlippageError`, which separates a
+//! rejected quote (`SlippageExceeded`) from a failure of the swap math itself.
+
+use crate::core::{BasisPoints, MathError};
+use crate::dex::protocol::DexProtocol;
+use std::fmt;
+
+impl MathError {
+    /// Add call-site context to an error while keeping its variant and inputs
+    ///
+    /// The context is prepended to the error's own context as `"context: original"`,
+    /// so repeated chaining up a call stack reads outermost first. Prefer this over
+    /// re-wrapping errors in `InvalidInput`, which loses the original variant.
+    pub fn chain(self, context: &str) -> MathError {
+        let prepend = |original: String| {
+            if original.is_empty() {
+                context.to_string()
+            } else {
+                format!("{}: {}", context, original)
+            }
+        };
+
+        match self {
+            MathError::InvalidInput {
+                operation,
+                reason,
+                context: original,
+            } => MathError::InvalidInput {
+                operation,
+                reason,
+                context: prepend(original),
+            },
+            MathError::Overflow {
+                operation,
+                inputs,
+                context: original,
+            } => MathError::Overflow {
+                operation,
+                inputs,
+                context: prepend(original),
+            },
+            MathError::Underflow {
+                operation,
+                inputs,
+                context: original,
+            } => MathError::Underflow {
+                operation,
+                inputs,
+                context: prepend(original),
+            },
+            MathError::DivisionByZero {
+                operation,
+                context: original,
+            } => MathError::DivisionByZero {
+                operation,
+                context: prepend(original),
+            },
+        }
+    }
+
+    /// Tag an error with the protocol whose math produced it
+    pub fn with_protocol(self, protocol: DexProtocol) -> ProtocolMathError {
+        ProtocolMathError {
+            protocol: Some(protocol),
+            error: self,
+        }
+    }
+}
+
+/// A `MathError` tagged with the protocol whose math produced it
+///
+/// Produced by the protocol-generic pool interfaces and the paths built on them
+/// (arbitrage, batch evaluation); see the module docs for what is not tagged.
+#[derive(Debug, Clone)]
+pub struct ProtocolMathError {
+    /// Protocol whose math failed (`None` for protocol-independent checks)
+    pub protocol: Option<DexProtocol>,
+    /// Underlying error
+    pub error: MathError,
+}
+
+impl ProtocolMathError {
+    /// Tag with `protocol` unless already tagged, keeping the innermost protocol
+    pub fn with_protocol(self, protocol: DexProtocol) -> ProtocolMathError {
+        ProtocolMathError {
+            protocol: self.protocol.or(Some(protocol)),
+            error: self.error,
+        }
+    }
+}
+
+impl From<MathError> for ProtocolMathError {
+    fn from(error: MathError) -> Self {
+        ProtocolMathError {
+            protocol: None,
+            error,
+        }
+    }
+}
+
+impl fmt::Display for ProtocolMathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.protocol {
+            Some(protocol) => write!(f, "[{}] {}", protocol, self.error),
+            None => write!(f, "{}", self.error),
+        }
+    }
+}
+
+/// Failure of a slippage-guarded quote
+#[derive(Debug, Clone)]
+pub enum SlippageError {
+    /// Output fell further below the ideal output than allowed
+    SlippageExceeded {
+        /// Measured shortfall versus the ideal output
+        actual_bps: BasisPoints,
+        /// Maximum accepted shortfall
+        max_bps: BasisPoints,
+    },
+    /// The underlying swap math failed
+    Math(MathError),
+}
+
+impl From<MathError> for SlippageError {
+    fn from(error: MathError) -> Self {
+        SlippageError::Math(error)
+    }
+}
+
+impl fmt::Display for SlippageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SlippageError::SlippageExceeded {
+                actual_bps,
+                max_bps,
+            } => write!(
+                f,
+                "Slippage exceeded: {} bps > {} bps",
+                actual_bps.as_u32(),
+                max_bps.as_u32()
+            ),
+            SlippageError::Math(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::types::U256;
+
+    #[test]
+    fn test_math_error_chain() {
+        let err = MathError::Overflow {
+            operation: "calculate_derivative".to_string(),
+            inputs: vec![U256::one()],
+            context: "sqrt ratio at tick+1".to_string(),
+        };
+
+        match err.chain("In newton_iteration").chain("In sqrt_price_to_tick") {
+            MathError::Overflow { operation, inputs, context } => {
+                assert_eq!(operation, "calculate_derivative");
+                assert_eq!(inputs, vec![U256::one()]);
+                assert_eq!(
+                    context,
+                    "In sqrt_price_to_tick: In newton_iteration: sqrt ratio at tick+1"
+                );
+            }
+            other => panic!("chain changed the error variant: {:?}", other),
+        }
+
+        let err = MathError::DivisionByZero {
+            operation: "mul_div".to_string(),
+            context: String::new(),
+        };
+        match err.chain("In get_amount0_delta") {
+            MathError::DivisionByZero { context, .. } => {
+                assert_eq!(context, "In get_amount0_delta")
+            }
+            other => panic!("chain changed the error variant: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_with_protocol() {
+        let error = MathError::DivisionByZero {
+            operation: "[calculate_dy]".to_string(),
+            context: "denominator is zero".to_string(),
+        };
+
+        // The innermost protocol wins; operation strings are never rewritten
+        let tagged = error
+            .with_protocol(DexProtocol::Curve)
+            .with_protocol(DexProtocol::UniswapV3);
+        assert_eq!(tagged.protocol, Some(DexProtocol::Curve));
+        match tagged.error {
+            MathError::DivisionByZero { operation, context } => {
+                assert_eq!(operation, "[calculate_dy]");
+                assert_eq!(context, "denominator is zero");
+            }
+            other => panic!("variant changed: {:?}", other),
+        }
+
+        let untagged = ProtocolMathError::from(MathError::DivisionByZero {
+            operation: "mul_div".to_string(),
+            context: String::new(),
+        });
+        assert_eq!(untagged.protocol, None);
+        assert_eq!(
+            untagged.with_protocol(DexProtocol::Balancer).protocol,
+            Some(DexProtocol::Balancer)
+        );
+    }
+}
@@ -0,0 +1,301 @@
+//! Cross-DEX Arbitrage Simulation
+//!
+//! Evaluates 2-hop arbitrage paths (buy on pool i, sell on pool j) across
+//! V3, Curve and Balancer pools that share a token pair.
+//!
+//! ## Search Strategy
+//!
+//! With N pools there are N*(N-1) ordered paths. Every path is first scored with
+//! a cheap linear scan over a fixed grid of input sizes; only the top candidates
+//! with positive sampled profit get the full golden section refinement used by
+//! the per-protocol sandwich optimizers.
+
+use crate::core::{MathError, ProtocolMathError};
+use crate::dex::optimizer::golden_section_maximize;
+use crate::dex::protocol::{PoolQuoter, PoolSimulator};
+use ethers::types::{Address, U256};
+
+/// Number of evenly spaced input sizes sampled per path in the linear scan
+const LINEAR_SCAN_POINTS: u64 = 10;
+
+/// Number of top linear-scan candidates refined with the full optimizer
+const REFINED_CANDIDATES: usize = 3;
+
+/// Golden section iterations per refined candidate
+const REFINE_ITERATIONS: usize = 40;
+
+/// A pool tracked by the registry
+pub struct RegisteredPool {
+    /// Pool contract address
+    pub address: Address,
+    /// Pool tokens, in the pool's own index order
+    pub tokens: Vec<Address>,
+    /// Protocol-specific pool state
+    pub state: Box<dyn PoolSimulator + Send + Sync>,
+}
+
+impl RegisteredPool {
+    /// Index of `token` in this pool, if the pool holds it
+    pub fn token_index(&self, token: Address) -> Option<usize> {
+        self.tokens.iter().position(|t| *t == token)
+    }
+}
+
+/// Set of pools available for routing
+#[derive(Default)]
+pub struct PoolRegistry {
+    pools: Vec<RegisteredPool>,
+}
+
+impl PoolRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a pool to the registry
+    pub fn register(&mut self, pool: RegisteredPool) {
+        self.pools.push(pool);
+    }
+
+    /// Pool registered at `address`, if any
+    pub fn get(&self, address: Address) -> Option<&RegisteredPool> {
+        self.pools.iter().find(|pool| pool.address == address)
+    }
+
+    /// Pools holding both tokens, with each token's index in that pool
+    pub fn pools_for_pair(
+        &self,
+        token_a: Address,
+        token_b: Address,
+    ) -> Vec<(&RegisteredPool, usize, usize)> {
+        self.pools
+            .iter()
+            .filter_map(|pool| {
+                Some((pool, pool.token_index(token_a)?, pool.token_index(token_b)?))
+            })
+            .collect()
+    }
+}
+
+/// Best 2-hop arbitrage found for a token pair
+#[derive(Debug, Clone)]
+pub struct ArbitrageOpportunity {
+    /// Pool where `token_in` is sold for `token_out`
+    pub buy_pool: Address,
+    /// Pool where `token_out` is sold back for `token_in`
+    pub sell_pool: Address,
+    /// Optimal `token_in` amount
+    pub amount_in: U256,
+    /// Profit in `token_in` (before gas)
+    pub profit: U256,
+}
+
+/// Batch evaluator for cross-DEX 2-hop arbitrage
+pub struct CrossDexArbitrageSimulator {
+    pub registry: PoolRegistry,
+}
+
+impl CrossDexArbitrageSimulator {
+    pub fn new(registry: PoolRegistry) -> Self {
+        Self { registry }
+    }
+
+    /// Find the most profitable buy-on-i / sell-on-j path for a token pair
+    ///
+    /// # Arguments
+    /// * `token_in` - Token borrowed and returned (profit denomination)
+    /// * `token_out` - Intermediate token
+    /// * `max_amount` - Upper bound on the `token_in` amount
+    ///
+    /// # Returns
+    /// * `Ok(Some(ArbitrageOpportunity))` - Best profitable path and amount
+    /// * `Ok(None)` - No path is profitable
+    /// * `Err(ProtocolMathError)` - If `max_amount` is zero, tokens are identical or a quote fails
+    pub fn find_best_arbitrage_path(
+        &self,
+        token_in: Address,
+        token_out: Address,
+        max_amount: U256,
+    ) -> Result<Option<ArbitrageOpportunity>, ProtocolMathError> {
+        if max_amount.is_zero() || token_in == token_out {
+            return Err(ProtocolMathError::from(MathError::InvalidInput {
+                operation: "find_best_arbitrage_path".to_string(),
+                reason: "max_amount must be non-zero and tokens must differ".to_string(),
+                context: format!(
+                    "token_in={:?}, token_out={:?}, max_amount={}",
+                    token_in, token_out, max_amount
+                ),
+            }));
+        }
+
+        let pools = self.registry.pools_for_pair(token_in, token_out);
+
+        // Linear scan: score every ordered (buy, sell) pair on a coarse grid
+        let mut candidates: Vec<(usize, usize, U256)> = Vec::new();
+        for (buy_idx, buy) in pools.iter().enumerate() {
+            for (sell_idx, sell) in pools.iter().enumerate() {
+                if buy_idx == sell_idx {
+                    continue;
+                }
+
+                let mut best_sampled = U256::zero();
+                for k in 1..=LINEAR_SCAN_POINTS {
+                    let amount = max_amount * U256::from(k) / U256::from(LINEAR_SCAN_POINTS);
+                    best_sampled = best_sampled.max(path_profit(buy, sell, amount)?);
+                }
+
+                if !best_sampled.is_zero() {
+                    candidates.push((buy_idx, sell_idx, best_sampled));
+                }
+            }
+        }
+
+        tracing::debug!(
+            "Cross-DEX arbitrage: {} pools, {} profitable candidates",
+            pools.len(),
+            candidates.len()
+        );
+
+        // Refine only the top candidates
+        candidates.sort_by(|a, b| b.2.cmp(&a.2));
+        let mut best: Option<ArbitrageOpportunity> = None;
+        for &(buy_idx, sell_idx, _) in candidates.iter().take(REFINED_CANDIDATES) {
+            let (buy, sell) = (&pools[buy_idx], &pools[sell_idx]);
+            let (amount_in, profit) = refine_path(buy, sell, max_amount)?;
+            if best.as_ref().map_or(true, |current| profit > current.profit) {
+                best = Some(ArbitrageOpportunity {
+                    buy_pool: buy.0.address,
+                    sell_pool: sell.0.address,
+                    amount_in,
+                    profit,
+                });
+            }
+        }
+
+        Ok(best.filter(|opportunity| !opportunity.profit.is_zero()))
+    }
+}
+
+/// Profit of buying on `buy` and selling on `sell` (0 if negative)
+fn path_profit(
+    buy: &(&RegisteredPool, usize, usize),
+    sell: &(&RegisteredPool, usize, usize),
+    amount_in: U256,
+) -> Result<U256, ProtocolMathError> {
+    // Each tuple is (pool, token_in index, token_out index); the sell leg swaps back
+    let (buy_pool, buy_token_in, buy_token_out) = *buy;
+    let (sell_pool, sell_token_in, sell_token_out) = *sell;
+
+    if amount_in.is_zero() {
+        return Ok(U256::zero());
+    }
+    let intermediate = buy_pool
+        .state
+        .quote_amount_out(amount_in, buy_token_in, buy_token_out)?;
+    // Dust inputs round to nothing on the first leg; that is a loss, not a failure
+    if intermediate.is_zero() {
+        return Ok(U256::zero());
+    }
+    let amount_back = sell_pool
+        .state
+        .quote_amount_out(intermediate, sell_token_out, sell_token_in)?;
+    Ok(amount_back.saturating_sub(amount_in))
+}
+
+/// Golden section search for the best input on a single path
+fn refine_path(
+    buy: &(&RegisteredPool, usize, usize),
+    sell: &(&RegisteredPool, usize, usize),
+    max_amount: U256,
+) -> Result<(U256, U256), ProtocolMathError> {
+    golden_section_maximize(U256::zero(), max_amount, REFINE_ITERATIONS, |amount_in| {
+        path_profit(buy, sell, amount_in)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::BasisPoints;
+    use crate::dex::protocol::DexProtocol;
+    use crate::dex::uniswap_v3::math::V3PoolState;
+
+    fn v3_pool(address: u64, sqrt_price_x96: U256) -> RegisteredPool {
+        RegisteredPool {
+            address: Address::from_low_u64_be(address),
+            tokens: vec![Address::from_low_u64_be(100), Address::from_low_u64_be(200)],
+            state: Box::new(V3PoolState {
+                sqrt_price_x96,
+                liquidity: 1_000_000_000_000_000_000_000u128,
+                tick: 0,
+                fee_bps: BasisPoints::new_const(5),
+            }),
+        }
+    }
+
+    #[test]
+    fn test_find_best_arbitrage_path() {
+        let q96 = U256::from(79228162514264337593543950336u128);
+        let mut registry = PoolRegistry::new();
+        // Pool 1 at price 1.0, pool 2 at ~1.1 (sqrt ~1.0488)
+        registry.register(v3_pool(1, q96));
+        registry.register(v3_pool(2, q96 * U256::from(10488) / U256::from(10000)));
+
+        let simulator = CrossDexArbitrageSimulator::new(registry);
+        let token0 = Address::from_low_u64_be(100);
+        let token1 = Address::from_low_u64_be(200);
+        let max_amount = U256::from(10_000_000_000_000_000_000u128); // 10 tokens
+
+        let opportunity = simulator
+            .find_best_arbitrage_path(token0, token1, max_amount)
+            .unwrap()
+            .expect("price gap should be arbitrageable");
+
+        // token0 buys more token1 in the higher-priced pool, then sells back in the cheaper one
+        assert_eq!(opportunity.buy_pool, Address::from_low_u64_be(2));
+        assert_eq!(opportunity.sell_pool, Address::from_low_u64_be(1));
+        assert!(opportunity.profit > U256::zero());
+        assert!(opportunity.amount_in <= max_amount);
+    }
+
+    #[test]
+    fn test_find_best_arbitrage_path_no_opportunity() {
+        let q96 = U256::from(79228162514264337593543950336u128);
+        let mut registry = PoolRegistry::new();
+        registry.register(v3_pool(1, q96));
+        registry.register(v3_pool(2, q96));
+
+        let simulator = CrossDexArbitrageSimulator::new(registry);
+        let token0 = Address::from_low_u64_be(100);
+        let token1 = Address::from_low_u64_be(200);
+        let max_amount = U256::from(10_000_000_000_000_000_000u128);
+
+        // Identical prices: fees make every path unprofitable
+        assert!(simulator
+            .find_best_arbitrage_path(token0, token1, max_amount)
+            .unwrap()
+            .is_none());
+        assert!(simulator
+            .find_best_arbitrage_path(token0, token0, max_amount)
+            .is_err());
+    }
+
+    #[test]
+    fn test_find_best_arbitrage_path_propagates_quote_errors() {
+        let q96 = U256::from(79228162514264337593543950336u128);
+        let mut registry = PoolRegistry::new();
+        registry.register(v3_pool(1, q96));
+        // Zero sqrt price: every quote on this pool fails
+        registry.register(v3_pool(2, U256::zero()));
+
+        let simulator = CrossDexArbitrageSimulator::new(registry);
+        let token0 = Address::from_low_u64_be(100);
+        let token1 = Address::from_low_u64_be(200);
+        let max_amount = U256::from(10_000_000_000_000_000_000u128);
+
+        let err = simulator
+            .find_best_arbitrage_path(token0, token1, max_amount)
+            .unwrap_err();
+        assert_eq!(err.protocol, Some(DexProtocol::UniswapV3));
+    }
+}
@@ -0,0 +1,251 @@
+//! Parallel Sandwich Candidate Evaluation
+//!
+//! A busy mempool can hold dozens of sandwich candidates at once. Each
+//! candidate is simulated against its own copy of the pool state, so
+//! evaluations are independent and run in parallel on a dedicated rayon pool.
+//!
+//! The pool leaves two cores free for networking and bundle signing. This
+//! module is only built with the `parallel-evaluation` feature, which pulls in
+//! the optional `rayon` dependency.
+
+#![cfg(feature = "parallel-evaluation")]
+
+use crate::core::ProtocolMathError;
+use crate::dex::arbitrage::{PoolRegistry, RegisteredPool};
+use crate::dex::optimizer::golden_section_maximize;
+use crate::dex::protocol::{PoolQuoter, PoolSimulator};
+use crate::dex::sandwich::{SandwichCandidate, SandwichOpportunity};
+use ethers::types::U256;
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuildError, ThreadPoolBuilder};
+
+/// Cores kept free for networking and signing
+const RESERVED_CORES: usize = 2;
+
+/// Golden section iterations per candidate
+const OPTIMIZATION_ITERATIONS: usize = 40;
+
+/// Evaluates sandwich candidates in parallel on a dedicated thread pool
+pub struct BatchSandwichEvaluator {
+    thread_pool: ThreadPool,
+}
+
+impl BatchSandwichEvaluator {
+    /// Create an evaluator using all but `RESERVED_CORES` cores (at least one thread)
+    pub fn new() -> Result<Self, ThreadPoolBuildError> {
+        let cores = std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1);
+        let num_threads = cores.saturating_sub(RESERVED_CORES).max(1);
+
+        tracing::debug!(
+            "Batch sandwich evaluator: {} threads ({} cores)",
+            num_threads,
+            cores
+        );
+
+        let thread_pool = ThreadPoolBuilder::new()
+            .num_threads(num_threads)
+            .thread_name(|i| format!("sandwich-eval-{}", i))
+            .build()?;
+        Ok(Self { thread_pool })
+    }
+
+    /// Number of worker threads
+    pub fn num_threads(&self) -> usize {
+        self.thread_pool.current_num_threads()
+    }
+
+    /// Evaluate every candidate, preserving input order
+    ///
+    /// # Arguments
+    /// * `candidates` - Pending victim swaps
+    /// * `pool_registry` - Current pool states
+    ///
+    /// # Returns
+    /// * `Vec<Result<Option<SandwichOpportunity>, ProtocolMathError>>` - One entry per
+    ///   candidate, as returned by `evaluate_single`
+    pub fn evaluate_batch(
+        &self,
+        candidates: Vec<SandwichCandidate>,
+        pool_registry: &PoolRegistry,
+    ) -> Vec<Result<Option<SandwichOpportunity>, ProtocolMathError>> {
+        self.thread_pool.install(|| {
+            candidates
+                .par_iter()
+                .map(|candidate| evaluate_single(candidate, pool_registry))
+                .collect()
+        })
+    }
+}
+
+/// Evaluate one candidate
+///
+/// # Arguments
+/// * `candidate` - Pending victim swap
+/// * `pool_registry` - Current pool states
+///
+/// # Returns
+/// * `Ok(Some(SandwichOpportunity))` - Best frontrun and its profit
+/// * `Ok(None)` - The pool or a token is unknown, or no frontrun is profitable
+/// * `Err(ProtocolMathError)` - If a swap simulation fails while sizing the frontrun
+pub fn evaluate_single(
+    candidate: &SandwichCandidate,
+    pool_registry: &PoolRegistry,
+) -> Result<Option<SandwichOpportunity>, ProtocolMathError> {
+    let resolved = pool_registry.get(candidate.pool).and_then(|pool| {
+        Some((
+            pool,
+            pool.token_index(candidate.token_in)?,
+            pool.token_index(candidate.token_out)?,
+        ))
+    });
+    let (pool, token_in, token_out) = match resolved {
+        Some(resolved) => resolved,
+        None => return Ok(None),
+    };
+    if token_in == token_out || candidate.victim_amount_in.is_zero() {
+        return Ok(None);
+    }
+
+    // Keep the victim output of each probe so the winner needs no re-simulation
+    let mut victim_outputs = Vec::with_capacity(OPTIMIZATION_ITERATIONS + 3);
+    let (frontrun_amount, expected_profit) = golden_section_maximize(
+        U256::zero(),
+        candidate.victim_amount_in,
+        OPTIMIZATION_ITERATIONS,
+        |amount| {
+            let (profit, victim_amount_out) =
+                simulate_sandwich(pool, candidate, token_in, token_out, amount)?;
+            victim_outputs.push((amount, victim_amount_out));
+            Ok::<_, ProtocolMathError>(profit)
+        },
+    )?;
+    if expected_profit.is_zero() {
+        return Ok(None);
+    }
+
+    let victim_amount_out = victim_outputs
+        .iter()
+        .rev()
+        .find(|(amount, _)| *amount == frontrun_amount)
+        .map(|(_, out)| *out)
+        .unwrap_or_default();
+    Ok(Some(SandwichOpportunity {
+        pool: candidate.pool,
+        frontrun_amount,
+        expected_profit,
+        victim_amount_out,
+    }))
+}
+
+/// Replay frontrun, victim and backrun; returns (profit, victim output)
+///
+/// Profit is zero when the victim's swap would revert on its slippage limit.
+fn simulate_sandwich(
+    pool: &RegisteredPool,
+    candidate: &SandwichCandidate,
+    token_in: usize,
+    token_out: usize,
+    frontrun_amount: U256,
+) -> Result<(U256, U256), ProtocolMathError> {
+    if frontrun_amount.is_zero() {
+        return Ok((U256::zero(), U256::zero()));
+    }
+
+    let (after_frontrun, frontrun_out) =
+        pool.state.simulate_swap(frontrun_amount, token_in, token_out)?;
+    let (after_victim, victim_out) =
+        after_frontrun.simulate_swap(candidate.victim_amount_in, token_in, token_out)?;
+    if victim_out < candidate.victim_min_amount_out || frontrun_out.is_zero() {
+        return Ok((U256::zero(), victim_out));
+    }
+
+    let backrun_out = after_victim.quote_amount_out(frontrun_out, token_out, token_in)?;
+    Ok((backrun_out.saturating_sub(frontrun_amount), victim_out))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::BasisPoints;
+    use crate::dex::protocol::DexProtocol;
+    use crate::dex::uniswap_v3::math::V3PoolState;
+    use ethers::types::Address;
+
+    fn registry() -> PoolRegistry {
+        let mut registry = PoolRegistry::new();
+        registry.register(RegisteredPool {
+            address: Address::from_low_u64_be(1),
+            tokens: vec![Address::from_low_u64_be(100), Address::from_low_u64_be(200)],
+            state: Box::new(V3PoolState {
+                sqrt_price_x96: U256::from(79228162514264337593543950336u128),
+                liquidity: 1_000_000_000_000_000_000_000u128,
+                tick: 0,
+                fee_bps: BasisPoints::new_const(5),
+            }),
+        });
+        registry
+    }
+
+    fn candidate(pool: u64, victim_amount_in: u128, min_amount_out: u128) -> SandwichCandidate {
+        SandwichCandidate {
+            pool: Address::from_low_u64_be(pool),
+            token_in: Address::from_low_u64_be(100),
+            token_out: Address::from_low_u64_be(200),
+            victim_amount_in: U256::from(victim_amount_in),
+            victim_min_amount_out: U256::from(min_amount_out),
+        }
+    }
+
+    #[test]
+    fn test_evaluate_batch() {
+        let evaluator = BatchSandwichEvaluator::new().unwrap();
+        assert!(evaluator.num_threads() >= 1);
+
+        let victim = 100_000_000_000_000_000_000u128; // 100 tokens, no slippage limit
+        let candidates = vec![
+            candidate(1, victim, 0),
+            candidate(2, victim, 0),      // unknown pool
+            candidate(1, victim, victim), // any frontrun breaks the victim's limit
+        ];
+
+        let results = evaluator.evaluate_batch(candidates.clone(), &registry());
+        assert_eq!(results.len(), 3);
+
+        let opportunity = results[0]
+            .as_ref()
+            .unwrap()
+            .as_ref()
+            .expect("large victim should be sandwichable");
+        assert!(opportunity.expected_profit > U256::zero());
+        assert!(opportunity.frontrun_amount <= U256::from(victim));
+        assert!(opportunity.victim_amount_out > U256::zero());
+        assert!(results[1].as_ref().unwrap().is_none());
+        assert!(results[2].as_ref().unwrap().is_none());
+
+        // Parallel and sequential evaluation agree
+        let sequential = evaluate_single(&candidates[0], &registry()).unwrap().unwrap();
+        assert_eq!(sequential.expected_profit, opportunity.expected_profit);
+        assert_eq!(sequential.victim_amount_out, opportunity.victim_amount_out);
+    }
+
+    #[test]
+    fn test_evaluate_single_propagates_simulation_errors() {
+        let mut registry = PoolRegistry::new();
+        registry.register(RegisteredPool {
+            address: Address::from_low_u64_be(1),
+            tokens: vec![Address::from_low_u64_be(100), Address::from_low_u64_be(200)],
+            state: Box::new(V3PoolState {
+                sqrt_price_x96: U256::zero(),
+                liquidity: 1_000_000_000_000_000_000_000u128,
+                tick: 0,
+                fee_bps: BasisPoints::new_const(5),
+            }),
+        });
+
+        let victim = 100_000_000_000_000_000_000u128;
+        let err = evaluate_single(&candidate(1, victim, 0), &registry).unwrap_err();
+        assert_eq!(err.protocol, Some(DexProtocol::UniswapV3));
+    }
+}
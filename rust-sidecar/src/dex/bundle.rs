@@ -0,0 +1,480 @@
+//! Flashbots Bundle Construction
+//!
+//! Turns sandwich simulation results into an `eth_sendBundle` payload. A
+//! sandwich bundle is only valid in the exact order [frontrun, victim, backrun];
+//! the builder enforces that order regardless of the order legs are added in.
+//!
+//! Our own legs are kept as calldata plus gas parameters and are signed by the
+//! searcher key when the payload is serialized; the victim transaction is
+//! forwarded exactly as seen in the mempool.
+//!
+//! Before a bundle is built, the victim's decoded swap parameters are checked
+//! against the pool state after our frontrun, so bundles whose victim would
+//! revert (price limit, minimum output, deadline) are dropped early.
+
+use crate::core::{BasisPoints, MathError};
+use crate::dex::adapter::SwapDirection;
+use crate::dex::uniswap_v3::math::calculate_v3_amount_out;
+use ethers::types::{Bytes, Transaction, U256};
+use serde_json::{json, Value};
+
+/// Position of a transaction in a sandwich bundle
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum BundleRole {
+    Frontrun,
+    Victim,
+    Backrun,
+}
+
+/// A bundle transaction
+#[derive(Debug, Clone)]
+pub enum SignedTransaction {
+    /// Our frontrun or backrun, signed when the payload is serialized
+    Searcher {
+        role: BundleRole,
+        calldata: Bytes,
+        gas_limit: u64,
+        priority_fee: U256,
+    },
+    /// Victim transaction, already signed by the victim
+    Victim(Transaction),
+}
+
+impl SignedTransaction {
+    /// Bundle position of this transaction
+    pub fn role(&self) -> BundleRole {
+        match self {
+            SignedTransaction::Searcher { role, .. } => *role,
+            SignedTransaction::Victim(_) => BundleRole::Victim,
+        }
+    }
+}
+
+/// Ordered bundle ready for `eth_sendBundle`
+#[derive(Debug, Clone)]
+pub struct FlashbotsBundle {
+    /// Transactions in [frontrun, victim, backrun] order
+    pub transactions: Vec<SignedTransaction>,
+    /// Block the bundle targets
+    pub target_block: u64,
+    /// Earliest valid timestamp (0 = unbounded)
+    pub min_timestamp: u64,
+    /// Latest valid timestamp (0 = unbounded)
+    pub max_timestamp: u64,
+}
+
+impl FlashbotsBundle {
+    /// Build the `eth_sendBundle` JSON-RPC request
+    ///
+    /// # Arguments
+    /// * `id` - JSON-RPC request id
+    /// * `sign` - Signs a searcher leg `(calldata, gas_limit, priority_fee)` into raw bytes
+    ///
+    /// # Returns
+    /// * `Ok(Value)` - JSON-RPC request body
+    /// * `Err(MathError)` - If signing a searcher leg fails
+    pub fn to_json_rpc<F>(&self, id: u64, mut sign: F) -> Result<Value, MathError>
+    where
+        F: FnMut(&Bytes, u64, U256) -> Result<Bytes, MathError>,
+    {
+        let txs = self
+            .transactions
+            .iter()
+            .map(|tx| match tx {
+                SignedTransaction::Searcher {
+                    calldata,
+                    gas_limit,
+                    priority_fee,
+                    ..
+                } => sign(calldata, *gas_limit, *priority_fee),
+                SignedTransaction::Victim(victim_tx) => Ok(victim_tx.rlp()),
+            })
+            .collect::<Result<Vec<Bytes>, MathError>>()?;
+
+        let mut params = json!({
+            "txs": txs,
+            "blockNumber": format!("0x{:x}", self.target_block),
+        });
+        if self.min_timestamp > 0 {
+            params["minTimestamp"] = json!(self.min_timestamp);
+        }
+        if self.max_timestamp > 0 {
+            params["maxTimestamp"] = json!(self.max_timestamp);
+        }
+
+        Ok(json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": "eth_sendBundle",
+            "params": [params],
+        }))
+    }
+}
+
+/// Collects the three sandwich legs into a Flashbots bundle
+#[derive(Debug, Clone, Default)]
+pub struct BundleBuilder {
+    transactions: Vec<SignedTransaction>,
+    target_block: u64,
+    min_timestamp: u64,
+    max_timestamp: u64,
+}
+
+impl BundleBuilder {
+    /// Create a builder for `target_block` (timestamps of 0 leave the bound unset)
+    pub fn new(target_block: u64, min_timestamp: u64, max_timestamp: u64) -> Self {
+        Self {
+            transactions: Vec::with_capacity(3),
+            target_block,
+            min_timestamp,
+            max_timestamp,
+        }
+    }
+
+    /// Set the frontrun leg
+    pub fn add_frontrun(&mut self, calldata: Bytes, gas_limit: u64, priority_fee: U256) {
+        self.set(SignedTransaction::Searcher {
+            role: BundleRole::Frontrun,
+            calldata,
+            gas_limit,
+            priority_fee,
+        });
+    }
+
+    /// Set the victim transaction
+    pub fn add_victim(&mut self, victim_tx: Transaction) {
+        self.set(SignedTransaction::Victim(victim_tx));
+    }
+
+    /// Set the backrun leg
+    pub fn add_backrun(&mut self, calldata: Bytes, gas_limit: u64, priority_fee: U256) {
+        self.set(SignedTransaction::Searcher {
+            role: BundleRole::Backrun,
+            calldata,
+            gas_limit,
+            priority_fee,
+        });
+    }
+
+    /// Insert a transaction into its slot, replacing any previous one
+    fn set(&mut self, tx: SignedTransaction) {
+        let role = tx.role();
+        if let Some(existing) = self.transactions.iter_mut().find(|t| t.role() == role) {
+            tracing::warn!("Replacing {:?} transaction in bundle", role);
+            *existing = tx;
+        } else {
+            self.transactions.push(tx);
+            self.transactions.sort_by_key(|t| t.role());
+        }
+    }
+
+    /// Build the bundle in [frontrun, victim, backrun] order
+    ///
+    /// # Returns
+    /// * `Ok(FlashbotsBundle)` - Complete, ordered bundle
+    /// * `Err(MathError)` - If any of the three legs is missing
+    pub fn build_payload(&self) -> Result<FlashbotsBundle, MathError> {
+        let roles: Vec<BundleRole> = self.transactions.iter().map(|t| t.role()).collect();
+        if roles != [BundleRole::Frontrun, BundleRole::Victim, BundleRole::Backrun] {
+            return Err(MathError::InvalidInput {
+                operation: "build_payload".to_string(),
+                reason: "Bundle needs exactly one frontrun, victim and backrun".to_string(),
+                context: format!("roles={:?}, target_block={}", roles, self.target_block),
+            });
+        }
+
+        Ok(FlashbotsBundle {
+            transactions: self.transactions.clone(),
+            target_block: self.target_block,
+            min_timestamp: self.min_timestamp,
+            max_timestamp: self.max_timestamp,
+        })
+    }
+}
+
+/// Victim V3 swap parameters decoded from calldata (exactInputSingle)
+#[derive(Debug, Clone)]
+pub struct V3SwapParams {
+    /// Exact input amount
+    pub amount_in: U256,
+    /// Minimum output the victim accepts (slippage check)
+    pub min_amount_out: U256,
+    /// Price limit in Q64.96 (zero = no limit)
+    pub sqrt_price_limit_x96: U256,
+    /// Unix timestamp deadline
+    pub deadline: u64,
+    /// Swap direction
+    pub direction: SwapDirection,
+}
+
+/// Pool state after our frontrun executes, plus inclusion timing
+#[derive(Debug, Clone)]
+pub struct StateChange {
+    /// Sqrt price after the frontrun (Q64.96)
+    pub sqrt_price_after: U256,
+    /// Active liquidity after the frontrun
+    pub liquidity_after: u128,
+    /// Pool fee in basis points
+    pub fee_bps: BasisPoints,
+    /// Current Unix timestamp
+    pub current_timestamp: u64,
+    /// Blocks until the bundle is expected to land
+    pub estimated_blocks_to_inclusion: u64,
+}
+
+/// Reason a sandwich bundle would revert on the victim's checks
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BundleRejectReason {
+    /// Frontrun already moved the price past the victim's sqrtPriceLimitX96
+    PriceLimitExceeded,
+    /// Victim output after the frontrun falls below amountOutMinimum
+    InsufficientOutput,
+    /// Victim deadline passes before the bundle can be included
+    DeadlineTooClose,
+    /// Victim output could not be simulated
+    SimulationFailed,
+}
+
+/// Ethereum slot time used to convert blocks to seconds
+const SLOT_TIME_SECONDS: u64 = 12;
+
+/// Validate that the victim swap will not revert after our frontrun
+///
+/// Checks, in order:
+/// 1. The post-frontrun price has not crossed the victim's price limit
+///    (price falls for token0 -> token1, rises for token1 -> token0)
+/// 2. `min_amount_out` is below the victim's simulated output after the frontrun
+/// 3. `deadline > current_timestamp + estimated_blocks_to_inclusion * 12`
+///
+/// # Arguments
+/// * `victim_params` - Victim swap parameters
+/// * `frontrun_state_change` - Pool state after the frontrun
+///
+/// # Returns
+/// * `Ok(())` - Victim will execute
+/// * `Err(BundleRejectReason)` - First failing check
+pub fn validate_swap_calldata_bounds(
+    victim_params: &V3SwapParams,
+    frontrun_state_change: &StateChange,
+) -> Result<(), BundleRejectReason> {
+    let price_after = frontrun_state_change.sqrt_price_after;
+    let limit = victim_params.sqrt_price_limit_x96;
+
+    if !limit.is_zero() {
+        let limit_exceeded = match victim_params.direction {
+            SwapDirection::Token0ToToken1 => price_after <= limit,
+            SwapDirection::Token1ToToken0 => price_after >= limit,
+        };
+        if limit_exceeded {
+            return Err(BundleRejectReason::PriceLimitExceeded);
+        }
+    }
+
+    let victim_output = calculate_v3_amount_out(
+        victim_params.amount_in,
+        price_after,
+        frontrun_state_change.liquidity_after,
+        frontrun_state_change.fee_bps,
+        victim_params.direction,
+    )
+    .map_err(|_| BundleRejectReason::SimulationFailed)?;
+
+    if victim_params.min_amount_out >= victim_output {
+        return Err(BundleRejectReason::InsufficientOutput);
+    }
+
+    let inclusion_timestamp = frontrun_state_change.current_timestamp.saturating_add(
+        frontrun_state_change
+            .estimated_blocks_to_inclusion
+            .saturating_mul(SLOT_TIME_SECONDS),
+    );
+    if victim_params.deadline <= inclusion_timestamp {
+        return Err(BundleRejectReason::DeadlineTooClose);
+    }
+
+    Ok(())
+}
+
+/// Check whether a sandwich bundle can be submitted without the victim reverting
+///
+/// Thin wrapper over `validate_swap_calldata_bounds` for callers that only need
+/// a go/no-go before Flashbots submission.
+pub fn verify_victim_calldata_compatible(
+    victim_params: &V3SwapParams,
+    frontrun_state_change: &StateChange,
+) -> bool {
+    match validate_swap_calldata_bounds(victim_params, frontrun_state_change) {
+        Ok(()) => true,
+        Err(reason) => {
+            tracing::debug!("Rejecting sandwich bundle: victim would revert ({:?})", reason);
+            false
+        }
+    }
+}
+
+/// Minimum outbid over a competitor's priority fee (1 gwei)
+const MIN_PRIORITY_FEE_INCREMENT_WEI: u64 = 1_000_000_000;
+
+/// Calculate the priority fee to bid for a sandwich bundle
+///
+/// Models the builder auction as first-price: we outbid the competitor's expected
+/// priority fee by 10% (at least 1 gwei), but never bid more per gas than the
+/// sandwich's gross profit per gas after the base fee, which would make it a loss.
+/// `bid = min(expected_gross / gas_units - base_fee, competitor_bid + increment)`
+///
+/// # Arguments
+/// * `expected_gross_profit` - Gross profit before gas (wei)
+/// * `competitor_expected_bid` - Estimated competitor priority fee per gas (wei)
+/// * `block_base_fee` - Current base fee per gas (wei)
+/// * `gas_units` - Gas used by the bundle
+///
+/// # Returns
+/// * `U256` - Priority fee per gas (wei); zero if the bundle can't cover the base fee
+pub fn calculate_optimal_priority_fee(
+    expected_gross_profit: U256,
+    competitor_expected_bid: U256,
+    block_base_fee: U256,
+    gas_units: u64,
+) -> U256 {
+    if gas_units == 0 {
+        return U256::zero();
+    }
+
+    // Highest priority fee that still breaks even
+    let max_affordable =
+        (expected_gross_profit / U256::from(gas_units)).saturating_sub(block_base_fee);
+
+    let increment = (competitor_expected_bid / U256::from(10))
+        .max(U256::from(MIN_PRIORITY_FEE_INCREMENT_WEI));
+    let competitive_bid = competitor_expected_bid.saturating_add(increment);
+
+    max_affordable.min(competitive_bid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bundle_builder_orders_legs() {
+        let mut builder = BundleBuilder::new(18_000_000, 0, 0);
+        // Added out of order on purpose
+        builder.add_backrun(Bytes::from(vec![0x02]), 150_000, U256::from(2_000_000_000u64));
+        builder.add_victim(Transaction::default());
+        assert!(builder.build_payload().is_err());
+
+        builder.add_frontrun(Bytes::from(vec![0x01]), 150_000, U256::from(3_000_000_000u64));
+        let bundle = builder.build_payload().unwrap();
+
+        let roles: Vec<BundleRole> = bundle.transactions.iter().map(|t| t.role()).collect();
+        assert_eq!(roles, vec![BundleRole::Frontrun, BundleRole::Victim, BundleRole::Backrun]);
+        assert_eq!(bundle.target_block, 18_000_000);
+    }
+
+    #[test]
+    fn test_bundle_json_rpc_payload() {
+        let mut builder = BundleBuilder::new(0x10, 0, 1_700_000_000);
+        builder.add_frontrun(Bytes::from(vec![0x01]), 150_000, U256::one());
+        builder.add_victim(Transaction::default());
+        builder.add_backrun(Bytes::from(vec![0x02]), 150_000, U256::one());
+
+        // Stub signer: echo the calldata
+        let payload = builder
+            .build_payload()
+            .unwrap()
+            .to_json_rpc(1, |calldata, _, _| Ok(calldata.clone()))
+            .unwrap();
+
+        assert_eq!(payload["method"], "eth_sendBundle");
+        let params = &payload["params"][0];
+        assert_eq!(params["blockNumber"], "0x10");
+        assert_eq!(params["txs"][0], "0x01");
+        assert_eq!(params["txs"][2], "0x02");
+        assert_eq!(params["maxTimestamp"], 1_700_000_000u64);
+        assert!(params.get("minTimestamp").is_none());
+    }
+
+    fn victim_and_state() -> (V3SwapParams, StateChange) {
+        let victim = V3SwapParams {
+            amount_in: U256::from(1_000_000_000_000_000_000u128),
+            min_amount_out: U256::from(900_000_000_000_000_000u128),
+            sqrt_price_limit_x96: U256::zero(),
+            deadline: 1_700_000_120,
+            direction: SwapDirection::Token0ToToken1,
+        };
+        let state = StateChange {
+            sqrt_price_after: U256::from(79228162514264337593543950336u128),
+            liquidity_after: 1_000_000_000_000_000_000_000u128,
+            fee_bps: BasisPoints::new_const(30),
+            current_timestamp: 1_700_000_000,
+            estimated_blocks_to_inclusion: 2,
+        };
+        (victim, state)
+    }
+
+    #[test]
+    fn test_verify_victim_calldata_compatible() {
+        let (victim, state) = victim_and_state();
+        assert!(verify_victim_calldata_compatible(&victim, &state));
+        assert_eq!(validate_swap_calldata_bounds(&victim, &state), Ok(()));
+    }
+
+    #[test]
+    fn test_validate_swap_calldata_bounds_reject_reasons() {
+        let (victim, state) = victim_and_state();
+
+        // Frontrun pushed price below the victim's limit
+        let mut limited = victim.clone();
+        limited.sqrt_price_limit_x96 = state.sqrt_price_after + U256::from(1);
+        assert_eq!(
+            validate_swap_calldata_bounds(&limited, &state),
+            Err(BundleRejectReason::PriceLimitExceeded)
+        );
+
+        // Victim demands more than the post-frontrun output
+        let mut greedy = victim.clone();
+        greedy.min_amount_out = victim.amount_in;
+        assert_eq!(
+            validate_swap_calldata_bounds(&greedy, &state),
+            Err(BundleRejectReason::InsufficientOutput)
+        );
+
+        // Deadline lands before inclusion (2 blocks = 24s)
+        let mut expiring = victim.clone();
+        expiring.deadline = state.current_timestamp + 24;
+        assert_eq!(
+            validate_swap_calldata_bounds(&expiring, &state),
+            Err(BundleRejectReason::DeadlineTooClose)
+        );
+        assert!(!verify_victim_calldata_compatible(&expiring, &state));
+    }
+
+    #[test]
+    fn test_calculate_optimal_priority_fee() {
+        let gwei = U256::from(1_000_000_000u64);
+        let base_fee = U256::from(20) * gwei;
+        let gas_units = 300_000u64;
+
+        // 0.1 ETH gross over 300k gas = ~333 gwei/gas: outbid competitor at 10 gwei by 1 gwei
+        let gross = U256::from(100_000_000_000_000_000u128);
+        let bid =
+            calculate_optimal_priority_fee(gross, U256::from(10) * gwei, base_fee, gas_units);
+        assert_eq!(bid, U256::from(11) * gwei);
+
+        // Large competitor bid: outbid by 10%
+        let bid =
+            calculate_optimal_priority_fee(gross, U256::from(100) * gwei, base_fee, gas_units);
+        assert_eq!(bid, U256::from(110) * gwei);
+
+        // Competitor bids above our break-even: cap at profit per gas minus base fee
+        let bid =
+            calculate_optimal_priority_fee(gross, U256::from(1000) * gwei, base_fee, gas_units);
+        assert_eq!(bid, gross / U256::from(gas_units) - base_fee);
+
+        // Can't cover base fee or no gas: bid nothing
+        assert!(
+            calculate_optimal_priority_fee(U256::from(1000), gwei, base_fee, gas_units).is_zero()
+        );
+        assert!(calculate_optimal_priority_fee(gross, gwei, base_fee, 0).is_zero());
+    }
+}
@@ -0,0 +1,318 @@
+//! Curve / V3 Price Convergence
+//!
+//! After a large trade on one pool, a Curve pool and a V3 pool quoting the same
+//! pair drift apart until arbitrage pulls them back together. The arbitrageur
+//! sells token 0 into the pool where it is expensive and buys the same amount
+//! of token 0 from the pool where it is cheap; the equilibrium is the token 0
+//! amount at which both marginal prices meet.
+//!
+//! ## Assumptions
+//!
+//! - Both tokens use 18 decimals (Curve balances are already 18-decimal scaled)
+//! - The V3 move stays within the active tick range
+//! - Fees do not move prices (they only reduce profit)
+
+use crate::core::{BasisPoints, MathError};
+use crate::dex::curve::math::{
+    calculate_curve_marginal_price, calculate_d, calculate_y, CurvePoolState,
+};
+use crate::dex::protocol::PoolPricer;
+use crate::dex::uniswap_v3::math::V3PoolState;
+use ethers::types::U256;
+
+/// Binary search steps over the arbitrage amount
+const EQUILIBRIUM_SEARCH_STEPS: usize = 192;
+
+/// Price tolerance used by `calculate_profit_to_equilibrium`
+const PROFIT_PRICE_TOLERANCE_BPS: u32 = 1;
+
+/// Pool states after moving `amount0` of token 0 between the pools
+struct ArbitrageLeg {
+    /// Curve marginal price of token 0 in token 1 (18 decimals)
+    curve_price: U256,
+    /// V3 price of token 0 in token 1 (18 decimals)
+    v3_price: U256,
+    /// Token 1 leaving (or, if token 0 is sold into V3, entering) the Curve pool
+    curve_token1_delta: U256,
+    /// Token 1 entering (or, if token 0 is sold into V3, leaving) the V3 pool
+    v3_token1_delta: U256,
+}
+
+/// `a * b / denominator` rounded down, with a 512-bit intermediate
+fn mul_div_floor(a: U256, b: U256, denominator: U256) -> Result<U256, MathError> {
+    if denominator.is_zero() {
+        return Err(MathError::DivisionByZero {
+            operation: "mul_div_floor".to_string(),
+            context: format!("a={}, b={}", a, b),
+        });
+    }
+    U256::try_from(a.full_mul(b) / denominator).map_err(|_| MathError::Overflow {
+        operation: "mul_div_floor".to_string(),
+        inputs: vec![a, b, denominator],
+        context: "Result exceeds U256".to_string(),
+    })
+}
+
+/// Simulate moving `amount0` of token 0 from the cheap pool to the expensive one
+///
+/// `curve_expensive` selects the direction: token 0 is sold into Curve and
+/// bought from V3 when true, and the reverse otherwise. No fees are applied.
+fn move_token0(
+    curve_state: &CurvePoolState,
+    curve_d: U256,
+    v3_state: &V3PoolState,
+    amount0: U256,
+    curve_expensive: bool,
+) -> Result<ArbitrageLeg, MathError> {
+    let invalid = |reason: &str| MathError::InvalidInput {
+        operation: "calculate_equilibrium_price".to_string(),
+        reason: reason.to_string(),
+        context: format!("amount0={}, curve_expensive={}", amount0, curve_expensive),
+    };
+
+    // Curve: token 0 balance moves by amount0 and token 1 rebalances to keep D
+    let balances = &curve_state.balances;
+    let mut curve_post = balances.clone();
+    curve_post[0] = if curve_expensive {
+        balances[0].checked_add(amount0).ok_or_else(|| invalid("Curve balance overflow"))?
+    } else {
+        balances[0]
+            .checked_sub(amount0)
+            .filter(|balance| !balance.is_zero())
+            .ok_or_else(|| invalid("Amount exceeds Curve token 0 balance"))?
+    };
+    curve_post[1] = calculate_y(0, 1, amount0, &curve_post, curve_state.a, curve_d)?;
+    let curve_price = calculate_curve_marginal_price(0, 1, &curve_post, curve_state.a)?;
+    let curve_token1_delta = if curve_expensive {
+        balances[1].saturating_sub(curve_post[1])
+    } else {
+        curve_post[1].saturating_sub(balances[1])
+    };
+
+    // V3: virtual token 0 reserve x = L * Q96 / sqrtP moves by amount0
+    let q96 = U256::from(1u128 << 96);
+    let liquidity = U256::from(v3_state.liquidity);
+    let x_virtual = mul_div_floor(liquidity, q96, v3_state.sqrt_price_x96)?;
+    let x_post = if curve_expensive {
+        x_virtual
+            .checked_sub(amount0)
+            .filter(|x| !x.is_zero())
+            .ok_or_else(|| invalid("Amount exceeds V3 virtual token 0 reserve"))?
+    } else {
+        x_virtual.checked_add(amount0).ok_or_else(|| invalid("V3 reserve overflow"))?
+    };
+    let sqrt_post = mul_div_floor(liquidity, q96, x_post)?;
+    let sqrt_delta = if sqrt_post > v3_state.sqrt_price_x96 {
+        sqrt_post - v3_state.sqrt_price_x96
+    } else {
+        v3_state.sqrt_price_x96 - sqrt_post
+    };
+    let v3_token1_delta = mul_div_floor(liquidity, sqrt_delta, q96)?;
+    let v3_price = V3PoolState {
+        sqrt_price_x96: sqrt_post,
+        ..v3_state.clone()
+    }
+    .spot_price(0, 1)?;
+
+    Ok(ArbitrageLeg {
+        curve_price,
+        v3_price,
+        curve_token1_delta,
+        v3_token1_delta,
+    })
+}
+
+/// Whether two prices are within `tolerance_bps` of the larger one
+fn within_tolerance(a: U256, b: U256, tolerance_bps: BasisPoints) -> bool {
+    let (high, low) = if a > b { (a, b) } else { (b, a) };
+    (high - low).saturating_mul(U256::from(10000))
+        <= high.saturating_mul(U256::from(tolerance_bps.as_u32()))
+}
+
+/// Binary search the token 0 amount that equalizes both pools
+///
+/// Returns the final leg and the direction (`true` if token 0 is sold into Curve).
+fn find_equilibrium(
+    curve_state: &CurvePoolState,
+    v3_state: &V3PoolState,
+    price_tolerance_bps: BasisPoints,
+) -> Result<(ArbitrageLeg, bool), MathError> {
+    if curve_state.balances.len() != 2 || v3_state.liquidity == 0 {
+        return Err(MathError::InvalidInput {
+            operation: "calculate_equilibrium_price".to_string(),
+            reason: "Need a 2-coin Curve pool and a V3 pool with liquidity".to_string(),
+            context: format!(
+                "curve_coins={}, v3_liquidity={}",
+                curve_state.balances.len(),
+                v3_state.liquidity
+            ),
+        });
+    }
+
+    let curve_d = calculate_d(&curve_state.balances, curve_state.a, 2)?;
+    let start = move_token0(curve_state, curve_d, v3_state, U256::zero(), true)?;
+    if within_tolerance(start.curve_price, start.v3_price, price_tolerance_bps) {
+        return Ok((start, true));
+    }
+
+    // Token 0 is sold into the pool where it is expensive, bought where it is cheap
+    let curve_expensive = start.curve_price > start.v3_price;
+    let gap_closed = |leg: &ArbitrageLeg| {
+        if curve_expensive {
+            leg.curve_price <= leg.v3_price
+        } else {
+            leg.v3_price <= leg.curve_price
+        }
+    };
+
+    // Token 0 can be bought out of the cheap pool only up to its reserve
+    let q96 = U256::from(1u128 << 96);
+    let mut high = if curve_expensive {
+        mul_div_floor(U256::from(v3_state.liquidity), q96, v3_state.sqrt_price_x96)?
+    } else {
+        curve_state.balances[0]
+    };
+    let mut low = U256::zero();
+    let mut best = start;
+
+    for _ in 0..EQUILIBRIUM_SEARCH_STEPS {
+        if high - low <= U256::one() {
+            break;
+        }
+        let mid = low + (high - low) / U256::from(2);
+        match move_token0(curve_state, curve_d, v3_state, mid, curve_expensive) {
+            Ok(leg) => {
+                let converged =
+                    within_tolerance(leg.curve_price, leg.v3_price, price_tolerance_bps);
+                if gap_closed(&leg) {
+                    high = mid;
+                } else {
+                    low = mid;
+                }
+                best = leg;
+                if converged {
+                    break;
+                }
+            }
+            // Draining a reserve: overshoot
+            Err(_) => high = mid,
+        }
+    }
+
+    if !within_tolerance(best.curve_price, best.v3_price, price_tolerance_bps) {
+        tracing::warn!(
+            "Curve/V3 equilibrium search ended outside tolerance: curve={}, v3={}",
+            best.curve_price,
+            best.v3_price
+        );
+    }
+
+    Ok((best, curve_expensive))
+}
+
+/// Find the price at which a Curve pool and a V3 pool equilibrate
+///
+/// # Arguments
+/// * `curve_state` - 2-coin Curve pool (token 0 / token 1)
+/// * `v3_state` - V3 pool for the same pair (token 0 / token 1)
+/// * `price_tolerance_bps` - Maximum remaining price gap at equilibrium
+///
+/// # Returns
+/// * `Ok(U256)` - Equilibrium price of token 0 in token 1 (18 decimals)
+/// * `Err(MathError)` - If the pools are invalid or a simulation fails
+pub fn calculate_equilibrium_price(
+    curve_state: &CurvePoolState,
+    v3_state: &V3PoolState,
+    price_tolerance_bps: BasisPoints,
+) -> Result<U256, MathError> {
+    let (leg, _) = find_equilibrium(curve_state, v3_state, price_tolerance_bps)?;
+    Ok((leg.curve_price + leg.v3_price) / U256::from(2))
+}
+
+/// Total arbitrage profit available until the two pools equilibrate
+///
+/// The arbitrageur receives token 1 from the pool it sells token 0 into and
+/// pays token 1 to the pool it buys token 0 from. Each pool's fee is charged on
+/// its token 1 leg: the output is reduced by the fee and the input grossed up.
+///
+/// # Arguments
+/// * `curve_state` - 2-coin Curve pool (token 0 / token 1)
+/// * `v3_state` - V3 pool for the same pair (token 0 / token 1)
+///
+/// # Returns
+/// * `Ok(U256)` - Profit in token 1 (0 if fees exceed the price gap)
+/// * `Err(MathError)` - If the pools are invalid or a simulation fails
+pub fn calculate_profit_to_equilibrium(
+    curve_state: &CurvePoolState,
+    v3_state: &V3PoolState,
+) -> Result<U256, MathError> {
+    let tolerance = BasisPoints::new_const(PROFIT_PRICE_TOLERANCE_BPS);
+    let (leg, curve_expensive) = find_equilibrium(curve_state, v3_state, tolerance)?;
+
+    let curve_fee = curve_state.fee_bps.min(9999);
+    let v3_fee = v3_state.fee_bps.as_u32().min(9999);
+    let (received, received_fee, paid, paid_fee) = if curve_expensive {
+        (leg.curve_token1_delta, curve_fee, leg.v3_token1_delta, v3_fee)
+    } else {
+        (leg.v3_token1_delta, v3_fee, leg.curve_token1_delta, curve_fee)
+    };
+
+    let bps = U256::from(10000);
+    let received_net = mul_div_floor(received, bps - U256::from(received_fee), bps)?;
+    let paid_gross = mul_div_floor(paid, bps, bps - U256::from(paid_fee))?;
+
+    Ok(received_net.saturating_sub(paid_gross))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn curve_pool() -> CurvePoolState {
+        let one = U256::from(10).pow(U256::from(18));
+        CurvePoolState {
+            balances: vec![U256::from(1_000_000) * one, U256::from(1_000_000) * one],
+            a: U256::from(100),
+            fee_bps: 4,
+        }
+    }
+
+    fn v3_pool(sqrt_price_x96: U256) -> V3PoolState {
+        V3PoolState {
+            sqrt_price_x96,
+            liquidity: 10_000_000_000_000_000_000_000_000u128,
+            tick: 0,
+            fee_bps: BasisPoints::new_const(5),
+        }
+    }
+
+    #[test]
+    fn test_calculate_equilibrium_price() {
+        let one = U256::from(10).pow(U256::from(18));
+        let q96 = U256::from(79228162514264337593543950336u128);
+        let tolerance = BasisPoints::new_const(1);
+
+        // Aligned pools: equilibrium is the common price
+        let price = calculate_equilibrium_price(&curve_pool(), &v3_pool(q96), tolerance).unwrap();
+        assert!(within_tolerance(price, one, tolerance));
+        // Only rounding dust is left to capture
+        let profit = calculate_profit_to_equilibrium(&curve_pool(), &v3_pool(q96)).unwrap();
+        assert!(profit < U256::from(1_000u64), "profit={}", profit);
+
+        // V3 at ~1.02 (sqrt ~1.01): equilibrium lies between the two prices
+        let v3 = v3_pool(q96 * U256::from(10100) / U256::from(10000));
+        let v3_price = v3.spot_price(0, 1).unwrap();
+        let price = calculate_equilibrium_price(&curve_pool(), &v3, tolerance).unwrap();
+        assert!(price > one && price < v3_price, "price={}", price);
+
+        // A 2% gap pays well over the 9 bps of combined fees
+        let profit = calculate_profit_to_equilibrium(&curve_pool(), &v3).unwrap();
+        assert!(profit > U256::zero());
+
+        let three_coin = CurvePoolState {
+            balances: vec![one, one, one],
+            ..curve_pool()
+        };
+        assert!(calculate_equilibrium_price(&three_coin, &v3, tolerance).is_err());
+    }
+}
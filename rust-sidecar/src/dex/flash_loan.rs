@@ -0,0 +1,174 @@
+//! Flash Loan Source Selection
+//!
+//! Every sandwich borrows its frontrun capital, so the flash loan premium is a
+//! fixed cost on each bundle. Providers differ in both fee and depth:
+//!
+//! - **Aave V3**: 0.09% premium (9 bps)
+//! - **Balancer**: free, limited to the Vault's balance of the token
+//! - **Euler**: fee depends on pool utilization
+//!
+//! Fees are rounded up, matching the on-chain premium calculation.
+
+use crate::core::{BasisPoints, MathError};
+use ethers::types::{Address, U256};
+
+/// Flash loan protocol
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlashLoanSource {
+    AaveV3,
+    Balancer,
+    Euler,
+}
+
+/// A flash loan provider quote for a token
+#[derive(Debug, Clone)]
+pub struct FlashLoanProvider {
+    /// Protocol offering the loan
+    pub source: FlashLoanSource,
+    /// Premium in basis points (0 for Balancer)
+    pub fee_bps: BasisPoints,
+    /// Amount of the token the provider can lend right now
+    pub max_available: U256,
+}
+
+/// Selected flash loan provider
+#[derive(Debug, Clone)]
+pub struct FlashLoanChoice {
+    /// Chosen provider
+    pub provider: FlashLoanProvider,
+    /// Premium owed on the requested amount
+    pub fee: U256,
+    /// Whether the provider can cover the full amount
+    pub is_available: bool,
+}
+
+/// Flash loan premium on `amount`, rounded up
+fn flash_loan_fee(amount: U256, fee_bps: BasisPoints) -> U256 {
+    let fee_bps = U256::from(fee_bps.as_u32());
+    let numerator = amount.saturating_mul(fee_bps);
+    let fee = numerator / U256::from(10000);
+    if (numerator % U256::from(10000)).is_zero() {
+        fee
+    } else {
+        fee + U256::one()
+    }
+}
+
+/// Select the cheapest flash loan source able to lend `amount`
+///
+/// Providers without enough liquidity (e.g. a Balancer Vault holding less than
+/// `amount` of the token) are skipped. If none can cover the amount, the
+/// deepest provider is returned with `is_available = false` so the caller can
+/// shrink the frontrun.
+///
+/// # Arguments
+/// * `amount` - Amount to borrow
+/// * `token` - Token being borrowed
+/// * `providers` - Provider quotes for `token`
+///
+/// # Returns
+/// * `Ok(FlashLoanChoice)` - Cheapest available provider and its fee
+/// * `Err(MathError)` - If `amount` is zero or no providers are given
+pub fn select_cheapest_flash_loan(
+    amount: U256,
+    token: Address,
+    providers: &[FlashLoanProvider],
+) -> Result<FlashLoanChoice, MathError> {
+    if amount.is_zero() || providers.is_empty() {
+        return Err(MathError::InvalidInput {
+            operation: "select_cheapest_flash_loan".to_string(),
+            reason: "amount must be non-zero and at least one provider is required".to_string(),
+            context: format!(
+                "token={:?}, amount={}, providers={}",
+                token,
+                amount,
+                providers.len()
+            ),
+        });
+    }
+
+    let cheapest = providers
+        .iter()
+        .filter(|provider| provider.max_available >= amount)
+        .min_by_key(|provider| flash_loan_fee(amount, provider.fee_bps));
+
+    if let Some(provider) = cheapest {
+        return Ok(FlashLoanChoice {
+            provider: provider.clone(),
+            fee: flash_loan_fee(amount, provider.fee_bps),
+            is_available: true,
+        });
+    }
+
+    // providers is non-empty, so a deepest provider always exists
+    let deepest = providers
+        .iter()
+        .max_by_key(|provider| provider.max_available)
+        .expect("providers is non-empty");
+    tracing::warn!(
+        "No flash loan provider can lend {} of {:?} (deepest: {:?} with {})",
+        amount,
+        token,
+        deepest.source,
+        deepest.max_available
+    );
+
+    Ok(FlashLoanChoice {
+        provider: deepest.clone(),
+        fee: flash_loan_fee(amount, deepest.fee_bps),
+        is_available: false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn provider(source: FlashLoanSource, fee_bps: u32, max_available: u64) -> FlashLoanProvider {
+        FlashLoanProvider {
+            source,
+            fee_bps: BasisPoints::new_const(fee_bps),
+            max_available: U256::from(max_available),
+        }
+    }
+
+    #[test]
+    fn test_select_cheapest_flash_loan() {
+        let token = Address::from_low_u64_be(1);
+        let providers = vec![
+            provider(FlashLoanSource::AaveV3, 9, 1_000_000),
+            provider(FlashLoanSource::Balancer, 0, 50_000),
+            provider(FlashLoanSource::Euler, 5, 1_000_000),
+        ];
+
+        // Balancer is free and deep enough
+        let choice = select_cheapest_flash_loan(U256::from(10_000u64), token, &providers).unwrap();
+        assert_eq!(choice.provider.source, FlashLoanSource::Balancer);
+        assert!(choice.fee.is_zero());
+        assert!(choice.is_available);
+
+        // Balancer Vault too shallow: Euler beats Aave (5 bps of 100_000 = 50)
+        let choice = select_cheapest_flash_loan(U256::from(100_000u64), token, &providers).unwrap();
+        assert_eq!(choice.provider.source, FlashLoanSource::Euler);
+        assert_eq!(choice.fee, U256::from(50));
+        assert!(choice.is_available);
+    }
+
+    #[test]
+    fn test_select_cheapest_flash_loan_unavailable() {
+        let token = Address::from_low_u64_be(1);
+        let providers = vec![
+            provider(FlashLoanSource::AaveV3, 9, 1_000),
+            provider(FlashLoanSource::Balancer, 0, 500),
+        ];
+
+        // Nobody can lend 2_000: report the deepest provider, fee rounded up
+        let choice = select_cheapest_flash_loan(U256::from(2_000u64), token, &providers).unwrap();
+        assert_eq!(choice.provider.source, FlashLoanSource::AaveV3);
+        assert_eq!(choice.fee, U256::from(2)); // 1.8 rounded up
+        assert!(!choice.is_available);
+
+        assert!(select_cheapest_flash_loan(U256::zero(), token, &providers).is_err());
+        assert!(select_cheapest_flash_loan(U256::one(), token, &[]).is_err());
+    }
+}
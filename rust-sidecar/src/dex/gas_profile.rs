@@ -0,0 +1,231 @@
+//! Empirical Gas Profile
+//!
+//! Records gas used by executed operations so sandwich gas estimates come from
+//! real receipts instead of only static constants. Until an operation has been
+//! observed, estimates fall back to static per-protocol defaults.
+//!
+//! ## Operation Keys
+//!
+//! - `"<protocol>_swap"` (e.g. `"uniswap_v3_swap"`): one swap leg, no tick crossings
+//! - `"tick_crossing"`: marginal gas per initialized tick crossed
+
+use crate::core::BasisPoints;
+use crate::dex::protocol::DexProtocol;
+use crate::dex::state_transition::PoolState;
+use crate::dex::uniswap_v3::math::tick_spacing_for_fee_bps;
+use ethers::types::U256;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Operation key for the marginal cost of one tick crossing
+pub const TICK_CROSSING_OPERATION: &str = "tick_crossing";
+
+/// Static gas per tick crossing used before any crossings are recorded
+const DEFAULT_TICK_CROSSING_GAS: u64 = 25_000;
+
+/// Static gas per swap leg used before any swaps are recorded
+fn default_swap_gas(protocol: DexProtocol) -> u64 {
+    match protocol {
+        DexProtocol::UniswapV2 => 100_000,
+        DexProtocol::UniswapV3 => 130_000,
+        DexProtocol::KyberElastic => 140_000,
+        DexProtocol::Balancer => 150_000,
+        DexProtocol::Curve => 180_000,
+    }
+}
+
+/// Operation key for one swap leg on `protocol`
+pub fn swap_operation(protocol: DexProtocol) -> String {
+    format!("{}_swap", protocol.as_str())
+}
+
+/// Gas usage history per operation
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GasProfile {
+    operations: HashMap<String, Vec<u64>>,
+}
+
+impl GasProfile {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the gas used by one execution of an operation
+    pub fn record_operation(&mut self, name: &str, gas_used: u64) {
+        self.operations
+            .entry(name.to_string())
+            .or_default()
+            .push(gas_used);
+    }
+
+    /// Mean gas used by an operation, if it has been recorded
+    pub fn average_gas(&self, name: &str) -> Option<f64> {
+        let samples = self.operations.get(name).filter(|s| !s.is_empty())?;
+        let total: u128 = samples.iter().map(|gas| u128::from(*gas)).sum();
+        Some(total as f64 / samples.len() as f64)
+    }
+
+    /// 95th percentile gas (nearest rank), if the operation has been recorded
+    pub fn p95_gas(&self, name: &str) -> Option<u64> {
+        let samples = self.operations.get(name).filter(|s| !s.is_empty())?;
+        let mut sorted = samples.clone();
+        sorted.sort_unstable();
+
+        // Nearest rank: ceil(0.95 * n), 1-based
+        let rank = (sorted.len() * 95).div_ceil(100);
+        Some(sorted[rank.max(1) - 1])
+    }
+
+    /// Estimate gas for a sandwich's frontrun and backrun legs
+    ///
+    /// `2 * swap_gas + num_tick_crossings * tick_crossing_gas`, using recorded
+    /// averages where available and static defaults otherwise. Tick crossings
+    /// only apply to concentrated-liquidity protocols.
+    ///
+    /// # Arguments
+    /// * `protocol` - Protocol of the sandwiched pool
+    /// * `num_tick_crossings` - Initialized ticks crossed across both legs
+    ///
+    /// # Returns
+    /// * `u64` - Estimated gas units (saturating)
+    pub fn estimate_sandwich_gas(&self, protocol: DexProtocol, num_tick_crossings: usize) -> u64 {
+        let swap_gas = self
+            .average_gas(&swap_operation(protocol))
+            .map(|gas| gas.round() as u64)
+            .unwrap_or_else(|| default_swap_gas(protocol));
+
+        let crossing_gas = if protocol.has_ticks() {
+            let per_crossing = self
+                .average_gas(TICK_CROSSING_OPERATION)
+                .map(|gas| gas.round() as u64)
+                .unwrap_or(DEFAULT_TICK_CROSSING_GAS);
+            per_crossing.saturating_mul(num_tick_crossings as u64)
+        } else {
+            0
+        };
+
+        swap_gas.saturating_mul(2).saturating_add(crossing_gas)
+    }
+
+    /// Estimate the gas an arbitrageur spends restoring a pool's price after a sandwich
+    ///
+    /// The arbitrage is modelled as two swap legs (this pool and the reference
+    /// venue), priced with `estimate_sandwich_gas`. On V3 pools each bps of
+    /// deviation is about one tick, and every spaced tick on the way back is
+    /// assumed initialized, which bounds the crossings from above; fee tiers
+    /// without a standard spacing use 60. Swap fees are paid in pool tokens and
+    /// are not included.
+    ///
+    /// # Arguments
+    /// * `price_deviation_bps` - How far the sandwich left the price from equilibrium
+    /// * `pool_state` - Pool snapshot after the sandwich
+    /// * `eth_gas_price` - Gas price in wei
+    ///
+    /// # Returns
+    /// * `U256` - Rebalancing gas cost in wei (0 when there is no deviation, saturating)
+    pub fn calculate_rebalancing_gas_cost(
+        &self,
+        price_deviation_bps: BasisPoints,
+        pool_state: &PoolState,
+        eth_gas_price: U256,
+    ) -> U256 {
+        if price_deviation_bps.as_u32() == 0 {
+            return U256::zero();
+        }
+
+        let (protocol, tick_crossings) = match pool_state {
+            PoolState::V3(state) => {
+                let tick_spacing = tick_spacing_for_fee_bps(state.fee_bps.as_u32()).unwrap_or(60);
+                (
+                    DexProtocol::UniswapV3,
+                    price_deviation_bps.as_u32() / tick_spacing.unsigned_abs(),
+                )
+            }
+            PoolState::Curve(_) => (DexProtocol::Curve, 0),
+            PoolState::Balancer(_) => (DexProtocol::Balancer, 0),
+        };
+
+        let gas_units = self.estimate_sandwich_gas(protocol, tick_crossings as usize);
+        eth_gas_price.saturating_mul(U256::from(gas_units))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_average_and_p95_gas() {
+        let mut profile = GasProfile::new();
+        assert!(profile.average_gas("uniswap_v3_swap").is_none());
+        assert!(profile.p95_gas("uniswap_v3_swap").is_none());
+
+        for gas in 1..=100u64 {
+            profile.record_operation("uniswap_v3_swap", gas * 1_000);
+        }
+
+        assert_eq!(profile.average_gas("uniswap_v3_swap"), Some(50_500.0));
+        assert_eq!(profile.p95_gas("uniswap_v3_swap"), Some(95_000));
+    }
+
+    #[test]
+    fn test_estimate_sandwich_gas() {
+        let mut profile = GasProfile::new();
+
+        // Static defaults before anything is recorded
+        let v3_gas = profile.estimate_sandwich_gas(DexProtocol::UniswapV3, 2);
+        assert_eq!(v3_gas, 2 * 130_000 + 2 * 25_000);
+        assert_eq!(profile.estimate_sandwich_gas(DexProtocol::Curve, 2), 2 * 180_000);
+
+        // Recorded averages replace the defaults
+        profile.record_operation(&swap_operation(DexProtocol::UniswapV3), 120_000);
+        profile.record_operation(&swap_operation(DexProtocol::UniswapV3), 140_000);
+        profile.record_operation(TICK_CROSSING_OPERATION, 21_000);
+        let v3_gas = profile.estimate_sandwich_gas(DexProtocol::UniswapV3, 3);
+        assert_eq!(v3_gas, 2 * 130_000 + 3 * 21_000);
+    }
+
+    #[test]
+    fn test_rebalancing_gas_cost() {
+        use crate::dex::uniswap_v3::math::V3PoolState;
+
+        let pool = PoolState::V3(V3PoolState {
+            sqrt_price_x96: U256::from(79228162514264337593543950336u128),
+            liquidity: 1_000_000_000_000_000_000_000u128,
+            tick: 0,
+            fee_bps: BasisPoints::new_const(30),
+        });
+        let gas_price = U256::from(20_000_000_000u64);
+        let mut profile = GasProfile::new();
+        let cost = |profile: &GasProfile, deviation_bps: u32| {
+            profile.calculate_rebalancing_gas_cost(
+                BasisPoints::new_const(deviation_bps),
+                &pool,
+                gas_price,
+            )
+        };
+
+        assert_eq!(cost(&profile, 0), U256::zero());
+
+        // 120 bps on a 60-spacing pool: two swaps and two tick crossings
+        assert_eq!(cost(&profile, 120), gas_price * U256::from(2 * 130_000 + 2 * 25_000));
+
+        // Larger deviations cost more to restore
+        assert!(cost(&profile, 600) > cost(&profile, 120));
+
+        // Recorded gas replaces the static defaults
+        profile.record_operation(&swap_operation(DexProtocol::UniswapV3), 100_000);
+        profile.record_operation(TICK_CROSSING_OPERATION, 20_000);
+        assert_eq!(cost(&profile, 120), gas_price * U256::from(2 * 100_000 + 2 * 20_000));
+    }
+
+    #[test]
+    fn test_gas_profile_serde_roundtrip() {
+        let mut profile = GasProfile::new();
+        profile.record_operation("curve_swap", 175_000);
+
+        let json = serde_json::to_string(&profile).unwrap();
+        let restored: GasProfile = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.average_gas("curve_swap"), Some(175_000.0));
+    }
+}
@@ -0,0 +1,344 @@
+//! Sandwich Model Accuracy Bounds
+//!
+//! The fast sandwich models trade exactness for speed. This module bounds
+//! how far their output can be from a full on-chain simulation. Callers can
+//! trust the fast path when the bound is within their profit margin, and
+//! otherwise run the full simulation.
+//!
+//! ## Error Sources
+//!
+//! - **Tick approximation** (V3 / Kyber): sqrt price to tick rounding, at most one
+//!   tick (~1 bps of price)
+//! - **Fee rounding**: per-swap fee rounding, at most 1 bps on realistic amounts
+//! - **Multi-tick crossing** (V3 / Kyber): the models hold liquidity constant, so
+//!   a swap that crosses initialized ticks can be off by up to its price impact
+//! - **Convergence** (Curve / Balancer): Newton iteration settles within 1 wei,
+//!   and the weighted power approximation within 1 bps
+//!
+//! ## Receipt Verification
+//!
+//! After a swap lands, its simulated output is compared with the receipt.
+//! Deviations within the pool fee are noise; a consistent sign across many
+//! receipts becomes a correction factor for future estimates.
+
+use crate::core::BasisPoints;
+use crate::dex::protocol::DexProtocol;
+use crate::dex::state_transition::PoolState;
+use ethers::types::U256;
+
+/// Worst-case error of the fast sandwich model for one victim swap
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModelAccuracyBound {
+    /// Error from rounding the sqrt price to a tick
+    pub tick_approximation_error_bps: u32,
+    /// Error from fee rounding
+    pub fee_rounding_error_bps: u32,
+    /// Error from assuming constant liquidity across ticks
+    pub multi_tick_crossing_error_bps: u32,
+    /// Error from iterative solvers (Curve Newton, Balancer power approximation)
+    pub convergence_error_bps: u32,
+    /// Sum of all sources, capped at 10000
+    pub total_max_error_bps: u32,
+}
+
+impl ModelAccuracyBound {
+    /// Bound used when the pool state does not match the protocol
+    pub const UNBOUNDED: ModelAccuracyBound = ModelAccuracyBound {
+        tick_approximation_error_bps: 10000,
+        fee_rounding_error_bps: 10000,
+        multi_tick_crossing_error_bps: 10000,
+        convergence_error_bps: 10000,
+        total_max_error_bps: 10000,
+    };
+
+    fn new(tick: u32, fee: u32, crossing: u32, convergence: u32) -> Self {
+        Self {
+            tick_approximation_error_bps: tick,
+            fee_rounding_error_bps: fee,
+            multi_tick_crossing_error_bps: crossing,
+            convergence_error_bps: convergence,
+            total_max_error_bps: tick
+                .saturating_add(fee)
+                .saturating_add(crossing)
+                .saturating_add(convergence)
+                .min(10000),
+        }
+    }
+
+    /// Whether the fast model is accurate enough for a given tolerance
+    pub fn is_within(&self, max_error_bps: u32) -> bool {
+        self.total_max_error_bps <= max_error_bps
+    }
+}
+
+/// Bound the fast sandwich model's error for one victim swap
+///
+/// V3 and Kyber pools are evaluated for a token0 victim, as in
+/// `calculate_v3_sandwich_profit`. V2 has no `PoolState` representation, so it
+/// and any protocol/state mismatch get `ModelAccuracyBound::UNBOUNDED`.
+///
+/// # Arguments
+/// * `protocol` - Protocol whose model is used
+/// * `pool_state` - Pool snapshot
+/// * `victim_amount` - Victim swap input amount
+///
+/// # Returns
+/// * `ModelAccuracyBound` - Per-source and total worst-case error in basis points
+pub fn calculate_model_accuracy_bound(
+    protocol: DexProtocol,
+    pool_state: &PoolState,
+    victim_amount: U256,
+) -> ModelAccuracyBound {
+    match (protocol, pool_state) {
+        (DexProtocol::UniswapV3 | DexProtocol::KyberElastic, PoolState::V3(state)) => {
+            if state.sqrt_price_x96.is_zero() || state.liquidity == 0 {
+                return ModelAccuracyBound::UNBOUNDED;
+            }
+
+            // Token0 virtual reserve x = L * Q96 / sqrtP; a small dx moves the
+            // price by about 2 * dx / x
+            let virtual_reserve0 = (U256::from(state.liquidity) << 96) / state.sqrt_price_x96;
+            let impact_bps = if virtual_reserve0.is_zero() {
+                10000
+            } else {
+                victim_amount
+                    .saturating_mul(U256::from(20000))
+                    .checked_div(virtual_reserve0)
+                    .unwrap_or(U256::MAX)
+                    .min(U256::from(10000))
+                    .as_u32()
+            };
+
+            ModelAccuracyBound::new(1, 1, impact_bps, 0)
+        }
+        (DexProtocol::Curve, PoolState::Curve(_)) => {
+            // 1 wei of Newton error, relative to the victim amount (rounded up)
+            let convergence_bps = if victim_amount.is_zero() {
+                0
+            } else {
+                ((U256::from(10000) + victim_amount - 1) / victim_amount)
+                    .min(U256::from(10000))
+                    .as_u32()
+            };
+            ModelAccuracyBound::new(0, 1, 0, convergence_bps)
+        }
+        (DexProtocol::Balancer, PoolState::Balancer(_)) => ModelAccuracyBound::new(0, 1, 0, 1),
+        _ => {
+            tracing::warn!(
+                "No accuracy bound for {} with a mismatched pool state",
+                protocol.as_str()
+            );
+            ModelAccuracyBound::UNBOUNDED
+        }
+    }
+}
+
+/// Direction of a simulation error against the executed swap
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModelBias {
+    /// Simulation promised more output than the swap delivered
+    TooOptimistic,
+    /// Swap delivered more output than simulated
+    TooConservative,
+    /// Simulation matched the receipt exactly
+    Accurate,
+}
+
+/// Comparison of a simulated swap output with the executed receipt
+#[derive(Debug, Clone)]
+pub struct SwapVerification {
+    /// `(actual - simulated) / simulated` in basis points (negative = over-estimated)
+    pub accuracy_bps: i32,
+    /// Whether the deviation is within the pool fee
+    pub is_within_tolerance: bool,
+    /// Direction of the deviation
+    pub model_bias: ModelBias,
+}
+
+/// Compare our simulated swap output against the output in the transaction receipt
+///
+/// Deviations smaller than the pool fee are treated as model noise (rounding,
+/// fee-growth timing); anything larger points to stale state or a model error.
+///
+/// # Arguments
+/// * `simulated_output` - Output predicted before submission
+/// * `actual_output` - Output decoded from the receipt's Swap event
+/// * `fee_bps` - Pool fee, used as the tolerance
+///
+/// # Returns
+/// * `SwapVerification` - Signed deviation (saturating at i32 bounds), tolerance check and bias
+pub fn verify_swap_receipt(
+    simulated_output: U256,
+    actual_output: U256,
+    fee_bps: BasisPoints,
+) -> SwapVerification {
+    let model_bias = match actual_output.cmp(&simulated_output) {
+        std::cmp::Ordering::Less => ModelBias::TooOptimistic,
+        std::cmp::Ordering::Greater => ModelBias::TooConservative,
+        std::cmp::Ordering::Equal => ModelBias::Accurate,
+    };
+
+    let deviation = actual_output.abs_diff(simulated_output);
+    let deviation_bps = if deviation.is_zero() {
+        0
+    } else if simulated_output.is_zero() {
+        i32::MAX
+    } else {
+        let bps = deviation.saturating_mul(U256::from(10000)) / simulated_output;
+        if bps > U256::from(i32::MAX as u32) {
+            i32::MAX
+        } else {
+            bps.as_u32() as i32
+        }
+    };
+    let accuracy_bps = match model_bias {
+        ModelBias::TooOptimistic => -deviation_bps,
+        _ => deviation_bps,
+    };
+
+    SwapVerification {
+        accuracy_bps,
+        is_within_tolerance: deviation_bps.unsigned_abs() <= fee_bps.as_u32(),
+        model_bias,
+    }
+}
+
+/// Multiplicative correction for future output estimates
+#[derive(Debug, Clone)]
+pub struct BiasCorrection {
+    /// Factor to apply to estimates in basis points (10000 = no correction)
+    pub correction_factor_bps: u32,
+    /// Mean `accuracy_bps` over the history
+    pub mean_accuracy_bps: i32,
+    /// Number of verifications the correction is based on
+    pub sample_count: usize,
+}
+
+impl BiasCorrection {
+    /// Scale an estimated output by the correction factor
+    pub fn apply(&self, estimate: U256) -> U256 {
+        estimate.saturating_mul(U256::from(self.correction_factor_bps)) / U256::from(10000)
+    }
+}
+
+/// Derive a correction factor from past receipt verifications
+///
+/// A single verification is noisy, but a consistent sign across many reveals
+/// a systematic model error. The mean signed deviation becomes the factor:
+/// `correction = 10000 + mean(accuracy_bps)`, e.g. outputs that average 20 bps
+/// below simulation give a 9980 bps factor.
+///
+/// # Arguments
+/// * `history` - Past verifications from `verify_swap_receipt`
+///
+/// # Returns
+/// * `BiasCorrection` - Neutral (10000 bps) for an empty history
+pub fn update_model_bias(history: &[SwapVerification]) -> BiasCorrection {
+    if history.is_empty() {
+        return BiasCorrection {
+            correction_factor_bps: 10000,
+            mean_accuracy_bps: 0,
+            sample_count: 0,
+        };
+    }
+
+    let total: i64 = history.iter().map(|v| i64::from(v.accuracy_bps)).sum();
+    let mean = total / history.len() as i64;
+    let mean_accuracy_bps = mean.clamp(i64::from(i32::MIN), i64::from(i32::MAX)) as i32;
+
+    BiasCorrection {
+        correction_factor_bps: (10000 + mean).clamp(0, i64::from(u32::MAX)) as u32,
+        mean_accuracy_bps,
+        sample_count: history.len(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dex::curve::math::CurvePoolState;
+    use crate::dex::uniswap_v3::math::V3PoolState;
+
+    fn v3_pool() -> PoolState {
+        PoolState::V3(V3PoolState {
+            sqrt_price_x96: U256::from(79228162514264337593543950336u128),
+            liquidity: 1_000_000_000_000_000_000_000u128,
+            tick: 0,
+            fee_bps: BasisPoints::new_const(5),
+        })
+    }
+
+    #[test]
+    fn test_v3_accuracy_bound() {
+        // 0.01 token against 1000 tokens of depth stays within a tick
+        let small = U256::from(10_000_000_000_000_000u128);
+        let bound = calculate_model_accuracy_bound(DexProtocol::UniswapV3, &v3_pool(), small);
+        assert_eq!(bound, ModelAccuracyBound::new(1, 1, 0, 0));
+        assert!(bound.is_within(2));
+
+        // 10 tokens move the price ~2%, so crossings may matter
+        let large = U256::from(10_000_000_000_000_000_000u128);
+        let bound = calculate_model_accuracy_bound(DexProtocol::UniswapV3, &v3_pool(), large);
+        assert_eq!(bound.multi_tick_crossing_error_bps, 200);
+        assert!(!bound.is_within(10));
+    }
+
+    #[test]
+    fn test_curve_and_mismatched_accuracy_bound() {
+        let curve = PoolState::Curve(CurvePoolState {
+            balances: vec![U256::from(1_000_000u64); 2],
+            a: U256::from(100u64),
+            fee_bps: 4,
+        });
+        let victim = U256::from(1_000_000_000_000_000_000u128);
+        let bound = calculate_model_accuracy_bound(DexProtocol::Curve, &curve, victim);
+        assert_eq!(bound.convergence_error_bps, 1);
+        assert_eq!(bound.total_max_error_bps, 2);
+
+        assert_eq!(
+            calculate_model_accuracy_bound(DexProtocol::UniswapV2, &curve, victim),
+            ModelAccuracyBound::UNBOUNDED
+        );
+    }
+
+    #[test]
+    fn test_verify_swap_receipt() {
+        let fee_bps = BasisPoints::new_const(30);
+        let simulated = U256::from(1_000_000u64);
+
+        // 0.2% short of the simulation: optimistic but within the 30 bps fee
+        let short = verify_swap_receipt(simulated, U256::from(998_000u64), fee_bps);
+        assert_eq!(short.accuracy_bps, -20);
+        assert!(short.is_within_tolerance);
+        assert_eq!(short.model_bias, ModelBias::TooOptimistic);
+
+        // 1% more than simulated: conservative and outside tolerance
+        let over = verify_swap_receipt(simulated, U256::from(1_010_000u64), fee_bps);
+        assert_eq!(over.accuracy_bps, 100);
+        assert!(!over.is_within_tolerance);
+        assert_eq!(over.model_bias, ModelBias::TooConservative);
+
+        let exact = verify_swap_receipt(simulated, simulated, fee_bps);
+        assert_eq!(exact.accuracy_bps, 0);
+        assert_eq!(exact.model_bias, ModelBias::Accurate);
+    }
+
+    #[test]
+    fn test_update_model_bias() {
+        let fee_bps = BasisPoints::new_const(30);
+        let simulated = U256::from(1_000_000u64);
+        let history: Vec<SwapVerification> = [998_000u64, 997_000, 999_000]
+            .iter()
+            .map(|actual| verify_swap_receipt(simulated, U256::from(*actual), fee_bps))
+            .collect();
+
+        // Outputs average 20 bps below simulation
+        let correction = update_model_bias(&history);
+        assert_eq!(correction.mean_accuracy_bps, -20);
+        assert_eq!(correction.correction_factor_bps, 9980);
+        assert_eq!(correction.sample_count, 3);
+        assert_eq!(correction.apply(simulated), U256::from(998_000u64));
+
+        assert_eq!(update_model_bias(&[]).correction_factor_bps, 10000);
+    }
+}
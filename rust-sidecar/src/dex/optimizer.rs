@@ -0,0 +1,164 @@
+//! Trade Size Optimization
+//!
+//! Sandwich, arbitrage and batch sizing all search for the input amount that
+//! maximizes a profit function over `[0, upper]`. The profit curves are
+//! unimodal but piecewise flat (integer rounding, and profit floored at zero
+//! once a trade turns unprofitable), so a bracketing golden section search is
+//! used rather than an interpolating method.
+//!
+//! The objective returns `Result`: a failed simulation aborts the search
+//! instead of being scored as zero profit, which would silently steer it.
+
+use ethers::types::U256;
+
+/// Default golden section iterations; shrinks the bracket by 0.618^40 ≈ 4e-9
+pub const DEFAULT_ITERATIONS: usize = 40;
+
+/// (φ - 1) scaled by 1000
+const GOLDEN_RATIO_INV: u64 = 618;
+
+/// Scale of `GOLDEN_RATIO_INV`
+const GOLDEN_RATIO_SCALE: u64 = 1000;
+
+/// Golden section step for a bracket of width `width`, without overflowing U256
+fn golden_step(width: U256) -> U256 {
+    let scale = U256::from(GOLDEN_RATIO_SCALE);
+    let ratio = U256::from(GOLDEN_RATIO_INV);
+    width / scale * ratio + width % scale * ratio / scale
+}
+
+/// Maximize a unimodal objective over `[lower, upper]` with golden section search
+///
+/// Each iteration keeps the surviving interior probe, so the objective is
+/// evaluated once per iteration. The search stops after `iterations` steps or
+/// when the bracket can no longer shrink.
+///
+/// # Arguments
+/// * `lower` - Lower bound of the search interval
+/// * `upper` - Upper bound of the search interval
+/// * `iterations` - Maximum number of bracket reductions
+/// * `objective` - Profit at a given input amount
+///
+/// # Returns
+/// * `Ok((U256, U256))` - Best input amount found and its objective value
+/// * `Err(E)` - The first error returned by `objective`
+pub fn golden_section_maximize<E, F>(
+    lower: U256,
+    upper: U256,
+    iterations: usize,
+    mut objective: F,
+) -> Result<(U256, U256), E>
+where
+    F: FnMut(U256) -> Result<U256, E>,
+{
+    if upper <= lower {
+        let value = objective(lower)?;
+        return Ok((lower, value));
+    }
+
+    let mut a = lower;
+    let mut b = upper;
+    let step = golden_step(b - a);
+    let mut c = b - step;
+    let mut d = a + step;
+    let mut fc = objective(c)?;
+    let mut fd = if d == c { fc } else { objective(d)? };
+
+    for _ in 0..iterations {
+        if fc >= fd {
+            b = d;
+            d = c;
+            fd = fc;
+            let step = golden_step(b - a);
+            if step.is_zero() {
+                break;
+            }
+            c = b - step;
+            fc = if c == d { fd } else { objective(c)? };
+        } else {
+            a = c;
+            c = d;
+            fc = fd;
+            let step = golden_step(b - a);
+            if step.is_zero() {
+                break;
+            }
+            d = a + step;
+            fd = if d == c { fc } else { objective(d)? };
+        }
+
+        // Integer rounding can cross the probes on tiny brackets
+        if c > d {
+            std::mem::swap(&mut c, &mut d);
+            std::mem::swap(&mut fc, &mut fd);
+        }
+    }
+
+    let midpoint = a + (b - a) / U256::from(2);
+    let f_mid = objective(midpoint)?;
+
+    let mut best = (midpoint, f_mid);
+    for (x, fx) in [(c, fc), (d, fd)] {
+        if fx > best.1 {
+            best = (x, fx);
+        }
+    }
+    Ok(best)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Concave profit peaking at `peak`: peak^2 - (x - peak)^2, floored at zero
+    fn concave(peak: u64) -> impl FnMut(U256) -> Result<U256, ()> {
+        move |x: U256| {
+            let x = x.as_u64() as i128;
+            let peak = peak as i128;
+            let value = peak * peak - (x - peak) * (x - peak);
+            Ok(U256::from(value.max(0) as u128))
+        }
+    }
+
+    #[test]
+    fn test_golden_section_finds_interior_peak() {
+        let (best, value) =
+            golden_section_maximize(U256::zero(), U256::from(1_000_000u64), 60, concave(314_159))
+                .unwrap();
+        let distance = best.as_u64().abs_diff(314_159);
+        assert!(distance <= 2, "best={} too far from peak", best);
+        assert!(value > U256::zero());
+    }
+
+    #[test]
+    fn test_golden_section_evaluates_once_per_iteration() {
+        let mut calls = 0usize;
+        let _ = golden_section_maximize(U256::zero(), U256::from(1u64 << 40), 20, |x| {
+            calls += 1;
+            Ok::<_, ()>(x)
+        });
+        // Two initial probes, one per iteration, one midpoint
+        assert!(calls <= 23, "calls={}", calls);
+    }
+
+    #[test]
+    fn test_golden_section_propagates_errors() {
+        let result = golden_section_maximize(U256::zero(), U256::from(1000u64), 40, |x| {
+            if x > U256::from(500u64) {
+                Err("simulation failed")
+            } else {
+                Ok(x)
+            }
+        });
+        assert_eq!(result, Err("simulation failed"));
+    }
+
+    #[test]
+    fn test_golden_section_empty_interval() {
+        let (best, value) =
+            golden_section_maximize(U256::from(7u64), U256::from(7u64), 40, |x| Ok::<_, ()>(x))
+                .unwrap();
+        assert_eq!(best, U256::from(7u64));
+        assert_eq!(value, U256::from(7u64));
+    }
+}
@@ -0,0 +1,148 @@
+//! ETH Denomination for Cross-Pool Comparison
+//!
+//! Sandwich profits are computed in whichever token the frontrun borrows. To
+//! rank opportunities across pools they are converted to ETH using externally
+//! supplied Chainlink `TOKEN / ETH` prices (18 decimals per whole token), which
+//! keeps this module free of any dependency on our own pool pricing.
+//!
+//! For stablecoins the `TOKEN / ETH` price is `1 / ETH_USD`, so normalizing a
+//! stablecoin amount divides it by the current ETH price.
+//!
+//! Token decimals are supplied by the caller (read from each token's
+//! `decimals()` once and cached); a token without known decimals is an error
+//! rather than being assumed to have 18.
+
+use crate::core::MathError;
+use ethers::types::{Address, H160, U256};
+use std::collections::HashMap;
+
+/// Wrapped Ether (mainnet)
+pub const WETH: Address = H160([
+    0xc0, 0x2a, 0xaa, 0x39, 0xb2, 0x23, 0xfe, 0x8d, 0x0a, 0x0e, 0x5c, 0x4f, 0x27, 0xea, 0xd9,
+    0x08, 0x3c, 0x75, 0x6c, 0xc2,
+]);
+
+/// USD Coin (mainnet, 6 decimals)
+pub const USDC: Address = H160([
+    0xa0, 0xb8, 0x69, 0x91, 0xc6, 0x21, 0x8b, 0x36, 0xc1, 0xd1, 0x9d, 0x4a, 0x2e, 0x9e, 0xb0,
+    0xce, 0x36, 0x06, 0xeb, 0x48,
+]);
+
+/// Tether USD (mainnet, 6 decimals)
+pub const USDT: Address = H160([
+    0xda, 0xc1, 0x7f, 0x95, 0x8d, 0x2e, 0xe5, 0x23, 0xa2, 0x20, 0x62, 0x06, 0x99, 0x45, 0x97,
+    0xc1, 0x3d, 0x83, 0x1e, 0xc7,
+]);
+
+/// Wrapped Bitcoin (mainnet, 8 decimals)
+pub const WBTC: Address = H160([
+    0x22, 0x60, 0xfa, 0xc5, 0xe5, 0x54, 0x2a, 0x77, 0x3a, 0xa4, 0x4f, 0xbc, 0xfe, 0xdf, 0x7c,
+    0x19, 0x3b, 0xc2, 0xc5, 0x99,
+]);
+
+/// Convert a token amount to its value in wei
+///
+/// `amount_eth = amount * price / 10^decimals`, where `price` is the token's
+/// Chainlink `TOKEN / ETH` answer (wei per whole token). WETH is returned as is.
+///
+/// # Arguments
+/// * `amount` - Raw token amount
+/// * `token` - Token address
+/// * `eth_prices` - `TOKEN / ETH` prices in wei per whole token
+/// * `token_decimals` - Decimals of each token
+///
+/// # Returns
+/// * `Ok(U256)` - Value in wei (rounded down)
+/// * `Err(MathError)` - If the token has no price or decimals, or the conversion overflows
+pub fn normalize_to_eth(
+    amount: U256,
+    token: Address,
+    eth_prices: &HashMap<Address, U256>,
+    token_decimals: &HashMap<Address, u8>,
+) -> Result<U256, MathError> {
+    if token == WETH {
+        return Ok(amount);
+    }
+
+    let price = eth_prices
+        .get(&token)
+        .ok_or_else(|| MathError::InvalidInput {
+            operation: "normalize_to_eth".to_string(),
+            reason: "No ETH price for token".to_string(),
+            context: format!("token={:?}, amount={}", token, amount),
+        })?;
+
+    let decimals = token_decimals
+        .get(&token)
+        .ok_or_else(|| MathError::InvalidInput {
+            operation: "normalize_to_eth".to_string(),
+            reason: "Unknown decimals for token".to_string(),
+            context: format!("token={:?}, amount={}", token, amount),
+        })?;
+
+    let unit = U256::exp10(*decimals as usize);
+    amount
+        .checked_mul(*price)
+        .map(|value| value / unit)
+        .ok_or_else(|| MathError::Overflow {
+            operation: "normalize_to_eth".to_string(),
+            inputs: vec![amount, *price],
+            context: format!("token={:?}", token),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_to_eth() {
+        let one_eth = U256::exp10(18);
+        let mut eth_prices = HashMap::new();
+        // ETH at $2000: 1 USDC = 0.0005 ETH
+        eth_prices.insert(USDC, one_eth / U256::from(2000));
+        // 1 WBTC = 20 ETH
+        eth_prices.insert(WBTC, one_eth * U256::from(20));
+        let decimals: HashMap<Address, u8> = [(USDC, 6), (USDT, 6), (WBTC, 8)].into();
+
+        // WETH passes through without a price
+        assert_eq!(
+            normalize_to_eth(one_eth, WETH, &eth_prices, &decimals).unwrap(),
+            one_eth
+        );
+
+        // 4000 USDC (6 decimals) = 2 ETH
+        let usdc = U256::from(4_000u64) * U256::exp10(6);
+        assert_eq!(
+            normalize_to_eth(usdc, USDC, &eth_prices, &decimals).unwrap(),
+            one_eth * U256::from(2)
+        );
+
+        // 0.5 WBTC (8 decimals) = 10 ETH
+        let wbtc = U256::from(50_000_000u64);
+        assert_eq!(
+            normalize_to_eth(wbtc, WBTC, &eth_prices, &decimals).unwrap(),
+            one_eth * U256::from(10)
+        );
+
+        // No price
+        assert!(normalize_to_eth(usdc, USDT, &eth_prices, &decimals).is_err());
+    }
+
+    #[test]
+    fn test_normalize_to_eth_unknown_decimals() {
+        let token = Address::from_low_u64_be(0x1234);
+        let mut eth_prices = HashMap::new();
+        eth_prices.insert(token, U256::exp10(15));
+
+        // Priced, but decimals unknown: an error instead of assuming 18
+        let amount = U256::exp10(18);
+        assert!(normalize_to_eth(amount, token, &eth_prices, &HashMap::new()).is_err());
+
+        let decimals: HashMap<Address, u8> = [(token, 9)].into();
+        assert_eq!(
+            normalize_to_eth(amount, token, &eth_prices, &decimals).unwrap(),
+            U256::exp10(24)
+        );
+    }
+}
@@ -0,0 +1,184 @@
+//! DEX Protocol Identifiers
+//!
+//! Identifies which protocol's math a pool, operation or error belongs to, and
+//! defines the protocol-agnostic pool interfaces implemented by each DEX's
+//! pool state.
+//!
+//! Quotes and simulations through the protocol-agnostic interfaces fail with a
+//! `ProtocolMathError` tagged with the protocol whose math failed. Direct calls
+//! into a DEX's math module return an untagged `MathError`.
+
+use crate::core::{MathError, ProtocolMathError};
+use ethers::types::U256;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Supported DEX protocols
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum DexProtocol {
+    UniswapV3,
+    Curve,
+    Balancer,
+    KyberElastic,
+    UniswapV2,
+}
+
+impl DexProtocol {
+    /// Short snake_case name, used in operation keys and logs
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DexProtocol::UniswapV3 => "uniswap_v3",
+            DexProtocol::Curve => "curve",
+            DexProtocol::Balancer => "balancer",
+            DexProtocol::KyberElastic => "kyber_elastic",
+            DexProtocol::UniswapV2 => "uniswap_v2",
+        }
+    }
+
+    /// Whether swaps cross discrete ticks (concentrated liquidity)
+    pub fn has_ticks(&self) -> bool {
+        matches!(self, DexProtocol::UniswapV3 | DexProtocol::KyberElastic)
+    }
+}
+
+impl fmt::Display for DexProtocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            DexProtocol::UniswapV3 => "UniswapV3",
+            DexProtocol::Curve => "Curve",
+            DexProtocol::Balancer => "Balancer",
+            DexProtocol::KyberElastic => "KyberElastic",
+            DexProtocol::UniswapV2 => "UniswapV2",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Protocol-agnostic spot price source
+///
+/// Lets the arbitrage router compare prices across V3, Curve and Balancer pools
+/// without knowing which protocol is underneath. All implementations return the
+/// price of `token_in` denominated in `token_out`, scaled by 10^18.
+pub trait PoolPricer {
+    /// Spot price of `token_in` in units of `token_out` (18-decimal fixed-point)
+    fn spot_price(&self, token_in: usize, token_out: usize) -> Result<U256, MathError>;
+
+    /// Current sqrt price in Q64.96 format (only `Some` for V3/Kyber pools)
+    fn sqrt_price_x96(&self) -> Option<U256>;
+}
+
+/// Protocol-agnostic swap quote source
+///
+/// Extends `PoolPricer` with exact-input quotes so multi-pool routing can
+/// simulate paths across V3, Curve and Balancer pools. Errors are tagged with
+/// the pool's protocol.
+pub trait PoolQuoter: PoolPricer {
+    /// Output of swapping `amount_in` of `token_in` for `token_out`, after fees
+    fn quote_amount_out(
+        &self,
+        amount_in: U256,
+        token_in: usize,
+        token_out: usize,
+    ) -> Result<U256, ProtocolMathError>;
+}
+
+/// Protocol-agnostic swap simulation
+///
+/// Extends `PoolQuoter` with the post-swap pool state so multi-transaction
+/// sequences (frontrun, victim, backrun) can be replayed on any protocol.
+pub trait PoolSimulator: PoolQuoter {
+    /// Pool state after swapping `amount_in` of `token_in` for `token_out`, and the output
+    fn simulate_swap(
+        &self,
+        amount_in: U256,
+        token_in: usize,
+        token_out: usize,
+    ) -> Result<(Box<dyn PoolSimulator + Send + Sync>, U256), ProtocolMathError>;
+}
+
+/// Move a swap's amounts through a multi-token pool's balances
+///
+/// Shared by the balance-based pools (Curve, Balancer): the input balance
+/// grows by `amount_in` and the output balance shrinks by `amount_out`.
+///
+/// # Arguments
+/// * `balances` - Pool balances, updated in place
+/// * `token_in` - Index of the input token
+/// * `amount_in` - Amount paid into the pool
+/// * `token_out` - Index of the output token
+/// * `amount_out` - Amount paid out of the pool
+/// * `operation` - Caller name for error context
+///
+/// # Returns
+/// * `Ok(())` - Balances updated
+/// * `Err(MathError)` - If an index is out of range, the input balance overflows or the
+///   output balance is smaller than `amount_out`
+pub fn apply_balance_change(
+    balances: &mut [U256],
+    token_in: usize,
+    amount_in: U256,
+    token_out: usize,
+    amount_out: U256,
+    operation: &str,
+) -> Result<(), MathError> {
+    if token_in >= balances.len() || token_out >= balances.len() {
+        return Err(MathError::InvalidInput {
+            operation: operation.to_string(),
+            reason: "Token index out of range".to_string(),
+            context: format!(
+                "token_in={}, token_out={}, n_tokens={}",
+                token_in,
+                token_out,
+                balances.len()
+            ),
+        });
+    }
+
+    let balance_in = balances[token_in]
+        .checked_add(amount_in)
+        .ok_or_else(|| MathError::Overflow {
+            operation: operation.to_string(),
+            inputs: vec![balances[token_in], amount_in],
+            context: format!("token_in={}", token_in),
+        })?;
+    let balance_out = balances[token_out]
+        .checked_sub(amount_out)
+        .ok_or_else(|| MathError::Underflow {
+            operation: operation.to_string(),
+            inputs: vec![balances[token_out], amount_out],
+            context: format!("token_out={}", token_out),
+        })?;
+
+    balances[token_in] = balance_in;
+    balances[token_out] = balance_out;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_balance_change() {
+        let mut balances = vec![U256::from(1000u64), U256::from(2000u64), U256::from(3000u64)];
+        apply_balance_change(&mut balances, 0, U256::from(100u64), 2, U256::from(250u64), "test")
+            .unwrap();
+        assert_eq!(
+            balances,
+            vec![U256::from(1100u64), U256::from(2000u64), U256::from(2750u64)]
+        );
+
+        // Output larger than the balance: error, balances untouched
+        let before = balances.clone();
+        assert!(matches!(
+            apply_balance_change(&mut balances, 0, U256::one(), 1, U256::from(5000u64), "test"),
+            Err(MathError::Underflow { .. })
+        ));
+        assert_eq!(balances, before);
+
+        assert!(matches!(
+            apply_balance_change(&mut balances, 0, U256::one(), 3, U256::one(), "test"),
+            Err(MathError::InvalidInput { .. })
+        ));
+    }
+}
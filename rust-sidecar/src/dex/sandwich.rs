@@ -0,0 +1,432 @@
+//! Sandwich Opportunities and Capital Allocation
+//!
+//! Protocol-agnostic sandwich candidate and opportunity types, shared by the
+//! batch evaluator, plus allocation of limited capital across opportunities
+//! that appear in the same block.
+//!
+//! ## Allocation
+//!
+//! Allocation is a greedy fractional-knapsack approximation: opportunities are
+//! funded in order of `expected_profit / frontrun_amount`, each up to its
+//! optimal frontrun size, until capital runs out. With flash loans only the
+//! premium is paid from own capital, so the capital stretches by
+//! `1 / flash_loan_fee_rate`.
+//!
+//! ## Gas Price Go/No-Go
+//!
+//! Gas is paid in ETH, so a gas spike can wipe out a sandwich found a few
+//! blocks earlier. The break-even gas price is `profit / gas_units`. Profits are
+//! quoted in the victim's input token, so they are converted to wei with
+//! `pricing::normalize_to_eth` first; gas units come from the `GasProfile`.
+//!
+//! ## Deadline Urgency
+//!
+//! Opportunities are ranked by profit scaled by how soon the victim's swap
+//! deadline expires, so one that must land in the next block or two is taken
+//! ahead of an equally profitable one that can wait.
+
+use crate::core::BasisPoints;
+use crate::dex::gas_profile::GasProfile;
+use crate::dex::protocol::DexProtocol;
+use ethers::types::{Address, U256};
+use std::cmp::Ordering;
+
+/// A pending victim swap that may be sandwiched
+#[derive(Debug, Clone)]
+pub struct SandwichCandidate {
+    /// Pool the victim swaps on
+    pub pool: Address,
+    /// Token the victim sells
+    pub token_in: Address,
+    /// Token the victim buys
+    pub token_out: Address,
+    /// Victim's exact input amount
+    pub victim_amount_in: U256,
+    /// Victim's minimum output (slippage limit)
+    pub victim_min_amount_out: U256,
+}
+
+/// Best sandwich found for a candidate
+#[derive(Debug, Clone)]
+pub struct SandwichOpportunity {
+    /// Pool being sandwiched
+    pub pool: Address,
+    /// Optimal frontrun input (in the victim's input token)
+    pub frontrun_amount: U256,
+    /// Profit in the victim's input token (before gas and flash loan fees)
+    pub expected_profit: U256,
+    /// Victim output after the frontrun
+    pub victim_amount_out: U256,
+}
+
+/// Wei per gwei
+const WEI_PER_GWEI: u64 = 1_000_000_000;
+
+/// Gas price (wei per gas) above which a sandwich loses money
+///
+/// # Arguments
+/// * `expected_profit_eth` - Profit before gas, in wei
+/// * `gas_units` - Gas used by the searcher's transactions
+///
+/// # Returns
+/// * `U256` - Break-even gas price in wei (`U256::MAX` if `gas_units` is zero)
+pub fn calculate_break_even_eth_gas_price(expected_profit_eth: U256, gas_units: u64) -> U256 {
+    if gas_units == 0 {
+        return U256::MAX;
+    }
+    expected_profit_eth / U256::from(gas_units)
+}
+
+/// Whether a sandwich still clears its gas cost at `gas_price_gwei`
+///
+/// `SandwichOpportunity::expected_profit` is in the victim's input token;
+/// convert it with `pricing::normalize_to_eth` before calling.
+///
+/// # Arguments
+/// * `profit_wei` - Profit before gas, in wei
+/// * `gas_profile` - Observed gas usage
+/// * `protocol` - Protocol of the sandwiched pool
+/// * `num_tick_crossings` - Initialized ticks crossed across both legs
+/// * `gas_price_gwei` - Gas price in gwei
+///
+/// # Returns
+/// * `bool` - Whether the profit exceeds the estimated gas cost
+pub fn sandwich_remains_profitable_at_gas_price(
+    profit_wei: U256,
+    gas_profile: &GasProfile,
+    protocol: DexProtocol,
+    num_tick_crossings: usize,
+    gas_price_gwei: u64,
+) -> bool {
+    let gas_units = gas_profile.estimate_sandwich_gas(protocol, num_tick_crossings);
+    let gas_cost = U256::from(gas_price_gwei)
+        .saturating_mul(U256::from(WEI_PER_GWEI))
+        .saturating_mul(U256::from(gas_units));
+    gas_cost < profit_wei
+}
+
+/// Longest backrun delay tabulated by `backrun_profit_sensitivity`
+pub const MAX_BACKRUN_DELAY_BLOCKS: u32 = 5;
+
+/// Expected backrun profit if the backrun lands `0..=MAX_BACKRUN_DELAY_BLOCKS` blocks late
+///
+/// Each block of delay lets the price drift against us by roughly one block of
+/// volatility: `nominal * (1 - delay * volatility_bps / 10000)`, floored at zero.
+/// Comparing rows shows what faster inclusion is worth against its bid.
+///
+/// # Arguments
+/// * `price_per_block_vol_bps` - Price volatility per block in basis points
+/// * `backrun_profit_nominal` - Backrun profit if it lands right after the victim
+///
+/// # Returns
+/// * `Vec<(u32, U256)>` - `(blocks_delay, expected_backrun_profit)` for each delay
+pub fn backrun_profit_sensitivity(
+    price_per_block_vol_bps: u32,
+    backrun_profit_nominal: U256,
+) -> Vec<(u32, U256)> {
+    (0..=MAX_BACKRUN_DELAY_BLOCKS)
+        .map(|delay| {
+            let drift_bps = delay.saturating_mul(price_per_block_vol_bps);
+            let retained_bps = 10000u32.saturating_sub(drift_bps);
+            let expected = backrun_profit_nominal.full_mul(U256::from(retained_bps))
+                / U256::from(10000);
+            // expected <= nominal, so it fits in U256
+            (delay, expected.try_into().unwrap_or(backrun_profit_nominal))
+        })
+        .collect()
+}
+
+/// Order two opportunities by profit per unit of capital, best first
+fn compare_profit_ratio(a: &SandwichOpportunity, b: &SandwichOpportunity) -> Ordering {
+    // a.profit / a.capital vs b.profit / b.capital, cross-multiplied in 512 bits
+    let a_ratio = a.expected_profit.full_mul(b.frontrun_amount);
+    let b_ratio = b.expected_profit.full_mul(a.frontrun_amount);
+    b_ratio.cmp(&a_ratio)
+}
+
+/// Greedily fund opportunities from `capacity` notional capital
+fn allocate_greedy(
+    opportunities: &[SandwichOpportunity],
+    capacity: U256,
+    min_allocation_bps: BasisPoints,
+) -> Vec<(usize, U256)> {
+    let mut order: Vec<usize> = (0..opportunities.len())
+        .filter(|&i| {
+            !opportunities[i].frontrun_amount.is_zero()
+                && !opportunities[i].expected_profit.is_zero()
+        })
+        .collect();
+    order.sort_by(|&a, &b| compare_profit_ratio(&opportunities[a], &opportunities[b]));
+
+    let mut remaining = capacity;
+    let mut allocations = Vec::new();
+    for index in order {
+        if remaining.is_zero() {
+            break;
+        }
+
+        let required = opportunities[index].frontrun_amount;
+        let amount = required.min(remaining);
+
+        // Skip partial fills too small to be worth a bundle
+        let min_amount =
+            required.saturating_mul(U256::from(min_allocation_bps.as_u32())) / U256::from(10000);
+        if amount < min_amount {
+            tracing::debug!(
+                "Skipping opportunity {}: {} available, {} minimum",
+                index,
+                amount,
+                min_amount
+            );
+            continue;
+        }
+
+        allocations.push((index, amount));
+        remaining -= amount;
+    }
+
+    allocations
+}
+
+/// Allocate own capital across concurrent sandwich opportunities
+///
+/// # Arguments
+/// * `opportunities` - Candidate sandwiches (frontrun size = capital required)
+/// * `total_capital` - Capital available, in the frontrun token
+/// * `min_allocation_bps` - Smallest partial fill accepted, as a share of an
+///   opportunity's optimal frontrun
+///
+/// # Returns
+/// * `Vec<(usize, U256)>` - `(opportunity_index, allocated_amount)`, best ratio first
+pub fn allocate_capital(
+    opportunities: &[SandwichOpportunity],
+    total_capital: U256,
+    min_allocation_bps: BasisPoints,
+) -> Vec<(usize, U256)> {
+    allocate_greedy(opportunities, total_capital, min_allocation_bps)
+}
+
+/// Allocate capital when frontruns are funded by flash loans
+///
+/// Own capital only pays the premium, so it supports `total_capital / fee_rate`
+/// of borrowed frontrun volume. A zero fee (e.g. Balancer) funds every
+/// opportunity in full.
+///
+/// # Arguments
+/// * `opportunities` - Candidate sandwiches (frontrun size = amount borrowed)
+/// * `total_capital` - Own capital available for premiums, in the frontrun token
+/// * `min_allocation_bps` - Smallest partial fill accepted, as a share of an
+///   opportunity's optimal frontrun
+/// * `flash_loan_fee_bps` - Flash loan premium in basis points
+///
+/// # Returns
+/// * `Vec<(usize, U256)>` - `(opportunity_index, borrowed_amount)`, best ratio first
+pub fn allocate_capital_with_flash_loans(
+    opportunities: &[SandwichOpportunity],
+    total_capital: U256,
+    min_allocation_bps: BasisPoints,
+    flash_loan_fee_bps: BasisPoints,
+) -> Vec<(usize, U256)> {
+    let capacity = match flash_loan_fee_bps.as_u32() {
+        0 => U256::MAX,
+        fee_bps => total_capital.saturating_mul(U256::from(10000)) / U256::from(fee_bps),
+    };
+    allocate_greedy(opportunities, capacity, min_allocation_bps)
+}
+
+/// Urgency of a sandwich opportunity based on the victim transaction's deadline
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum UrgencyLevel {
+    /// Deadline already passed - victim will revert
+    Expired,
+    /// Deadline far out (> 20 blocks) - can wait for a better window
+    Low,
+    /// 6-20 blocks remaining
+    Medium,
+    /// 3-5 blocks remaining
+    High,
+    /// 1-2 blocks remaining - must be included now
+    Critical,
+}
+
+/// Classify a victim transaction's urgency from the blocks left before its deadline
+///
+/// # Arguments
+/// * `deadline_blocks_remaining` - Blocks until the victim's swap deadline
+///
+/// # Returns
+/// * `UrgencyLevel` - Expired at 0, Critical at 1-2, High at 3-5, Medium at 6-20, Low beyond
+pub fn calculate_deadline_urgency(deadline_blocks_remaining: u32) -> UrgencyLevel {
+    match deadline_blocks_remaining {
+        0 => UrgencyLevel::Expired,
+        1..=2 => UrgencyLevel::Critical,
+        3..=5 => UrgencyLevel::High,
+        6..=20 => UrgencyLevel::Medium,
+        _ => UrgencyLevel::Low,
+    }
+}
+
+/// Adjust an opportunity's profit into a ranking score based on urgency
+///
+/// Low-urgency opportunities are discounted (they can be taken in a later block),
+/// critical ones are boosted so they rank ahead of equally profitable ones.
+/// Expired opportunities score zero.
+///
+/// # Arguments
+/// * `profit` - Expected profit of the opportunity
+/// * `urgency` - Urgency from `calculate_deadline_urgency`
+///
+/// # Returns
+/// * `U256` - Urgency-adjusted profit score (saturates instead of overflowing)
+pub fn adjust_profit_for_urgency(profit: U256, urgency: UrgencyLevel) -> U256 {
+    // Multipliers in basis points (10000 = 1.0x)
+    let multiplier_bps: u32 = match urgency {
+        UrgencyLevel::Expired => return U256::zero(),
+        UrgencyLevel::Low => 8000,
+        UrgencyLevel::Medium => 9000,
+        UrgencyLevel::High => 10000,
+        UrgencyLevel::Critical => 12000,
+    };
+
+    // Multiply in 512 bits so large profits keep their ordering, then saturate
+    let adjusted = profit.full_mul(U256::from(multiplier_bps)) / U256::from(10000);
+    adjusted.try_into().unwrap_or(U256::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn opportunity(frontrun_amount: u64, expected_profit: u64) -> SandwichOpportunity {
+        SandwichOpportunity {
+            pool: Address::zero(),
+            frontrun_amount: U256::from(frontrun_amount),
+            expected_profit: U256::from(expected_profit),
+            victim_amount_out: U256::zero(),
+        }
+    }
+
+    #[test]
+    fn test_allocate_capital() {
+        let opportunities = vec![
+            opportunity(500_000, 5_000),   // 1%
+            opportunity(400_000, 12_000),  // 3%
+            opportunity(300_000, 6_000),   // 2%
+            opportunity(100_000, 0),       // unprofitable
+        ];
+        let none = BasisPoints::new_const(0);
+
+        // Best ratios first; the last funded opportunity is filled partially
+        let allocations = allocate_capital(&opportunities, U256::from(1_000_000u64), none);
+        assert_eq!(
+            allocations,
+            vec![
+                (1, U256::from(400_000u64)),
+                (2, U256::from(300_000u64)),
+                (0, U256::from(300_000u64)),
+            ]
+        );
+
+        // A 70% minimum drops the 60% partial fill of opportunity 0
+        let strict = BasisPoints::new_const(7000);
+        let allocations = allocate_capital(&opportunities, U256::from(1_000_000u64), strict);
+        assert_eq!(allocations.len(), 2);
+    }
+
+    #[test]
+    fn test_allocate_capital_with_flash_loans() {
+        let opportunities = vec![opportunity(500_000, 5_000), opportunity(400_000, 12_000)];
+        let none = BasisPoints::new_const(0);
+
+        // 9 bps premium: 450 of own capital supports 500_000 of borrowing
+        let allocations = allocate_capital_with_flash_loans(
+            &opportunities,
+            U256::from(450u64),
+            none,
+            BasisPoints::new_const(9),
+        );
+        assert_eq!(
+            allocations,
+            vec![(1, U256::from(400_000u64)), (0, U256::from(100_000u64))]
+        );
+
+        // Free flash loans fund everything
+        let allocations =
+            allocate_capital_with_flash_loans(&opportunities, U256::zero(), none, none);
+        assert_eq!(allocations.len(), 2);
+    }
+
+    #[test]
+    fn test_break_even_gas_price() {
+        // 0.003 ETH over 300k gas breaks even at 10 gwei
+        let profit = U256::from(3_000_000_000_000_000u64);
+        assert_eq!(
+            calculate_break_even_eth_gas_price(profit, 300_000),
+            U256::from(10 * WEI_PER_GWEI)
+        );
+        assert_eq!(calculate_break_even_eth_gas_price(profit, 0), U256::MAX);
+
+        // Recorded V3 legs of 150k gas: 300k for the sandwich
+        let mut gas_profile = GasProfile::new();
+        gas_profile.record_operation("uniswap_v3_swap", 150_000);
+        let v3 = DexProtocol::UniswapV3;
+        assert!(sandwich_remains_profitable_at_gas_price(profit, &gas_profile, v3, 0, 9));
+        assert!(!sandwich_remains_profitable_at_gas_price(profit, &gas_profile, v3, 0, 10));
+        assert!(!sandwich_remains_profitable_at_gas_price(profit, &gas_profile, v3, 0, u64::MAX));
+
+        // Each tick crossing adds gas: 4 crossings at the 25k default push 9 gwei over
+        assert!(!sandwich_remains_profitable_at_gas_price(profit, &gas_profile, v3, 4, 9));
+    }
+
+    #[test]
+    fn test_backrun_profit_sensitivity() {
+        let table = backrun_profit_sensitivity(300, U256::from(1_000_000u64));
+        assert_eq!(table.len(), 6);
+        assert_eq!(table[0], (0, U256::from(1_000_000u64)));
+        assert_eq!(table[1], (1, U256::from(970_000u64)));
+        assert_eq!(table[5], (5, U256::from(850_000u64)));
+
+        // Volatility large enough to erase the profit floors at zero
+        let table = backrun_profit_sensitivity(4000, U256::from(1_000_000u64));
+        assert_eq!(table[2], (2, U256::from(200_000u64)));
+        assert_eq!(table[3], (3, U256::zero()));
+    }
+
+    #[test]
+    fn test_calculate_deadline_urgency() {
+        assert_eq!(calculate_deadline_urgency(0), UrgencyLevel::Expired);
+        assert_eq!(calculate_deadline_urgency(1), UrgencyLevel::Critical);
+        assert_eq!(calculate_deadline_urgency(2), UrgencyLevel::Critical);
+        assert_eq!(calculate_deadline_urgency(3), UrgencyLevel::High);
+        assert_eq!(calculate_deadline_urgency(5), UrgencyLevel::High);
+        assert_eq!(calculate_deadline_urgency(6), UrgencyLevel::Medium);
+        assert_eq!(calculate_deadline_urgency(20), UrgencyLevel::Medium);
+        assert_eq!(calculate_deadline_urgency(21), UrgencyLevel::Low);
+        assert_eq!(calculate_deadline_urgency(u32::MAX), UrgencyLevel::Low);
+    }
+
+    #[test]
+    fn test_adjust_profit_for_urgency() {
+        let profit = U256::from(1_000_000_000_000_000_000u128);
+
+        assert!(adjust_profit_for_urgency(profit, UrgencyLevel::Expired).is_zero());
+        assert!(adjust_profit_for_urgency(profit, UrgencyLevel::Low) < profit);
+        assert_eq!(adjust_profit_for_urgency(profit, UrgencyLevel::High), profit);
+        assert!(adjust_profit_for_urgency(profit, UrgencyLevel::Critical) > profit);
+
+        // Scores are ordered by urgency for equal profit
+        assert!(
+            adjust_profit_for_urgency(profit, UrgencyLevel::Low)
+                < adjust_profit_for_urgency(profit, UrgencyLevel::Medium)
+        );
+
+        // Saturates after dividing, so huge profits keep the urgency ordering
+        assert_eq!(adjust_profit_for_urgency(U256::MAX, UrgencyLevel::Critical), U256::MAX);
+        let large = U256::MAX / U256::from(11000);
+        assert!(
+            adjust_profit_for_urgency(large, UrgencyLevel::Critical)
+                > adjust_profit_for_urgency(large, UrgencyLevel::High)
+        );
+        assert_eq!(adjust_profit_for_urgency(large, UrgencyLevel::High), large);
+    }
+}
@@ -0,0 +1,277 @@
+//! Bundle State Transition Simulation
+//!
+//! Replays a sandwich bundle swap by swap against pool snapshots, recording
+//! the pool state and the caller's token flows after every transaction. The
+//! searcher's net balance change must match the profit reported by the
+//! protocol math; a mismatch means the profit model and the state model have
+//! diverged.
+//!
+//! Pool state updates are modelled per protocol:
+//!
+//! - **V3 / Kyber**: new sqrt price and tick within the active range, liquidity unchanged
+//! - **Curve / Balancer**: input balance grows by `amount_in`, output balance
+//!   shrinks by `amount_out` (fees stay in the pool)
+
+use crate::core::MathError;
+use crate::dex::balancer::math::BalancerPoolState;
+use crate::dex::bundle::BundleRole;
+use crate::dex::curve::math::CurvePoolState;
+use crate::dex::uniswap_v3::math::V3PoolState;
+use ethers::types::{Address, U256};
+use std::collections::HashMap;
+
+/// Pools are keyed by contract address
+pub type PoolKey = Address;
+
+/// Protocol-specific pool snapshot
+#[derive(Debug, Clone)]
+pub enum PoolState {
+    V3(V3PoolState),
+    Curve(CurvePoolState),
+    Balancer(BalancerPoolState),
+}
+
+impl PoolState {
+    /// Apply a swap, returning the new state and the output amount
+    ///
+    /// # Arguments
+    /// * `amount_in` - Exact input amount
+    /// * `token_in` - Index of the input token in the pool
+    /// * `token_out` - Index of the output token in the pool
+    ///
+    /// # Returns
+    /// * `Ok((PoolState, U256))` - Post-swap state and amount out
+    /// * `Err(MathError)` - If the swap cannot be quoted or the indices are invalid
+    pub fn apply_swap(
+        &self,
+        amount_in: U256,
+        token_in: usize,
+        token_out: usize,
+    ) -> Result<(PoolState, U256), MathError> {
+        match self {
+            PoolState::V3(state) => state
+                .after_swap(amount_in, token_in, token_out)
+                .map(|(next, amount_out)| (PoolState::V3(next), amount_out)),
+            PoolState::Curve(state) => state
+                .after_swap(amount_in, token_in, token_out)
+                .map(|(next, amount_out)| (PoolState::Curve(next), amount_out)),
+            PoolState::Balancer(state) => state
+                .after_swap(amount_in, token_in, token_out)
+                .map(|(next, amount_out)| (PoolState::Balancer(next), amount_out)),
+        }
+    }
+}
+
+/// One decoded swap in a bundle
+#[derive(Debug, Clone)]
+pub struct BundleSwap {
+    /// Position in the sandwich
+    pub role: BundleRole,
+    /// Pool the swap executes against
+    pub pool: PoolKey,
+    /// Index of the input token in the pool
+    pub token_in: usize,
+    /// Index of the output token in the pool
+    pub token_out: usize,
+    /// Exact input amount
+    pub amount_in: U256,
+    /// Gas used by the transaction
+    pub gas_used: u64,
+}
+
+/// Pool and caller state around one bundle transaction
+#[derive(Debug, Clone)]
+pub struct StateTransition {
+    /// Pool state before the transaction
+    pub pool_state_before: PoolState,
+    /// Pool state after the transaction
+    pub pool_state_after: PoolState,
+    /// Caller's (tokens paid, tokens received), in the swap's input and output tokens
+    pub caller_balance_delta: (U256, U256),
+    /// Gas used by the transaction
+    pub gas_used: u64,
+}
+
+/// Apply each bundle transaction in sequence, tracking pool state and caller flows
+///
+/// Swaps on the same pool see the state left by earlier swaps; pools not
+/// touched by the bundle are never read.
+///
+/// # Arguments
+/// * `bundle` - Decoded swaps in bundle order
+/// * `initial_states` - Pool snapshots at the start of the bundle
+///
+/// # Returns
+/// * `Ok(Vec<StateTransition>)` - One transition per swap, in bundle order
+/// * `Err(MathError)` - If a pool is missing or a swap fails
+pub fn simulate_state_transitions(
+    bundle: &[BundleSwap],
+    initial_states: &HashMap<PoolKey, PoolState>,
+) -> Result<Vec<StateTransition>, MathError> {
+    let mut states = initial_states.clone();
+    let mut transitions = Vec::with_capacity(bundle.len());
+
+    for swap in bundle {
+        let before = states
+            .get(&swap.pool)
+            .cloned()
+            .ok_or_else(|| MathError::InvalidInput {
+                operation: "simulate_state_transitions".to_string(),
+                reason: "No initial state for pool".to_string(),
+                context: format!("pool={:?}, role={:?}", swap.pool, swap.role),
+            })?;
+
+        let (after, amount_out) = before.apply_swap(swap.amount_in, swap.token_in, swap.token_out)?;
+        states.insert(swap.pool, after.clone());
+
+        transitions.push(StateTransition {
+            pool_state_before: before,
+            pool_state_after: after,
+            caller_balance_delta: (swap.amount_in, amount_out),
+            gas_used: swap.gas_used,
+        });
+    }
+
+    Ok(transitions)
+}
+
+/// Check that the searcher's state-based balance change matches the modelled profit
+///
+/// Net change = backrun output - frontrun input - gas cost, floored at zero
+/// like the profit functions.
+///
+/// # Arguments
+/// * `bundle` - Decoded swaps in bundle order
+/// * `transitions` - Output of `simulate_state_transitions` for `bundle`
+/// * `expected_profit` - Profit reported by the protocol math (before gas)
+/// * `gas_cost` - Total searcher gas cost, in the frontrun input token
+///
+/// # Returns
+/// * `Ok(U256)` - Net searcher balance change
+/// * `Err(MathError)` - If the bundle lacks a frontrun or backrun, or the two disagree
+pub fn verify_sandwich_accounting(
+    bundle: &[BundleSwap],
+    transitions: &[StateTransition],
+    expected_profit: U256,
+    gas_cost: U256,
+) -> Result<U256, MathError> {
+    let leg = |role: BundleRole| {
+        bundle
+            .iter()
+            .zip(transitions)
+            .find(|(swap, _)| swap.role == role)
+            .map(|(_, transition)| transition.caller_balance_delta)
+    };
+
+    let (Some((frontrun_in, _)), Some((_, backrun_out))) =
+        (leg(BundleRole::Frontrun), leg(BundleRole::Backrun))
+    else {
+        return Err(MathError::InvalidInput {
+            operation: "verify_sandwich_accounting".to_string(),
+            reason: "Bundle needs a frontrun and a backrun".to_string(),
+            context: format!("swaps={}, transitions={}", bundle.len(), transitions.len()),
+        });
+    };
+
+    let net = backrun_out.saturating_sub(frontrun_in.saturating_add(gas_cost));
+    let expected = expected_profit.saturating_sub(gas_cost);
+    if net != expected {
+        tracing::warn!(
+            "Sandwich accounting mismatch: state-based {} vs modelled {}",
+            net,
+            expected
+        );
+        return Err(MathError::InvalidInput {
+            operation: "verify_sandwich_accounting".to_string(),
+            reason: "State-based balance change does not match modelled profit".to_string(),
+            context: format!(
+                "net={}, expected={}, frontrun_in={}, backrun_out={}, gas_cost={}",
+                net, expected, frontrun_in, backrun_out, gas_cost
+            ),
+        });
+    }
+
+    Ok(net)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::BasisPoints;
+    use crate::dex::protocol::PoolQuoter;
+    use crate::dex::uniswap_v3::math::calculate_v3_sandwich_profit;
+
+    fn swap(role: BundleRole, token_in: usize, amount_in: U256) -> BundleSwap {
+        BundleSwap {
+            role,
+            pool: Address::from_low_u64_be(1),
+            token_in,
+            token_out: 1 - token_in,
+            amount_in,
+            gas_used: 150_000,
+        }
+    }
+
+    #[test]
+    fn test_state_transitions_match_v3_sandwich_profit() {
+        let pool = V3PoolState {
+            sqrt_price_x96: U256::from(79228162514264337593543950336u128),
+            liquidity: 1_000_000_000_000_000_000_000u128,
+            tick: 0,
+            fee_bps: BasisPoints::new_const(5),
+        };
+        let mut initial_states = HashMap::new();
+        initial_states.insert(Address::from_low_u64_be(1), PoolState::V3(pool.clone()));
+
+        let frontrun = U256::from(10_000_000_000_000_000_000u128);
+        let victim = U256::from(50_000_000_000_000_000_000u128);
+
+        // Backrun sells exactly what the frontrun bought
+        let frontrun_out = pool.quote_amount_out(frontrun, 0, 1).unwrap();
+        let bundle = vec![
+            swap(BundleRole::Frontrun, 0, frontrun),
+            swap(BundleRole::Victim, 0, victim),
+            swap(BundleRole::Backrun, 1, frontrun_out),
+        ];
+
+        let transitions = simulate_state_transitions(&bundle, &initial_states).unwrap();
+        assert_eq!(transitions.len(), 3);
+
+        // Each transaction starts from the previous one's end state
+        for pair in transitions.windows(2) {
+            match (&pair[0].pool_state_after, &pair[1].pool_state_before) {
+                (PoolState::V3(a), PoolState::V3(b)) => {
+                    assert_eq!(a.sqrt_price_x96, b.sqrt_price_x96)
+                }
+                _ => panic!("expected V3 states"),
+            }
+        }
+
+        let expected = calculate_v3_sandwich_profit(
+            frontrun,
+            victim,
+            pool.sqrt_price_x96,
+            pool.liquidity,
+            pool.tick,
+            pool.fee_bps,
+            BasisPoints::new_const(0),
+        )
+        .unwrap();
+        assert!(expected > U256::zero());
+
+        let gas_cost = U256::from(1_000_000u64);
+        let net = verify_sandwich_accounting(&bundle, &transitions, expected, gas_cost).unwrap();
+        assert_eq!(net, expected - gas_cost);
+
+        // A profit model that disagrees with the state replay is flagged
+        assert!(
+            verify_sandwich_accounting(&bundle, &transitions, expected + 1, gas_cost).is_err()
+        );
+    }
+
+    #[test]
+    fn test_state_transitions_missing_pool() {
+        let bundle = vec![swap(BundleRole::Frontrun, 0, U256::from(1_000u64))];
+        assert!(simulate_state_transitions(&bundle, &HashMap::new()).is_err());
+    }
+}
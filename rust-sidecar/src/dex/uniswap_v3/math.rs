@@ -0,0 +1,7654 @@
+//! Uniswap V3 / Kyber Elastic Math - Production-grade tick math
+//!
+//! V3 uses concentrated liquidity with ticks representing price points.
+//! Math is identical between Uniswap V3 and Kyber Elastic.
+//!
+//! Key formula: price = 1.0001^tick
+//! Represented as sqrt(price) in Q64.96 fixed-point format
+
+use crate::core::{BasisPoints, MathError, ProtocolMathError};
+use crate::dex::adapter::SwapDirection;
+use crate::dex::bundle::V3SwapParams;
+use crate::dex::optimizer::{golden_section_maximize, DEFAULT_ITERATIONS};
+use crate::dex::protocol::{DexProtocol, PoolPricer, PoolQuoter, PoolSimulator};
+use ethers::types::U256;
+use primitive_types::U512;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+/// Rounding direction for Uniswap V3 amount calculations
+/// 
+/// Uniswap V3 uses asymmetric rounding:
+/// - RoundUp: When trader pays (overcharge trader, favor pool)
+/// - RoundDown: When pool pays (underpay trader, favor pool)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rounding {
+    /// Round up (ceil) - used when computing trader input amounts
+    Up,
+    /// Round down (floor) - used when computing pool output amounts
+    Down,
+}
+
+/// Minimum tick value
+pub const MIN_TICK: i32 = -887272;
+
+/// Maximum tick value
+pub const MAX_TICK: i32 = 887272;
+
+/// Minimum sqrt ratio (at MIN_TICK)
+pub const MIN_SQRT_RATIO: u128 = 4295128739;
+
+/// Maximum sqrt ratio (at MAX_TICK) - calculated at runtime
+fn get_max_sqrt_ratio() -> U256 {
+    U256::from_dec_str("1461446703485210103287273052203988822378723970342").unwrap()
+}
+
+/// Constant: log2(1.0001) in Q64.64 fixed-point format
+/// log2(1.0001) = ln(1.0001) / ln(2) ≈ 0.000144269504088
+/// In Q64.64: 0.000144269504088 * 2^64 ≈ 2657364
+/// More precisely: 0.000144269504088 * 18446744073709551616 ≈ 2657364.8
+#[allow(dead_code)]
+const LOG2_1_0001_Q64_64: i128 = 2657365;
+
+/// Constant: 1 / log2(1.0001) in Q64.64 fixed-point format
+/// 1 / log2(1.0001) ≈ 6931.470
+/// In Q64.64: 6931.470 * 2^64 ≈ 127845451740000000000
+/// More precisely: 6931.470 * 18446744073709551616 ≈ 127845451740000000000
+#[allow(dead_code)]
+const INV_LOG2_1_0001_Q64_64: i128 = 127845451740000000000;
+
+/// Static constant for U256::MAX as U512 (computed once at first access)
+/// This avoids recalculating on every u512_to_ethers_u256 call
+static MAX_U256_U512: OnceLock<U512> = OnceLock::new();
+
+/// Get U256::MAX as U512 (lazy initialization)
+/// Initialized directly without calling ethers_u256_to_u512 to avoid circular dependency
+fn get_max_u256_u512() -> &'static U512 {
+    MAX_U256_U512.get_or_init(|| {
+        // Directly construct U512 from U256::MAX bytes
+        // U256::MAX = 2^256 - 1, represented as all 0xFF bytes
+        let mut u512_bytes = [0u8; 64];
+        // Lower 32 bytes are all 0xFF (U256::MAX)
+        u512_bytes[32..64].fill(0xFF);
+        U512::from_big_endian(&u512_bytes)
+    })
+}
+
+/// Find the most significant bit (MSB) position of a U256 value
+/// Returns the bit position (0-255), or 0 if value is zero
+///
+/// Dispatches to the `lzcnt`-based path on x86_64 CPUs that support it.
+fn find_msb_u256(value: U256) -> u8 {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("bmi1") && is_x86_feature_detected!("lzcnt") {
+            // SAFETY: the required CPU features were detected at runtime
+            return unsafe { find_msb_u256_simd(value) };
+        }
+    }
+
+    find_msb_u256_portable(value)
+}
+
+/// Find the MSB position using hardware leading-zero counts
+///
+/// U256 is stored as four little-endian u64 words; the MSB is
+/// `255 - leading_zeros` over the whole 256-bit value. Returns 0 if value is zero.
+///
+/// # Safety
+/// The CPU must support the `bmi1` and `lzcnt` target features.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "bmi1,lzcnt")]
+unsafe fn find_msb_u256_simd(value: U256) -> u8 {
+    let mut leading_zeros = 0u32;
+    for word in value.0.iter().rev() {
+        if *word != 0 {
+            leading_zeros += word.leading_zeros();
+            return (255 - leading_zeros) as u8;
+        }
+        leading_zeros += 64;
+    }
+
+    0
+}
+
+/// Find the MSB position by binary search (any target)
+fn find_msb_u256_portable(value: U256) -> u8 {
+    if value.is_zero() {
+        return 0;
+    }
+
+    let mut msb = 0u8;
+    let mut r = value;
+
+    // Binary search for MSB position
+    if r >= U256::from(1u128) << 128 {
+        r = r >> 128;
+        msb |= 128;
+    }
+    if r >= U256::from(1u128) << 64 {
+        r = r >> 64;
+        msb |= 64;
+    }
+    if r >= U256::from(1u128) << 32 {
+        r = r >> 32;
+        msb |= 32;
+    }
+    if r >= U256::from(1u128) << 16 {
+        r = r >> 16;
+        msb |= 16;
+    }
+    if r >= U256::from(1u128) << 8 {
+        r = r >> 8;
+        msb |= 8;
+    }
+    if r >= U256::from(1u128) << 4 {
+        r = r >> 4;
+        msb |= 4;
+    }
+    if r >= U256::from(1u128) << 2 {
+        r = r >> 2;
+        msb |= 2;
+    }
+    if r >= U256::from(1u128) << 1 {
+        msb |= 1;
+    }
+
+    msb
+}
+
+/// Calculate log2 approximation using MSB
+/// Returns log2(value) in Q64.64 fixed-point format
+///
+/// # Arguments
+/// * `value` - The value to calculate log2 of
+/// * `base_shift` - The shift representing 1.0 in the input format (96 for Q64.96, 64 for Q64.64)
+fn log2_approx_with_base(value: U256, base_shift: u32) -> Result<i128, MathError> {
+    if value.is_zero() {
+        return Err(MathError::InvalidInput {
+            operation: "log2_approx".to_string(),
+            reason: "Cannot calculate log2 of zero".to_string(),
+            context: "".to_string(),
+        });
+    }
+
+    if value == U256::from(1u128) << base_shift {
+        // Value equals 1.0 in the given format, log2(1.0) = 0
+        return Ok(0);
+    }
+
+    let msb = find_msb_u256(value);
+    // For given format, log2 ≈ MSB - base_shift
+    // Convert to Q64.64: (MSB - base_shift) * 2^64
+    let log2_approx = ((msb as i128) - (base_shift as i128)) << 64;
+    Ok(log2_approx)
+}
+
+/// Calculate log2 approximation using MSB (Q64.96 format)
+/// Returns log2(value) in Q64.64 fixed-point format
+/// For sqrt_price in Q64.96 format, log2 ≈ MSB - 96
+fn log2_approx(value: U256) -> Result<i128, MathError> {
+    log2_approx_with_base(value, 96)
+}
+
+/// Calculate precise log2 using iterative refinement
+/// Returns log2(value) in Q64.64 fixed-point format
+/// Uses MSB as initial approximation, then refines using iterative method
+///
+/// # Arguments
+/// * `value` - The value to calculate log2 of
+/// * `base_shift` - The shift representing 1.0 in the input format (96 for Q64.96, 64 for Q64.64)
+fn log2_precise_with_base(value: U256, base_shift: u32) -> Result<i128, MathError> {
+    if value.is_zero() {
+        return Err(MathError::InvalidInput {
+            operation: "log2_precise".to_string(),
+            reason: "Cannot calculate log2 of zero".to_string(),
+            context: "".to_string(),
+        });
+    }
+
+    let one_in_format = U256::from(1u128) << base_shift;
+    if value == one_in_format {
+        // Value equals 1.0 in the given format, log2(1.0) = 0
+        return Ok(0);
+    }
+
+    let msb = u32::from(find_msb_u256(value));
+
+    // Initial approximation: log2 ≈ MSB - base_shift
+    // In Q64.64: (MSB - base_shift) * 2^64
+    let mut log2: i128 = ((msb as i128) - (base_shift as i128)) << 64;
+
+    // Refine with fractional part using iterative squaring method
+    // This is the standard method used in Uniswap V3's TickMath
+    //
+    // Key insight: For value in Q64.base_shift format:
+    // log2(value) = msb - base_shift + log2(value / 2^msb)
+    //             = msb - base_shift + log2(normalized_fraction)
+    // where normalized_fraction is in [1, 2)
+    //
+    // For the fractional part, we use: log2(f) where f ∈ [1, 2)
+    // We compute this by repeated squaring:
+    // If f^2 >= 2, then log2(f) has a 0.5 (2^-1) bit set, set f = f^2/2
+    // If f^2 < 2, then log2(f) doesn't have that bit set
+    // Continue for more precision bits
+
+    // Normalize to [2^base_shift, 2^(base_shift+1)) = [1.0, 2.0) in given format
+    let mut r = if msb > base_shift {
+        value >> (msb - base_shift)
+    } else if msb < base_shift {
+        value << (base_shift - msb)
+    } else {
+        value
+    };
+
+    // Now r is in [2^base_shift, 2^(base_shift+1))
+    // Compute fractional bits by repeated squaring
+    // Each iteration gives one more bit of precision
+    let two_base = U256::from(1u128) << (base_shift + 1); // 2.0 in format
+
+    // Compute up to 16 fractional bits for good precision
+    for i in 1..=16u32 {
+        // Square r (need to handle overflow - use U512 if necessary)
+        // r is in [2^base_shift, 2^(base_shift+1)), so r^2 is in [2^(2*base_shift), 2^(2*base_shift+2))
+        // To keep in range, divide by 2^base_shift after squaring
+
+        // r^2 / 2^base_shift = new_r
+        // If new_r >= 2^(base_shift+1), then this bit of log2 is set
+        let r_squared = mul_div(r, r, one_in_format).unwrap_or(r);
+
+        if r_squared >= two_base {
+            // This bit is set
+            log2 += 1i128 << (64 - i);
+            // Normalize back to [1, 2): divide by 2
+            r = r_squared >> 1;
+        } else {
+            // This bit is not set
+            r = r_squared;
+        }
+    }
+
+    Ok(log2)
+}
+
+/// Calculate precise log2 for Q64.96 format (sqrt_price)
+/// Returns log2(value) in Q64.64 fixed-point format
+fn log2_precise(value: U256) -> Result<i128, MathError> {
+    log2_precise_with_base(value, 96)
+}
+
+/// Calculate precise log2 for Q64.64 format (price ratio)
+/// Returns log2(value) in Q64.64 fixed-point format
+fn log2_precise_q64_64(value: U256) -> Result<i128, MathError> {
+    log2_precise_with_base(value, 64)
+}
+
+/// Calculate price ratio between new and old sqrt_price
+/// Returns ratio in Q64.64 format (where 2^64 = 1.0)
+/// Formula: ratio = (new_sqrt_price << 64) / old_sqrt_price
+/// Uses U512 to handle intermediate overflow (sqrt_prices are typically ~2^96)
+fn calculate_price_ratio(new_sqrt_price: U256, old_sqrt_price: U256) -> Result<U256, MathError> {
+    if old_sqrt_price.is_zero() {
+        return Err(MathError::DivisionByZero {
+            operation: "calculate_price_ratio".to_string(),
+            context: format!(
+                "old_sqrt_price cannot be zero (new_sqrt_price={})",
+                new_sqrt_price
+            ),
+        });
+    }
+
+    if new_sqrt_price.is_zero() {
+        return Err(MathError::InvalidInput {
+            operation: "calculate_price_ratio".to_string(),
+            reason: "new_sqrt_price cannot be zero".to_string(),
+            context: format!("old_sqrt_price={}", old_sqrt_price),
+        });
+    }
+
+    // CRITICAL: Use U512 for intermediate calculation
+    // sqrt_prices are typically ~2^96, so sqrt_price << 64 would be ~2^160
+    // This exceeds U256::MAX (2^256), so we must use U512
+    let new_u512 = ethers_u256_to_u512(new_sqrt_price);
+    let old_u512 = ethers_u256_to_u512(old_sqrt_price);
+
+    // Calculate (new_sqrt_price << 64) in U512
+    let numerator = new_u512
+        .checked_mul(u128_to_u512(1u128 << 64))
+        .ok_or_else(|| MathError::Overflow {
+            operation: "calculate_price_ratio".to_string(),
+            inputs: vec![new_sqrt_price],
+            context: "Multiplying new_sqrt_price by 2^64 in U512".to_string(),
+        })?;
+
+    // Divide in U512
+    let ratio_u512 = numerator / old_u512;
+
+    // Convert back to U256 - ratio should be close to 2^64 for similar prices
+    u512_to_ethers_u256(ratio_u512).map_err(|e| match e {
+        MathError::Overflow {
+            operation,
+            inputs: _,
+            context: _,
+        } => MathError::Overflow {
+            operation,
+            inputs: vec![new_sqrt_price, old_sqrt_price],
+            context: format!(
+                "Price ratio exceeds U256::MAX (new={}, old={})",
+                new_sqrt_price, old_sqrt_price
+            ),
+        },
+        other => other,
+    })
+}
+
+/// Fast-path price ratio for the Brent's method hot loop
+/// Returns ratio in Q64.64 format, identical to `calculate_price_ratio`
+/// When new_sqrt_price < 2^128, (new_sqrt_price << 64) fits in U256 (< 2^192),
+/// so the U512 widening is skipped. Falls back to the U512 path otherwise
+/// (including zero inputs, which it rejects with the same errors).
+fn calculate_price_ratio_fast(
+    new_sqrt_price: U256,
+    old_sqrt_price: U256,
+) -> Result<U256, MathError> {
+    if !new_sqrt_price.is_zero()
+        && !old_sqrt_price.is_zero()
+        && new_sqrt_price < (U256::one() << 128)
+    {
+        return Ok((new_sqrt_price << 64) / old_sqrt_price);
+    }
+
+    calculate_price_ratio(new_sqrt_price, old_sqrt_price)
+}
+
+/// Calculate tick delta from price ratio using logarithmic formula
+/// Returns tick_delta with directional rounding:
+/// - Positive delta: round DOWN (floor) - haven't crossed next tick boundary
+/// - Negative delta: round UP (ceiling toward zero) - haven't crossed previous tick boundary
+/// Formula: tick_delta = log2(ratio) / log2(1.0001)
+///
+/// # Arguments
+/// * `ratio` - Price ratio in Q64.64 format (where 2^64 = 1.0)
+fn calculate_tick_delta_from_ratio(ratio: U256) -> Result<i32, MathError> {
+    if ratio.is_zero() {
+        return Err(MathError::InvalidInput {
+            operation: "calculate_tick_delta_from_ratio".to_string(),
+            reason: "ratio cannot be zero".to_string(),
+            context: "".to_string(),
+        });
+    }
+
+    // Calculate log2(ratio) in Q64.64 format
+    // CRITICAL: ratio is in Q64.64 format (2^64 = 1.0), so use the Q64.64 version
+    let log2_ratio = log2_precise_q64_64(ratio)?;
+
+    // Calculate tick_delta = log2(ratio) / log2(1.0001)
+    // = log2(ratio) * (1/log2(1.0001))
+    // Where 1/log2(1.0001) ≈ 6931.47
+    //
+    // log2_ratio is in Q64.64, meaning log2_ratio / 2^64 = actual log2 value
+    // We need: tick_delta = (log2_ratio / 2^64) * 6931.47
+    // = log2_ratio * 6931.47 / 2^64
+    //
+    // Use integer constant: 6931 (drop fractional part for speed, HFT-optimized)
+    // This gives ~0.007% error which is acceptable for tick calculation
+    const INV_LOG2_1_0001_INT: i64 = 6931;
+
+    // tick_delta = log2_ratio * 6931 / 2^64
+    let tick_delta_i64 = ((log2_ratio as i128) * (INV_LOG2_1_0001_INT as i128)) >> 64;
+    let tick_delta = tick_delta_i64 as i32;
+
+    // Validate bounds (reasonable for single swap)
+    if tick_delta.abs() > 10000 {
+        return Err(MathError::InvalidInput {
+            operation: "calculate_tick_delta_from_ratio".to_string(),
+            reason: format!("tick_delta {} exceeds reasonable bounds", tick_delta),
+            context: format!("ratio={}, log2_ratio={}", ratio, log2_ratio),
+        });
+    }
+
+    Ok(tick_delta)
+}
+
+/// Convert tick to square root price ratio (Q64.96 format)
+///
+/// This implements the exact Uniswap V3 TickMath.sol algorithm.
+/// Uses bit-by-bit multiplication for precision.
+///
+/// # Arguments
+/// * `tick` - Tick value (-887272 to 887272)
+///
+/// # Returns
+/// * `Ok(U256)` - Sqrt price ratio in Q64.96 format
+/// * `Err(MathError)` - If tick out of bounds
+///
+/// # Examples
+/// ```
+/// use ethers::types::U256;
+/// use rust_sidecar::dex::uniswap_v3::math::get_sqrt_ratio_at_tick;
+///
+/// // Tick 0 is price 1.0, i.e. sqrt_price = 2^96
+/// let sqrt_price = get_sqrt_ratio_at_tick(0).unwrap();
+/// assert_eq!(sqrt_price, U256::from(79228162514264337593543950336u128));
+///
+/// // ETH/USDC-like tick: higher ticks give higher prices
+/// let eth_usdc = get_sqrt_ratio_at_tick(200_000).unwrap();
+/// assert!(eth_usdc > sqrt_price);
+/// assert!(get_sqrt_ratio_at_tick(887_273).is_err());
+/// ```
+pub fn get_sqrt_ratio_at_tick(tick: i32) -> Result<U256, MathError> {
+    // Validate tick bounds
+    if tick < MIN_TICK || tick > MAX_TICK {
+        return Err(MathError::InvalidInput {
+            operation: "get_sqrt_ratio_at_tick".to_string(),
+            reason: format!("Tick {} out of bounds [{}, {}]", tick, MIN_TICK, MAX_TICK),
+            context: "".to_string(),
+        });
+    }
+
+    // Fast path for common values
+    match tick {
+        0 => return Ok(U256::from(79228162514264337593543950336u128)), // 2^96
+        MIN_TICK => return Ok(U256::from(MIN_SQRT_RATIO)),
+        MAX_TICK => return Ok(get_max_sqrt_ratio()),
+        _ => {}
+    }
+
+    // Exact algorithm from Uniswap V3 TickMath.sol
+    // https://github.com/Uniswap/v3-core/blob/main/contracts/libraries/TickMath.sol
+    let abs_tick = if tick < 0 {
+        (-tick) as u32
+    } else {
+        tick as u32
+    };
+
+    // CRITICAL: Magic numbers from Uniswap V3 TickMath.sol (in hex, converted to decimal)
+    // Initial ratio based on bit 0x1
+    // 0xfffcb933bd6fad37aa2d162d1a594001 = 340265354078544963557816517032075149313
+    // 0x100000000000000000000000000000000 = 340282366920938463463374607431768211456 (2^128)
+    let mut ratio: U256 = if abs_tick & 0x1 != 0 {
+        U256::from_dec_str("340265354078544963557816517032075149313").unwrap()
+    } else {
+        U256::from(1u128) << 128
+    };
+
+    // Bit-by-bit multiplication (exact magic numbers from TickMath.sol)
+    // Each constant is derived from 1/sqrt(1.0001) raised to powers of 2
+    // The pattern is: ratio = (ratio * MAGIC_CONSTANT) >> 128
+    // 0xfff97272373d413259a46990580e213a
+    if abs_tick & 0x2 != 0 {
+        ratio =
+            (ratio * U256::from_dec_str("340248342086729790484326174814286782906").unwrap()) >> 128;
+    }
+    // 0xfff2e50f5f656932ef12357cf3c7fdcc
+    if abs_tick & 0x4 != 0 {
+        ratio =
+            (ratio * U256::from_dec_str("340214320654664324051920982716015181772").unwrap()) >> 128;
+    }
+    // 0xffe5caca7e10e4e61c3624eaa0941cd0
+    if abs_tick & 0x8 != 0 {
+        ratio =
+            (ratio * U256::from_dec_str("340146287995602323631171512101879684816").unwrap()) >> 128;
+    }
+    // 0xffcb9843d60f6159c9db58835c926644
+    if abs_tick & 0x10 != 0 {
+        ratio =
+            (ratio * U256::from_dec_str("340010263488231146823593991679159461444").unwrap()) >> 128;
+    }
+    // 0xff973b41fa98c081472e6896dfb254c0
+    if abs_tick & 0x20 != 0 {
+        ratio =
+            (ratio * U256::from_dec_str("339738377640345403697157401104375502528").unwrap()) >> 128;
+    }
+    // 0xff2ea16466c96a3843ec78b326b52861
+    if abs_tick & 0x40 != 0 {
+        ratio =
+            (ratio * U256::from_dec_str("339195258003219555707034227454543997025").unwrap()) >> 128;
+    }
+    // 0xfe5dee046a99a2a811c461f1969c3053
+    if abs_tick & 0x80 != 0 {
+        ratio =
+            (ratio * U256::from_dec_str("338111622100601834656805679988414885971").unwrap()) >> 128;
+    }
+    // 0xfcbe86c7900a88aedcffc83b479aa3a4
+    if abs_tick & 0x100 != 0 {
+        ratio =
+            (ratio * U256::from_dec_str("335954724994790223023589805789778977700").unwrap()) >> 128;
+    }
+    // 0xf987a7253ac413176f2b074cf7815e54
+    if abs_tick & 0x200 != 0 {
+        ratio =
+            (ratio * U256::from_dec_str("331682121138379247127172139078559817300").unwrap()) >> 128;
+    }
+    // 0xf3392b0822b70005940c7a398e4b70f3
+    if abs_tick & 0x400 != 0 {
+        ratio =
+            (ratio * U256::from_dec_str("323299236684853023288211250268160618739").unwrap()) >> 128;
+    }
+    // 0xe7159475a2c29b7443b29c7fa6e889d9
+    if abs_tick & 0x800 != 0 {
+        ratio =
+            (ratio * U256::from_dec_str("307163716377032989948697243942600083417").unwrap()) >> 128;
+    }
+    // 0xd097f3bdfd2022b8845ad8f792aa5825
+    if abs_tick & 0x1000 != 0 {
+        ratio =
+            (ratio * U256::from_dec_str("277268403626896220162999269216087595813").unwrap()) >> 128;
+    }
+    // 0xa9f746462d870fdf8a65dc1f90e061e5
+    if abs_tick & 0x2000 != 0 {
+        ratio =
+            (ratio * U256::from_dec_str("225923453940442621947126027127485391333").unwrap()) >> 128;
+    }
+    // 0x70d869a156d2a1b890bb3df62baf32f7
+    if abs_tick & 0x4000 != 0 {
+        ratio =
+            (ratio * U256::from_dec_str("149997214084966997727330242082538205943").unwrap()) >> 128;
+    }
+    // 0x31be135f97d08fd981231505542fcfa6
+    if abs_tick & 0x8000 != 0 {
+        ratio =
+            (ratio * U256::from_dec_str("66119101136024775622716233608466517926").unwrap()) >> 128;
+    }
+    // 0x9aa508b5b7a84e1c677de54f3e99bc9
+    if abs_tick & 0x10000 != 0 {
+        ratio =
+            (ratio * U256::from_dec_str("12847376061809297530290974190478138441").unwrap()) >> 128;
+    }
+    // 0x5d6af8dedb81196699c329225ee604
+    if abs_tick & 0x20000 != 0 {
+        ratio =
+            (ratio * U256::from_dec_str("485053260817066172746253684029974020").unwrap()) >> 128;
+    }
+    // 0x2216e584f5fa1ea926041bedfe98
+    if abs_tick & 0x40000 != 0 {
+        ratio = (ratio * U256::from_dec_str("691415978906521570653435304214168").unwrap()) >> 128;
+    }
+    // 0x48a170391f7dc42444e8fa2
+    if abs_tick & 0x80000 != 0 {
+        ratio = (ratio * U256::from_dec_str("1404880482679654955896180642").unwrap()) >> 128;
+    }
+
+    // Handle positive ticks: reciprocal (uint256.max / ratio)
+    // CRITICAL: In Uniswap V3, for tick > 0, ratio = type(uint256).max / ratio
+    // For tick < 0, ratio stays as computed
+    let result = if tick > 0 { U256::MAX / ratio } else { ratio };
+
+    // Convert from Q128.128 to Q64.96 with rounding up
+    // sqrtPriceX96 = (ratio >> 32) + (ratio % (1 << 32) == 0 ? 0 : 1)
+    let shift_amount = 32u32;
+    let mask = (U256::from(1u128) << shift_amount) - U256::from(1);
+    let remainder = result & mask;
+    let mut sqrt_price = result >> shift_amount;
+    if !remainder.is_zero() {
+        sqrt_price = sqrt_price + U256::from(1); // Round up
+    }
+
+    Ok(sqrt_price)
+}
+
+/// Largest multiple of 60 within [MIN_TICK, MAX_TICK]
+const TICK_TABLE_60_MAX: i32 = 887220;
+
+/// Number of precomputed entries: ticks -887220..=887220 in steps of 60
+const TICK_TABLE_60_LEN: usize = (2 * TICK_TABLE_60_MAX / 60 + 1) as usize;
+
+/// Precomputed sqrt ratios for every multiple of 60 (0.3% fee tier tick spacing)
+/// Built once at first access; index = (tick + 887220) / 60
+static TICK_TABLE_60: OnceLock<Vec<U256>> = OnceLock::new();
+
+/// Get the tick-spacing-60 sqrt ratio table (built on first access, then shared)
+fn get_tick_table_60() -> &'static [U256] {
+    TICK_TABLE_60.get_or_init(|| {
+        (0..TICK_TABLE_60_LEN)
+            .map(|idx| {
+                let tick = -TICK_TABLE_60_MAX + (idx as i32) * 60;
+                // All entries are within [MIN_TICK, MAX_TICK], so this cannot fail
+                get_sqrt_ratio_at_tick(tick).unwrap_or_default()
+            })
+            .collect()
+    })
+}
+
+/// Build the tick-spacing-60 table ahead of the hot path
+///
+/// The table is built once per process; call this at startup so the first
+/// sandwich simulation does not pay for the ~30k TickMath evaluations.
+pub fn init_tick_table_60() {
+    let table = get_tick_table_60();
+    tracing::debug!("Tick spacing 60 sqrt ratio table ready: {} entries", table.len());
+}
+
+/// Convert tick to sqrt ratio using the precomputed 0.3% tier table
+///
+/// Multiples of 60 are served from `TICK_TABLE_60` (an index lookup instead of
+/// TickMath's 20 multiplications); any other tick falls back to
+/// `get_sqrt_ratio_at_tick`.
+///
+/// # Arguments
+/// * `tick` - Tick value (-887272 to 887272)
+///
+/// # Returns
+/// * `Some(U256)` - Sqrt price ratio in Q64.96 format
+/// * `None` - If tick is out of bounds
+pub fn get_sqrt_ratio_at_tick_spacing_60(tick: i32) -> Option<U256> {
+    if tick % 60 == 0 && (-TICK_TABLE_60_MAX..=TICK_TABLE_60_MAX).contains(&tick) {
+        let idx = ((tick + TICK_TABLE_60_MAX) / 60) as usize;
+        return Some(get_tick_table_60()[idx]);
+    }
+
+    get_sqrt_ratio_at_tick(tick).ok()
+}
+
+/// Calculate numerical derivative of get_sqrt_ratio_at_tick at given tick
+/// Uses central difference: f'(tick) ≈ (f(tick+1) - f(tick-1)) / 2
+/// At boundaries, uses forward or backward difference
+fn calculate_derivative(tick: i32) -> Result<U256, MathError> {
+    if tick <= MIN_TICK {
+        // At minimum, use forward difference
+        let f_plus = get_sqrt_ratio_at_tick(tick + 1)?;
+        let f_current = get_sqrt_ratio_at_tick(tick)?;
+        f_plus
+            .checked_sub(f_current)
+            .ok_or_else(|| MathError::Underflow {
+                operation: "calculate_derivative".to_string(),
+                inputs: vec![f_plus, f_current],
+                context: format!("Forward difference at tick={}", tick),
+            })
+    } else if tick >= MAX_TICK {
+        // At maximum, use backward difference
+        let f_current = get_sqrt_ratio_at_tick(tick)?;
+        let f_minus = get_sqrt_ratio_at_tick(tick - 1)?;
+        f_current
+            .checked_sub(f_minus)
+            .ok_or_else(|| MathError::Underflow {
+                operation: "calculate_derivative".to_string(),
+                inputs: vec![f_current, f_minus],
+                context: format!("Backward difference at tick={}", tick),
+            })
+    } else {
+        // Central difference (most accurate)
+        let f_plus = get_sqrt_ratio_at_tick(tick + 1)?;
+        let f_minus = get_sqrt_ratio_at_tick(tick - 1)?;
+        let diff = f_plus
+            .checked_sub(f_minus)
+            .ok_or_else(|| MathError::Underflow {
+                operation: "calculate_derivative".to_string(),
+                inputs: vec![f_plus, f_minus],
+                context: format!("Central difference at tick={}", tick),
+            })?;
+        // Divide by 2: diff / 2
+        Ok(diff / U256::from(2))
+    }
+}
+
+/// 1 / log2(sqrt(1.0001)) ≈ 13863.64, in Q32 fixed point
+///
+/// Truncating to 13863 would drift by up to ~40 ticks near MIN_TICK/MAX_TICK,
+/// so the fractional part is kept.
+const INV_LOG2_SQRT_1_0001_Q32: i128 = 59543866431248;
+
+/// Initial tick guess from the logarithm of the sqrt price
+///
+/// `tick ≈ log2(sqrt_price_x96 / 2^96) / log2(sqrt(1.0001))`, with log2 taken from
+/// the MSB plus 16 fractional bits (`log2_precise`). The guess is within a tick
+/// or two of the answer, so Newton's method needs only 2-3 iterations.
+fn log2_initial_tick_guess(sqrt_price_x96: U256) -> i32 {
+    let log2_q64 = match log2_precise(sqrt_price_x96) {
+        Ok(log2) => log2,
+        // Only fails for zero, which callers reject; fall back to the MSB alone
+        Err(_) => (i128::from(find_msb_u256(sqrt_price_x96)) - 96) << 64,
+    };
+
+    // Q64.64 * Q32 -> Q96; |log2| < 2^71 and the constant < 2^46, so no overflow
+    let tick = (log2_q64 * INV_LOG2_SQRT_1_0001_Q32) >> 96;
+    tick.clamp(i128::from(MIN_TICK), i128::from(MAX_TICK)) as i32
+}
+
+/// Check if Newton's method has converged
+/// Converged if: |f(tick)| < tolerance
+fn check_convergence(tick: i32, sqrt_price_x96: U256, tolerance: U256) -> Result<bool, MathError> {
+    let sqrt_at_tick = get_sqrt_ratio_at_tick(tick)?;
+
+    // Check if |f(tick)| < tolerance
+    let f_abs = if sqrt_at_tick >= sqrt_price_x96 {
+        sqrt_at_tick
+            .checked_sub(sqrt_price_x96)
+            .ok_or_else(|| MathError::Underflow {
+                operation: "check_convergence".to_string(),
+                inputs: vec![sqrt_at_tick, sqrt_price_x96],
+                context: format!("f_abs calculation at tick={}", tick),
+            })?
+    } else {
+        sqrt_price_x96
+            .checked_sub(sqrt_at_tick)
+            .ok_or_else(|| MathError::Underflow {
+                operation: "check_convergence".to_string(),
+                inputs: vec![sqrt_price_x96, sqrt_at_tick],
+                context: format!("f_abs calculation at tick={}", tick),
+            })?
+    };
+
+    Ok(f_abs < tolerance)
+}
+
+/// Newton's method iteration: tick_new = tick_old - f(tick_old) / f'(tick_old)
+///
+/// Since we're working with integers, we need to handle the division carefully.
+/// f(tick) = get_sqrt_ratio_at_tick(tick) - sqrt_price_x96
+/// f'(tick) = numerical derivative
+fn newton_iteration(tick: i32, sqrt_price_x96: U256) -> Result<i32, MathError> {
+    // Calculate f(tick) = get_sqrt_ratio_at_tick(tick) - sqrt_price_x96
+    // We need to preserve the sign: positive means sqrt_at_tick > sqrt_price_x96 (need to decrease tick)
+    let sqrt_at_tick = get_sqrt_ratio_at_tick(tick)?;
+    let (f_tick_abs, f_tick_sign) = if sqrt_at_tick >= sqrt_price_x96 {
+        let diff =
+            sqrt_at_tick
+                .checked_sub(sqrt_price_x96)
+                .ok_or_else(|| MathError::Underflow {
+                    operation: "newton_iteration".to_string(),
+                    inputs: vec![sqrt_at_tick, sqrt_price_x96],
+                    context: format!("f(tick) calculation at tick={}", tick),
+                })?;
+        (diff, true) // positive: need to decrease tick
+    } else {
+        let diff =
+            sqrt_price_x96
+                .checked_sub(sqrt_at_tick)
+                .ok_or_else(|| MathError::Underflow {
+                    operation: "newton_iteration".to_string(),
+                    inputs: vec![sqrt_price_x96, sqrt_at_tick],
+                    context: format!("f(tick) calculation at tick={}", tick),
+                })?;
+        (diff, false) // negative: need to increase tick
+    };
+
+    // Calculate f'(tick) using numerical derivative
+    let f_prime = calculate_derivative(tick)
+        .map_err(|e| e.chain("In sqrt_price_to_tick convergence check"))?;
+
+    // Check for zero derivative (would cause division by zero)
+    if f_prime.is_zero() {
+        return Err(MathError::DivisionByZero {
+            operation: "newton_iteration".to_string(),
+            context: format!("Derivative is zero at tick={}", tick),
+        });
+    }
+
+    // Newton step: delta_tick = f(tick) / f'(tick)
+    // Since f and f' are U256, we need to handle the division
+    // For integer tick, we want: delta_tick ≈ f(tick) / f'(tick)
+    // Use scaled division: delta_tick = (f(tick) * SCALE) / f'(tick)
+    const SCALE: u128 = 1_000_000_000_000_000_000; // 10^18 for precision
+
+    let f_tick_scaled =
+        f_tick_abs
+            .checked_mul(U256::from(SCALE))
+            .ok_or_else(|| MathError::Overflow {
+                operation: "newton_iteration".to_string(),
+                inputs: vec![f_tick_abs, U256::from(SCALE)],
+                context: "Scaling f(tick) for division".to_string(),
+            })?;
+
+    let delta_tick_scaled =
+        f_tick_scaled
+            .checked_div(f_prime)
+            .ok_or_else(|| MathError::DivisionByZero {
+                operation: "newton_iteration".to_string(),
+                context: "Dividing by derivative".to_string(),
+            })?;
+
+    // Convert scaled delta back to integer tick delta
+    // delta_tick = delta_tick_scaled / SCALE
+    let delta_tick_abs = (delta_tick_scaled / U256::from(SCALE)).as_u128() as i128;
+
+    // Apply Newton step: tick_new = tick_old - f(tick) / f'(tick)
+    // If f(tick) > 0 (sqrt_at_tick > sqrt_price_x96), we subtract delta (decrease tick)
+    // If f(tick) < 0 (sqrt_at_tick < sqrt_price_x96), we add delta (increase tick)
+    let tick_new = if f_tick_sign {
+        // f(tick) > 0, subtract delta to decrease tick
+        tick as i128 - delta_tick_abs
+    } else {
+        // f(tick) < 0, add delta to increase tick
+        tick as i128 + delta_tick_abs
+    };
+
+    // Clamp to valid range
+    let tick_new = tick_new.max(MIN_TICK as i128).min(MAX_TICK as i128) as i32;
+
+    Ok(tick_new)
+}
+
+/// Convert sqrt price (Q64.96) to tick index (Uniswap V3 TickMath.getTickAtSqrtRatio)
+/// 
+/// PROTOCOL PARITY: Returns the greatest tick t such that getSqrtRatioAtTick(t) <= sqrtPriceX96
+/// This matches on-chain getTickAtSqrtRatio behavior exactly (strict flooring, not nearest).
+/// Uses Newton's method with binary search fallback for optimal performance.
+///
+/// Algorithm:
+/// 1. Calculate initial guess using binary search (5 iterations for fast approximation)
+/// 2. Apply Newton's method: tick_{n+1} = tick_n - f(tick_n) / f'(tick_n)
+///    where f(tick) = get_sqrt_ratio_at_tick(tick) - sqrt_price_x96
+///    and f'(tick) is calculated using numerical derivative (central/forward/backward difference)
+/// 3. Check convergence: |f(tick)| < tolerance (1 part per billion)
+/// 4. If Newton's method converges, find greatest tick where sqrtRatio <= sqrtPriceX96 (strict flooring)
+/// 5. If Newton's method fails to converge, fallback to binary search for 100% reliability
+///
+/// Performance:
+/// - Newton's method typically converges in 3-5 iterations (much faster than binary search)
+/// - Binary search fallback ensures 100% reliability even if Newton's method fails
+/// - Initial guess reduces search space by ~32x before Newton's method starts
+///
+/// # Arguments
+/// * `sqrt_price_x96` - Sqrt price in Q64.96 format
+///
+/// # Returns
+/// * `Ok(i32)` - Tick index (greatest tick where sqrtRatio <= sqrtPriceX96)
+/// * `Err(MathError)` - If sqrt_price out of valid range
+///
+/// # Examples
+/// ```
+/// use ethers::types::U256;
+/// use rust_sidecar::dex::uniswap_v3::math::{get_sqrt_ratio_at_tick, sqrt_price_to_tick};
+///
+/// let tick_0 = U256::from(79228162514264337593543950336u128);
+/// assert_eq!(sqrt_price_to_tick(tick_0).unwrap(), 0);
+///
+/// // Round trip through an ETH/USDC-like tick
+/// let sqrt_price = get_sqrt_ratio_at_tick(-10_000).unwrap();
+/// assert_eq!(sqrt_price_to_tick(sqrt_price).unwrap(), -10_000);
+/// ```
+pub fn sqrt_price_to_tick(sqrt_price_x96: U256) -> Result<i32, MathError> {
+    // Validate bounds (same as before)
+    if sqrt_price_x96 < U256::from(MIN_SQRT_RATIO) {
+        return Ok(MIN_TICK);
+    }
+    if sqrt_price_x96 >= get_max_sqrt_ratio() {
+        return Ok(MAX_TICK);
+    }
+
+    // Fast path for common values
+    let tick_0 = U256::from(79228162514264337593543950336u128); // tick = 0
+    if sqrt_price_x96 == tick_0 {
+        return Ok(0);
+    }
+    if sqrt_price_x96 == U256::from(MIN_SQRT_RATIO) {
+        return Ok(MIN_TICK);
+    }
+    if sqrt_price_x96 == get_max_sqrt_ratio() {
+        return Ok(MAX_TICK);
+    }
+
+    // Initial guess from log2 of the sqrt price (within a couple of ticks)
+    let mut tick = log2_initial_tick_guess(sqrt_price_x96);
+
+    // Set convergence tolerance: 1 part per billion of sqrt_price
+    let tolerance = sqrt_price_x96
+        .checked_div(U256::from(1_000_000_000))
+        .unwrap_or(U256::from(1));
+
+    const MAX_ITERATIONS: usize = 10;
+
+    // Newton's method iteration
+    for _iteration in 0..MAX_ITERATIONS {
+        // Check convergence
+        if check_convergence(tick, sqrt_price_x96, tolerance)? {
+            // PROTOCOL PARITY: Use strict flooring, not "closest tick"
+            // On-chain getTickAtSqrtRatio returns greatest tick t where getSqrtRatioAtTick(t) <= sqrtPriceX96
+            // This is strict ≤ semantics, not nearest-neighbor
+            
+            // Start with candidate tick from Newton's method
+            let mut candidate_tick = tick;
+            
+            // Verify candidate: getSqrtRatioAtTick(candidate) <= sqrtPriceX96
+            let candidate_sqrt = get_sqrt_ratio_at_tick(candidate_tick)?;
+            if candidate_sqrt > sqrt_price_x96 {
+                // Candidate is too high, move down
+                candidate_tick = candidate_tick.saturating_sub(1).max(MIN_TICK);
+            }
+            
+            // Find greatest tick where sqrtRatio <= sqrtPriceX96
+            // Walk upward while next tick's sqrtRatio <= sqrtPriceX96
+            while candidate_tick < MAX_TICK {
+                let next_tick = candidate_tick + 1;
+                let next_sqrt = get_sqrt_ratio_at_tick(next_tick)?;
+                if next_sqrt <= sqrt_price_x96 {
+                    candidate_tick = next_tick;
+                } else {
+                    break;
+                }
+            }
+            
+            // Final verification: ensure candidate_tick satisfies the condition
+            let final_sqrt = get_sqrt_ratio_at_tick(candidate_tick)?;
+            if final_sqrt > sqrt_price_x96 {
+                // Should never happen, but safety check
+                candidate_tick = candidate_tick.saturating_sub(1).max(MIN_TICK);
+            }
+            
+            return Ok(candidate_tick);
+        }
+
+        // Perform Newton iteration
+        let tick_new = newton_iteration(tick, sqrt_price_x96)?;
+
+        // Check if we're stuck (no progress)
+        if tick_new == tick {
+            // No progress, break and use binary search fallback
+            break;
+        }
+
+        tick = tick_new;
+    }
+
+    // Fallback to binary search if Newton's method didn't converge
+    // PROTOCOL PARITY: Binary search also uses strict flooring
+    let mut low = MIN_TICK;
+    let mut high = MAX_TICK;
+
+    while high - low > 1 {
+        let mid = (low + high) / 2;
+        let mid_sqrt = get_sqrt_ratio_at_tick(mid)?;
+
+        if sqrt_price_x96 >= mid_sqrt {
+            // mid_sqrt <= sqrt_price_x96, so mid is a valid candidate
+            // Continue searching upward
+            low = mid;
+        } else {
+            // mid_sqrt > sqrt_price_x96, so mid is too high
+            high = mid;
+        }
+    }
+
+    // After binary search, 'low' is the greatest tick where getSqrtRatioAtTick(low) <= sqrtPriceX96
+    // Verify this condition holds
+    let low_sqrt = get_sqrt_ratio_at_tick(low)?;
+    if low_sqrt > sqrt_price_x96 {
+        // Should never happen, but safety check
+        return Ok(MIN_TICK);
+    }
+    
+    // Final check: if next tick also satisfies condition, use it (strict flooring: greatest tick)
+    if low < MAX_TICK {
+        let next_tick = low + 1;
+        let next_sqrt = get_sqrt_ratio_at_tick(next_tick)?;
+        if next_sqrt <= sqrt_price_x96 {
+            return Ok(next_tick);
+        }
+    }
+    
+    Ok(low)
+}
+
+/// Convert ethers::types::U256 to primitive_types::U512
+/// Handles full 256-bit range by extracting all bytes
+fn ethers_u256_to_u512(value: ethers::types::U256) -> U512 {
+    // CRITICAL: Use byte-based conversion to preserve full 256-bit range
+    // low_u128() truncates values > u128::MAX, causing incorrect conversions
+    // Extract all 32 bytes directly to preserve full precision
+
+    let mut u256_bytes = [0u8; 32];
+    value.to_big_endian(&mut u256_bytes);
+
+    // Construct U512 from 32-byte U256 value
+    // U512::from_big_endian expects 64 bytes in big-endian format
+    // We pad with zeros on the left (high bytes) to make it 64 bytes
+    let mut u512_bytes = [0u8; 64];
+
+    // Copy U256 bytes (32 bytes) into lower 32 bytes of U512 (bytes 32-63)
+    // This preserves the full 256-bit value without truncation
+    u512_bytes[32..64].copy_from_slice(&u256_bytes);
+
+    U512::from_big_endian(&u512_bytes)
+}
+
+/// Helper function to create U512 from a small u128 value
+/// Uses byte-based conversion to ensure correctness
+/// CRITICAL: primitive_types::U512 doesn't implement From<u128> or From<u64>,
+/// so we must use byte-based conversion to avoid "assertion failed: 8 * 8 == bytes.len()"
+#[inline]
+fn u128_to_u512(value: u128) -> U512 {
+    // Convert u128 to U256 first, then to U512 using our safe conversion
+    ethers_u256_to_u512(U256::from(value))
+}
+
+/// Convert primitive_types::U512 back to ethers::types::U256
+/// Returns error if value exceeds U256::MAX
+fn u512_to_ethers_u256(value: U512) -> Result<U256, MathError> {
+    // Check if value fits in U256 using static constant (computed once)
+    let max_u256_u512 = get_max_u256_u512();
+
+    if value > *max_u256_u512 {
+        return Err(MathError::Overflow {
+            operation: "u512_to_ethers_u256".to_string(),
+            inputs: vec![],
+            context: "U512 value exceeds U256::MAX".to_string(),
+        });
+    }
+
+    // Extract lower 32 bytes (256 bits) and convert to U256
+    // CRITICAL: U512.to_big_endian requires a 64-byte buffer (512 bits)
+    let mut u512_bytes = [0u8; 64];
+    value.to_big_endian(&mut u512_bytes);
+    // The lower 32 bytes are in bytes 32-63 (big-endian: MSB first)
+    let mut u256_bytes = [0u8; 32];
+    u256_bytes.copy_from_slice(&u512_bytes[32..64]);
+    Ok(U256::from_big_endian(&u256_bytes))
+}
+
+/// Multiply two U256 values and divide by a third with full precision
+/// Uses 512-bit intermediate arithmetic to prevent overflow
+///
+/// # Arguments
+/// * `a` - First multiplicand
+/// * `b` - Second multiplicand  
+/// * `denominator` - Divisor
+///
+/// # Returns
+/// * `Ok(U256)` - Result of (a * b) / denominator
+/// * `Err(MathError)` - If denominator is zero or result exceeds U256::MAX
+fn mul_div(a: U256, b: U256, denominator: U256) -> Result<U256, MathError> {
+    if denominator.is_zero() {
+        return Err(MathError::DivisionByZero {
+            operation: "mul_div".to_string(),
+            context: format!("denominator is zero (a={}, b={})", a, b),
+        });
+    }
+
+    // Early overflow detection: heuristic check before expensive U512 conversion
+    // Estimate bits needed: log2(a) + log2(b)
+    // If both a and b are large, product might overflow U256 (but we use U512, so this is just for logging)
+    // This is an optimization hint, not a hard check
+    let a_bits = if a.is_zero() {
+        0
+    } else {
+        256 - a.leading_zeros()
+    };
+    let b_bits = if b.is_zero() {
+        0
+    } else {
+        256 - b.leading_zeros()
+    };
+    if a_bits + b_bits > 256 {
+        tracing::debug!(
+            "mul_div: Large values detected (a={}, b={}, denominator={}, estimated_bits={})",
+            a,
+            b,
+            denominator,
+            a_bits + b_bits
+        );
+    }
+
+    // Convert to U512 for intermediate calculation (full 256-bit range)
+    let a_u512 = ethers_u256_to_u512(a);
+    let b_u512 = ethers_u256_to_u512(b);
+    let denom_u512 = ethers_u256_to_u512(denominator);
+
+    // Calculate product in U512 with checked arithmetic
+    let product = a_u512
+        .checked_mul(b_u512)
+        .ok_or_else(|| MathError::Overflow {
+            operation: "mul_div".to_string(),
+            inputs: vec![a, b],
+            context: format!(
+                "product calculation exceeds U512::MAX (a={}, b={}, estimated_bits={})",
+                a,
+                b,
+                a_bits + b_bits
+            ),
+        })?;
+
+    // Divide in U512
+    let result_u512 = product / denom_u512;
+
+    // Convert back to U256 with overflow check
+    u512_to_ethers_u256(result_u512).map_err(|e| {
+        // Enhance error with input values for debugging
+        match e {
+            MathError::Overflow {
+                operation,
+                inputs: _,
+                context,
+            } => MathError::Overflow {
+                operation,
+                inputs: vec![a, b, denominator],
+                context: format!(
+                    "{} (result from mul_div: a={}, b={}, denominator={})",
+                    context, a, b, denominator
+                ),
+            },
+            _ => e,
+        }
+    })
+}
+
+/// Multiply two U256 values and divide by a third with rounding up
+/// Uses 512-bit intermediate arithmetic to prevent overflow
+/// Implements: result = ceil((a * b) / denominator) = (a * b + denominator - 1) / denominator
+///
+/// # Arguments
+/// * `a` - First multiplicand
+/// * `b` - Second multiplicand  
+/// * `denominator` - Divisor
+///
+/// # Returns
+/// * `Ok(U256)` - Result of ceil((a * b) / denominator)
+/// * `Err(MathError)` - If denominator is zero or result exceeds U256::MAX
+///
+/// # Examples
+/// ```
+/// use ethers::types::U256;
+/// use rust_sidecar::dex::uniswap_v3::math::mul_div_rounding_up;
+///
+/// // 1 ETH * 3000 pips / 1e6 = 0.003 ETH (exact)
+/// let one_eth = U256::from(1_000_000_000_000_000_000u128);
+/// let fee = mul_div_rounding_up(one_eth, U256::from(3000), U256::from(1_000_000)).unwrap();
+/// assert_eq!(fee, U256::from(3_000_000_000_000_000u128));
+///
+/// // Inexact division rounds up: ceil(10 / 3) = 4
+/// let rounded = mul_div_rounding_up(U256::from(10), U256::from(1), U256::from(3)).unwrap();
+/// assert_eq!(rounded, U256::from(4));
+/// assert!(mul_div_rounding_up(one_eth, one_eth, U256::zero()).is_err());
+/// ```
+pub fn mul_div_rounding_up(a: U256, b: U256, denominator: U256) -> Result<U256, MathError> {
+    if denominator.is_zero() {
+        return Err(MathError::DivisionByZero {
+            operation: "mul_div_rounding_up".to_string(),
+            context: format!("denominator is zero (a={}, b={})", a, b),
+        });
+    }
+
+    // Convert to U512 for intermediate calculation (full 256-bit range)
+    let a_u512 = ethers_u256_to_u512(a);
+    let b_u512 = ethers_u256_to_u512(b);
+    let denom_u512 = ethers_u256_to_u512(denominator);
+
+    // Early overflow detection: heuristic check before expensive U512 conversion
+    let a_bits = if a.is_zero() {
+        0
+    } else {
+        256 - a.leading_zeros()
+    };
+    let b_bits = if b.is_zero() {
+        0
+    } else {
+        256 - b.leading_zeros()
+    };
+    if a_bits + b_bits > 256 {
+        tracing::debug!(
+            "mul_div_rounding_up: Large values detected (a={}, b={}, denominator={}, estimated_bits={})",
+            a, b, denominator, a_bits + b_bits
+        );
+    }
+
+    // Calculate product in U512 with checked arithmetic
+    let product = a_u512.checked_mul(b_u512)
+        .ok_or_else(|| MathError::Overflow {
+            operation: "mul_div_rounding_up".to_string(),
+            inputs: vec![a, b],
+            context: format!("product calculation exceeds U512::MAX (a={}, b={}, denominator={}, estimated_bits={})", a, b, denominator, a_bits + b_bits),
+        })?;
+
+    // Rounding up formula: (a * b + denominator - 1) / denominator
+    // Add (denominator - 1) before dividing
+    // CRITICAL: Use u128_to_u512 helper - primitive_types::U512 doesn't implement From<u128>
+    let rounding_adjustment =
+        denom_u512
+            .checked_sub(u128_to_u512(1))
+            .ok_or_else(|| MathError::Underflow {
+                operation: "mul_div_rounding_up".to_string(),
+                inputs: vec![denominator],
+                context: format!(
+                    "denominator is zero (should have been caught earlier) (a={}, b={})",
+                    a, b
+                ),
+            })?;
+
+    let numerator_rounded = product
+        .checked_add(rounding_adjustment)
+        .ok_or_else(|| MathError::Overflow {
+            operation: "mul_div_rounding_up".to_string(),
+            inputs: vec![a, b, denominator],
+            context: format!("numerator + rounding adjustment exceeds U512::MAX (a={}, b={}, denominator={}, product={:?})", a, b, denominator, product),
+        })?;
+
+    // Divide in U512
+    let result_u512 = numerator_rounded / denom_u512;
+
+    // Convert back to U256 with overflow check
+    u512_to_ethers_u256(result_u512).map_err(|e| {
+        // Enhance error with input values for debugging
+        match e {
+            MathError::Overflow {
+                operation,
+                inputs: _,
+                context,
+            } => MathError::Overflow {
+                operation,
+                inputs: vec![a, b, denominator],
+                context: format!(
+                    "{} (result from mul_div_rounding_up: a={}, b={}, denominator={})",
+                    context, a, b, denominator
+                ),
+            },
+            _ => e,
+        }
+    })
+}
+
+/// Calculate amount0 delta between two sqrt prices (Uniswap V3 SqrtPriceMath.getAmount0Delta)
+/// 
+/// Formula: amount0 = L * Q96 * (sqrtB - sqrtA) / (sqrtA * sqrtB)
+/// 
+/// # Arguments
+/// * `sqrt_ratio_a` - Lower sqrt price in Q64.96 format
+/// * `sqrt_ratio_b` - Upper sqrt price in Q64.96 format
+/// * `liquidity` - Liquidity amount
+/// * `round_up` - If true, round up (trader pays). If false, round down (pool pays).
+/// 
+/// # Returns
+/// * `Ok(U256)` - Amount0 delta
+/// * `Err(MathError)` - If calculation fails
+/// 
+/// # Protocol Rules
+/// - When computing trader input: round_up = true (overcharge trader)
+/// - When computing pool output: round_up = false (underpay trader)
+pub fn get_amount0_delta(
+    sqrt_ratio_a: U256,
+    sqrt_ratio_b: U256,
+    liquidity: u128,
+    round_up: bool,
+) -> Result<U256, MathError> {
+    if sqrt_ratio_a >= sqrt_ratio_b {
+        return Err(MathError::InvalidInput {
+            operation: "get_amount0_delta".to_string(),
+            reason: format!(
+                "sqrt_ratio_a ({}) must be less than sqrt_ratio_b ({})",
+                sqrt_ratio_a, sqrt_ratio_b
+            ),
+            context: "Uniswap V3 amount0 delta calculation".to_string(),
+        });
+    }
+
+    if liquidity == 0 {
+        return Ok(U256::zero());
+    }
+
+    let q96 = U256::from(1u128 << 96);
+    let liquidity_u256 = U256::from(liquidity);
+
+    // PROTOCOL PARITY: Match Solidity operation order exactly
+    // Solidity: FullMath.mulDiv(liquidity, sqrtBX96 - sqrtAX96, sqrtAX96) * Q96 / sqrtBX96
+    // Order: (L * (sqrtB - sqrtA) / sqrtA) * Q96 / sqrtB
+    
+    let sqrt_diff = sqrt_ratio_b
+        .checked_sub(sqrt_ratio_a)
+        .ok_or_else(|| MathError::Underflow {
+            operation: "get_amount0_delta".to_string(),
+            inputs: vec![sqrt_ratio_b, sqrt_ratio_a],
+            context: "sqrt price difference calculation".to_string(),
+        })?;
+
+    // Step 1: (L * (sqrtB - sqrtA)) / sqrtA
+    // Use mul_div for intermediate result
+    let intermediate = if round_up {
+        mul_div_rounding_up(liquidity_u256, sqrt_diff, sqrt_ratio_a)?
+    } else {
+        mul_div(liquidity_u256, sqrt_diff, sqrt_ratio_a)?
+    };
+
+    // Step 2: intermediate * Q96 / sqrtB
+    if round_up {
+        mul_div_rounding_up(intermediate, q96, sqrt_ratio_b)
+    } else {
+        mul_div(intermediate, q96, sqrt_ratio_b)
+    }
+}
+
+/// Calculate amount1 delta between two sqrt prices (Uniswap V3 SqrtPriceMath.getAmount1Delta)
+/// 
+/// Formula: amount1 = L * (sqrtB - sqrtA) / Q96
+/// 
+/// # Arguments
+/// * `sqrt_ratio_a` - Lower sqrt price in Q64.96 format
+/// * `sqrt_ratio_b` - Upper sqrt price in Q64.96 format
+/// * `liquidity` - Liquidity amount
+/// * `round_up` - If true, round up (trader pays). If false, round down (pool pays).
+/// 
+/// # Returns
+/// * `Ok(U256)` - Amount1 delta
+/// * `Err(MathError)` - If calculation fails
+/// 
+/// # Protocol Rules
+/// - When computing trader input: round_up = true (overcharge trader)
+/// - When computing pool output: round_up = false (underpay trader)
+pub fn get_amount1_delta(
+    sqrt_ratio_a: U256,
+    sqrt_ratio_b: U256,
+    liquidity: u128,
+    round_up: bool,
+) -> Result<U256, MathError> {
+    if sqrt_ratio_a >= sqrt_ratio_b {
+        return Err(MathError::InvalidInput {
+            operation: "get_amount1_delta".to_string(),
+            reason: format!(
+                "sqrt_ratio_a ({}) must be less than sqrt_ratio_b ({})",
+                sqrt_ratio_a, sqrt_ratio_b
+            ),
+            context: "Uniswap V3 amount1 delta calculation".to_string(),
+        });
+    }
+
+    if liquidity == 0 {
+        return Ok(U256::zero());
+    }
+
+    let q96 = U256::from(1u128 << 96);
+    let liquidity_u256 = U256::from(liquidity);
+
+    // Formula: amount1 = L * (sqrtB - sqrtA) / Q96
+    // Operation order matches Solidity: multiply first, then divide
+    let sqrt_diff = sqrt_ratio_b
+        .checked_sub(sqrt_ratio_a)
+        .ok_or_else(|| MathError::Underflow {
+            operation: "get_amount1_delta".to_string(),
+            inputs: vec![sqrt_ratio_b, sqrt_ratio_a],
+            context: "sqrt price difference calculation".to_string(),
+        })?;
+
+    // Numerator: L * (sqrtB - sqrtA)
+    let numerator = liquidity_u256
+        .checked_mul(sqrt_diff)
+        .ok_or_else(|| MathError::Overflow {
+            operation: "get_amount1_delta".to_string(),
+            inputs: vec![liquidity_u256, sqrt_diff],
+            context: "numerator calculation".to_string(),
+        })?;
+
+    // Apply rounding based on direction
+    if round_up {
+        mul_div_rounding_up(numerator, U256::from(1), q96)
+    } else {
+        mul_div(numerator, U256::from(1), q96)
+    }
+}
+
+/// Calculate V3 price impact in basis points
+///
+/// # Arguments
+/// * `amount_in` - Input amount
+/// * `liquidity` - Pool liquidity
+/// * `sqrt_price_x96` - Current sqrt price in Q64.96
+///
+/// # Returns
+/// * `Ok(u32)` - Price impact in basis points
+///
+/// # Examples
+/// ```
+/// use ethers::types::U256;
+/// use rust_sidecar::dex::uniswap_v3::math::calculate_v3_price_impact;
+///
+/// // 1 ETH into 1000 ETH of active liquidity = 10 bps
+/// let impact = calculate_v3_price_impact(
+///     U256::from(1_000_000_000_000_000_000u128),
+///     U256::from(1_000_000_000_000_000_000_000u128),
+///     U256::from(79228162514264337593543950336u128),
+/// )
+/// .unwrap();
+/// assert_eq!(impact, 10);
+/// ```
+pub fn calculate_v3_price_impact(
+    amount_in: U256,
+    liquidity: U256,
+    _sqrt_price_x96: U256,
+) -> Result<u32, MathError> {
+    if amount_in.is_zero() || liquidity.is_zero() {
+        return Ok(0);
+    }
+
+    // Simplified price impact calculation
+    // Real implementation would calculate exact tick movement
+    let impact_scaled =
+        amount_in
+            .checked_mul(U256::from(10000))
+            .ok_or_else(|| MathError::Overflow {
+                operation: "calculate_v3_price_impact".to_string(),
+                inputs: vec![amount_in, U256::from(10000)],
+                context: "".to_string(),
+            })?;
+
+    let impact = impact_scaled / liquidity;
+
+    Ok(if impact > U256::from(10000) {
+        10000
+    } else {
+        impact.as_u32()
+    })
+}
+
+/// Breakdown of a V3 swap's execution cost relative to the spot price
+#[derive(Debug, Clone, Copy)]
+pub struct V3ExecutionCost {
+    /// Total shortfall of the actual output vs. the spot-price output
+    pub total_cost_bps: BasisPoints,
+    /// Explicit pool fee component
+    pub fee_cost_bps: BasisPoints,
+    /// Market impact component (price moving against the trader)
+    pub slippage_cost_bps: BasisPoints,
+}
+
+/// Calculate the total execution cost of a V3 swap in basis points
+///
+/// Total cost = (ideal_output - actual_output) / ideal_output * 10000, where the
+/// ideal output is the input valued at the current spot price with no fee and no
+/// price movement. The fee component is the pool fee; the remainder is slippage.
+///
+/// # Arguments
+/// * `amount_in` - Input amount of the swap
+/// * `ideal_output` - Output at the current spot price (no fee, no impact)
+/// * `actual_output` - Output from `calculate_v3_amount_out`
+/// * `fee_bps` - Pool fee in basis points
+///
+/// # Returns
+/// * `V3ExecutionCost` - Total cost with fee and slippage components (capped at 10000)
+pub fn calculate_v3_total_execution_cost_bps(
+    amount_in: U256,
+    ideal_output: U256,
+    actual_output: U256,
+    fee_bps: BasisPoints,
+) -> V3ExecutionCost {
+    if amount_in.is_zero() || ideal_output.is_zero() {
+        return V3ExecutionCost {
+            total_cost_bps: BasisPoints::new_const(0),
+            fee_cost_bps: BasisPoints::new_const(0),
+            slippage_cost_bps: BasisPoints::new_const(0),
+        };
+    }
+
+    let shortfall = ideal_output.saturating_sub(actual_output);
+    let total_bps = BasisPoints::from_fee_amount(shortfall, ideal_output)
+        .map(|bps| bps.as_u32())
+        .unwrap_or(BasisPoints::MAX);
+
+    // The fee can never explain more than the observed total cost
+    let fee_component = fee_bps.as_u32().min(total_bps);
+    let slippage_component = total_bps - fee_component;
+
+    V3ExecutionCost {
+        total_cost_bps: BasisPoints::new_const(total_bps),
+        fee_cost_bps: BasisPoints::new_const(fee_component),
+        slippage_cost_bps: BasisPoints::new_const(slippage_component),
+    }
+}
+
+/// Split a V3 swap fee into the LP portion and the protocol portion
+///
+/// When governance enables the protocol fee switch, the pool keeps
+/// `fee / protocol_fee_fraction` for the protocol (UniswapV3Pool: `feeAmount / feeProtocol`)
+/// and only the remainder accrues to LPs. This lowers the LP reinvestment rate,
+/// which callers should account for when modelling JIT/LP fee income.
+///
+/// # Arguments
+/// * `amount_in` - Swap input amount
+/// * `fee_bps` - Pool fee in basis points
+/// * `protocol_fee_fraction` - Protocol fee denominator: 0 (disabled) or 4-10
+///
+/// # Returns
+/// * `Ok((lp_fee, protocol_fee))` - Fee split, where `lp_fee + protocol_fee` is the total fee
+/// * `Err(MathError)` - If `protocol_fee_fraction` is not 0 or 4-10, or on overflow
+pub fn calculate_v3_protocol_fee_taken(
+    amount_in: U256,
+    fee_bps: BasisPoints,
+    protocol_fee_fraction: u8,
+) -> Result<(U256, U256), MathError> {
+    if protocol_fee_fraction != 0 && !(4..=10).contains(&protocol_fee_fraction) {
+        return Err(MathError::InvalidInput {
+            operation: "calculate_v3_protocol_fee_taken".to_string(),
+            reason: "protocol_fee_fraction must be 0 (disabled) or between 4 and 10".to_string(),
+            context: format!("protocol_fee_fraction={}", protocol_fee_fraction),
+        });
+    }
+
+    let total_fee = amount_in
+        .checked_mul(U256::from(fee_bps.as_u32()))
+        .ok_or_else(|| MathError::Overflow {
+            operation: "calculate_v3_protocol_fee_taken".to_string(),
+            inputs: vec![amount_in, U256::from(fee_bps.as_u32())],
+            context: "total fee calculation".to_string(),
+        })?
+        / U256::from(10000);
+
+    if protocol_fee_fraction == 0 {
+        return Ok((total_fee, U256::zero()));
+    }
+
+    let protocol_fee = total_fee / U256::from(protocol_fee_fraction);
+    let lp_fee = total_fee - protocol_fee;
+
+    Ok((lp_fee, protocol_fee))
+}
+
+/// Convert sqrt price (Q64.96) to regular price
+pub fn sqrt_price_to_price(sqrt_price_x96: U256) -> Result<U256, MathError> {
+    // sqrt_price_x96 is in Q64.96 format
+    // Price = (sqrt_price_x96 / 2^96)^2 = sqrt_price_x96^2 / 2^192
+
+    // First, square the sqrt_price (this gives us price * 2^192)
+    let sqrt_squared =
+        sqrt_price_x96
+            .checked_mul(sqrt_price_x96)
+            .ok_or_else(|| MathError::Overflow {
+                operation: "sqrt_price_to_price".to_string(),
+                inputs: vec![sqrt_price_x96],
+                context: "Squaring sqrt_price".to_string(),
+            })?;
+
+    // Divide by 2^192 to get the actual price
+    // 2^192 = 2^64 * 2^64 * 2^64
+    let two_pow_64 = U256::from(1) << 64;
+    let two_pow_128 = two_pow_64.checked_mul(two_pow_64).unwrap();
+    let two_pow_192 = two_pow_128.checked_mul(two_pow_64).unwrap();
+
+    sqrt_squared
+        .checked_div(two_pow_192)
+        .ok_or_else(|| MathError::DivisionByZero {
+            operation: "sqrt_price_to_price".to_string(),
+            context: "Dividing by 2^192".to_string(),
+        })
+}
+
+/// Calculate sqrt_price_x96 from reserve amounts (inverse of price calculation)
+///
+/// For V3: sqrtPriceX96 = sqrt(reserve_out / reserve_in) * 2^96
+/// Reuses the battle-tested sqrt implementation from Curve math.
+///
+/// # Arguments
+/// * `reserve_in` - Reserve of token0 (input token)
+/// * `reserve_out` - Reserve of token1 (output token)
+///
+/// # Returns
+/// * `Ok(U256)` - Sqrt price in Q64.96 format
+/// * `Err(MathError)` - If calculation fails
+pub fn reserves_to_sqrt_price_x96(reserve_in: U256, reserve_out: U256) -> Result<U256, MathError> {
+    if reserve_in.is_zero() {
+        return Err(MathError::DivisionByZero {
+            operation: "reserves_to_sqrt_price_x96".to_string(),
+            context: "Reserve in cannot be zero".to_string(),
+        });
+    }
+
+    // Calculate price ratio: reserve_out / reserve_in
+    // Then multiply by 2^96 before taking square root for precision
+    let price_ratio = reserve_out
+        .checked_mul(U256::from(1u128) << 96)
+        .ok_or_else(|| MathError::Overflow {
+            operation: "reserves_to_sqrt_price_x96".to_string(),
+            inputs: vec![reserve_out],
+            context: "Price ratio calculation".to_string(),
+        })?
+        .checked_div(reserve_in)
+        .ok_or_else(|| MathError::DivisionByZero {
+            operation: "reserves_to_sqrt_price_x96".to_string(),
+            context: "Dividing by reserve_in".to_string(),
+        })?;
+
+    // Reuse battle-tested sqrt from Curve math module
+    crate::dex::curve::math::sqrt_u256(price_ratio)
+}
+
+/// Convert a human-readable token0 price into the `sqrtPriceX96` used to initialize a pool
+///
+/// 1. Decimal adjustment: raw price = price * 10^(token1_decimals - token0_decimals)
+/// 2. The raw price is split exactly into `m * 2^e` (53-bit mantissa, binary exponent)
+/// 3. sqrtPriceX96 = sqrt(m * 2^(e + 192)), computed as `sqrt(m << s) >> (s - e - 192) / 2`
+///    with `s` chosen so the Curve `sqrt_u256` argument keeps ~126 bits of precision,
+///    whatever the magnitude of the price
+/// 4. Clamped to `[MIN_SQRT_RATIO, MAX_SQRT_RATIO)`
+///
+/// # Arguments
+/// * `token0_price_in_token1` - Price of one whole token0 in whole token1
+/// * `token0_decimals` - token0 decimals
+/// * `token1_decimals` - token1 decimals
+///
+/// # Returns
+/// * `Ok(U256)` - Initial sqrt price in Q64.96 format
+/// * `Err(MathError)` - If the price is not a positive finite number
+pub fn price_to_initial_sqrt_price_x96(
+    token0_price_in_token1: f64,
+    token0_decimals: u8,
+    token1_decimals: u8,
+) -> Result<U256, MathError> {
+    if !token0_price_in_token1.is_finite() || token0_price_in_token1 <= 0.0 {
+        return Err(MathError::InvalidInput {
+            operation: "price_to_initial_sqrt_price_x96".to_string(),
+            reason: "Price must be a positive finite number".to_string(),
+            context: format!("price={}", token0_price_in_token1),
+        });
+    }
+
+    let min_sqrt_ratio = U256::from(MIN_SQRT_RATIO);
+    let max_sqrt_ratio = get_max_sqrt_ratio() - U256::one();
+
+    let decimal_shift = token1_decimals as i32 - token0_decimals as i32;
+    let raw_price = token0_price_in_token1 * 10f64.powi(decimal_shift);
+
+    // Beyond f64 range the price is far outside the V3 range in either direction
+    if raw_price.is_infinite() {
+        return Ok(max_sqrt_ratio);
+    }
+    if raw_price == 0.0 {
+        return Ok(min_sqrt_ratio);
+    }
+
+    // raw_price = mantissa * 2^exponent, exactly
+    let bits = raw_price.to_bits();
+    let biased_exponent = ((bits >> 52) & 0x7ff) as i32;
+    let fraction = bits & ((1u64 << 52) - 1);
+    let (mantissa, exponent) = if biased_exponent == 0 {
+        (fraction, -1074) // subnormal
+    } else {
+        (fraction | (1u64 << 52), biased_exponent - 1075)
+    };
+
+    // sqrt(m * 2^t) with t = e + 192 (the 2^96 factor squared). Shift m left by s bits
+    // (s of the same parity as t, m << s < 2^255) and undo with a half shift afterwards.
+    let t = exponent + 192;
+    let s = if t % 2 == 0 { 200 } else { 201 };
+    let half_shift = (s - t) / 2;
+    let root = crate::dex::curve::math::sqrt_u256(U256::from(mantissa) << s)?;
+
+    let sqrt_price_x96 = if half_shift >= 0 {
+        if half_shift >= 256 {
+            U256::zero()
+        } else {
+            root >> half_shift
+        }
+    } else {
+        let left = (-half_shift) as usize;
+        // root < 2^128, so anything shifted past 2^160 is above MAX_SQRT_RATIO anyway
+        if left >= 64 {
+            return Ok(max_sqrt_ratio);
+        }
+        root << left
+    };
+
+    Ok(sqrt_price_x96.max(min_sqrt_ratio).min(max_sqrt_ratio))
+}
+
+/// Tick spacing for a Uniswap V3 fee tier
+///
+/// # Arguments
+/// * `fee_bps` - Fee tier in basis points (1, 5, 30 or 100)
+///
+/// # Returns
+/// * `Some(i32)` - Tick spacing (1, 10, 60 or 200)
+/// * `None` - If the fee tier is not enabled on the factory
+pub fn tick_spacing_for_fee_bps(fee_bps: u32) -> Option<i32> {
+    match fee_bps {
+        1 => Some(1),
+        5 => Some(10),
+        30 => Some(60),
+        100 => Some(200),
+        _ => None,
+    }
+}
+
+/// Validate an initial sqrt price against a fee tier's usable tick range
+///
+/// Positions must start and end on multiples of the tick spacing, so the initial
+/// tick needs at least one full spacing of room below and above it within
+/// `[MIN_TICK, MAX_TICK]` for liquidity to be placed around the price.
+///
+/// # Arguments
+/// * `sqrt_price_x96` - Proposed initial sqrt price
+/// * `fee_bps` - Fee tier in basis points
+///
+/// # Returns
+/// * `Ok(())` - Price is usable for the fee tier
+/// * `Err(MathError)` - Unknown fee tier or price too close to the tick bounds
+pub fn validate_initial_price_for_fee_tier(
+    sqrt_price_x96: U256,
+    fee_bps: u32,
+) -> Result<(), MathError> {
+    let tick_spacing = tick_spacing_for_fee_bps(fee_bps).ok_or_else(|| MathError::InvalidInput {
+        operation: "validate_initial_price_for_fee_tier".to_string(),
+        reason: "Unsupported fee tier".to_string(),
+        context: format!("fee_bps={}", fee_bps),
+    })?;
+
+    if sqrt_price_x96 < U256::from(MIN_SQRT_RATIO) || sqrt_price_x96 >= get_max_sqrt_ratio() {
+        return Err(MathError::InvalidInput {
+            operation: "validate_initial_price_for_fee_tier".to_string(),
+            reason: "sqrt_price_x96 outside [MIN_SQRT_RATIO, MAX_SQRT_RATIO)".to_string(),
+            context: format!("sqrt_price_x96={}", sqrt_price_x96),
+        });
+    }
+
+    // Usable ticks are multiples of the spacing inside [MIN_TICK, MAX_TICK]
+    let min_usable_tick = (MIN_TICK / tick_spacing) * tick_spacing;
+    let max_usable_tick = (MAX_TICK / tick_spacing) * tick_spacing;
+    let tick = sqrt_price_to_tick(sqrt_price_x96)?;
+
+    if tick < min_usable_tick + tick_spacing || tick >= max_usable_tick - tick_spacing {
+        return Err(MathError::InvalidInput {
+            operation: "validate_initial_price_for_fee_tier".to_string(),
+            reason: "Initial price leaves no room for a position at this tick spacing".to_string(),
+            context: format!(
+                "tick={}, tick_spacing={}, usable=[{}, {}]",
+                tick, tick_spacing, min_usable_tick, max_usable_tick
+            ),
+        });
+    }
+
+    Ok(())
+}
+
+/// Snapshot of a Uniswap V3 / Kyber Elastic pool's active range
+#[derive(Debug, Clone)]
+pub struct V3PoolState {
+    /// Current sqrt price in Q64.96 format
+    pub sqrt_price_x96: U256,
+    /// Active liquidity in the current tick range
+    pub liquidity: u128,
+    /// Current tick
+    pub tick: i32,
+    /// Pool fee in basis points
+    pub fee_bps: BasisPoints,
+}
+
+impl PoolPricer for V3PoolState {
+    /// Token index 0 is token0 and index 1 is token1
+    fn spot_price(&self, token_in: usize, token_out: usize) -> Result<U256, MathError> {
+        if token_in > 1 || token_out > 1 || token_in == token_out {
+            return Err(MathError::InvalidInput {
+                operation: "V3PoolState::spot_price".to_string(),
+                reason: "V3 pools only support token indices 0 and 1".to_string(),
+                context: format!("token_in={}, token_out={}", token_in, token_out),
+            });
+        }
+
+        let q96 = U256::from(1u128 << 96);
+        let scale = U256::from(10).pow(U256::from(18));
+
+        // price_x96 = sqrt_price^2 / 2^96 (price of token0 in token1, Q96)
+        let price_x96 = mul_div(self.sqrt_price_x96, self.sqrt_price_x96, q96)?;
+
+        if token_in == 0 {
+            mul_div(price_x96, scale, q96)
+        } else {
+            // Inverse price: 2^96 * 10^18 / price_x96
+            mul_div(q96, scale, price_x96)
+        }
+    }
+
+    fn sqrt_price_x96(&self) -> Option<U256> {
+        Some(self.sqrt_price_x96)
+    }
+}
+
+impl PoolQuoter for V3PoolState {
+    /// Quotes within the active range only (no tick crossing)
+    fn quote_amount_out(
+        &self,
+        amount_in: U256,
+        token_in: usize,
+        token_out: usize,
+    ) -> Result<U256, ProtocolMathError> {
+        self.amount_out(amount_in, token_in, token_out)
+            .map_err(|e| e.with_protocol(DexProtocol::UniswapV3))
+    }
+}
+
+impl V3PoolState {
+    /// Output of an exact-input swap within the active range (no tick crossing)
+    pub fn amount_out(
+        &self,
+        amount_in: U256,
+        token_in: usize,
+        token_out: usize,
+    ) -> Result<U256, MathError> {
+        let direction = match (token_in, token_out) {
+            (0, 1) => SwapDirection::Token0ToToken1,
+            (1, 0) => SwapDirection::Token1ToToken0,
+            _ => {
+                return Err(MathError::InvalidInput {
+                    operation: "V3PoolState::amount_out".to_string(),
+                    reason: "V3 pools only support token indices 0 and 1".to_string(),
+                    context: format!("token_in={}, token_out={}", token_in, token_out),
+                })
+            }
+        };
+
+        calculate_v3_amount_out(
+            amount_in,
+            self.sqrt_price_x96,
+            self.liquidity,
+            self.fee_bps,
+            direction,
+        )
+    }
+
+    /// Pool state after an exact-input swap within the active range, and the output
+    pub fn after_swap(
+        &self,
+        amount_in: U256,
+        token_in: usize,
+        token_out: usize,
+    ) -> Result<(V3PoolState, U256), MathError> {
+        let amount_out = self.amount_out(amount_in, token_in, token_out)?;
+        let direction = if token_in == 0 {
+            SwapDirection::Token0ToToken1
+        } else {
+            SwapDirection::Token1ToToken0
+        };
+        let (sqrt_price_x96, tick) = calculate_v3_post_frontrun_state(
+            amount_in,
+            self.sqrt_price_x96,
+            self.liquidity,
+            self.tick,
+            self.fee_bps,
+            direction,
+        )?;
+
+        let next = V3PoolState {
+            sqrt_price_x96,
+            tick,
+            ..self.clone()
+        };
+        debug_assert!(
+            verify_v3_swap_invariant(self, &next, amount_in, amount_out, self.fee_bps),
+            "V3 virtual xy=k violated: amount_in={}, amount_out={}, sqrt_before={}, sqrt_after={}",
+            amount_in,
+            amount_out,
+            self.sqrt_price_x96,
+            next.sqrt_price_x96
+        );
+        Ok((next, amount_out))
+    }
+}
+
+/// Check that a simulated in-range V3 swap preserves the virtual `x * y = k`
+///
+/// Within one tick range V3 behaves like V2 on virtual reserves
+/// `x = L / sqrtP` and `y = L * sqrtP`. For a swap of `amount_in` (after fee)
+/// that pays `amount_out`, `(x + in) * (y - out)` must stay within 1 bps of
+/// `x * y` (token1 in: `(x - out) * (y + in)`). The direction is taken from the
+/// price move. Intended as a debug-build check on `amount_out` math: simulated
+/// swaps assert it with `debug_assert!`, which compiles out of release builds.
+///
+/// # Arguments
+/// * `state_before` - Pool state before the swap
+/// * `state_after` - Pool state after the swap
+/// * `amount_in` - Swap input (before fee)
+/// * `amount_out` - Simulated swap output
+/// * `fee_bps` - Pool fee in basis points
+///
+/// # Returns
+/// * `bool` - `true` if the invariant holds within 1 bps
+pub fn verify_v3_swap_invariant(
+    state_before: &V3PoolState,
+    state_after: &V3PoolState,
+    amount_in: U256,
+    amount_out: U256,
+    fee_bps: BasisPoints,
+) -> bool {
+    if state_before.sqrt_price_x96.is_zero() || state_before.liquidity == 0 {
+        return false;
+    }
+
+    let q96 = U512::from(1u128 << 96);
+    let sqrt_price = U512::from(state_before.sqrt_price_x96);
+    let liquidity = U512::from(state_before.liquidity);
+    let x = liquidity * q96 / sqrt_price;
+    let y = liquidity * sqrt_price / q96;
+
+    let fee_multiplier = U512::from(10000u32.saturating_sub(fee_bps.as_u32()));
+    let amount_in_after_fee = U512::from(amount_in) * fee_multiplier / U512::from(10000);
+    let amount_out = U512::from(amount_out);
+
+    let zero_for_one = state_after.sqrt_price_x96 <= state_before.sqrt_price_x96;
+    let (reserve_in, reserve_out) = if zero_for_one { (x, y) } else { (y, x) };
+    if amount_out > reserve_out {
+        return false;
+    }
+
+    let k_before = x * y;
+    let k_after = (reserve_in + amount_in_after_fee) * (reserve_out - amount_out);
+    let deviation = if k_after > k_before {
+        k_after - k_before
+    } else {
+        k_before - k_after
+    };
+
+    deviation * U512::from(10000) <= k_before
+}
+
+impl PoolSimulator for V3PoolState {
+    fn simulate_swap(
+        &self,
+        amount_in: U256,
+        token_in: usize,
+        token_out: usize,
+    ) -> Result<(Box<dyn PoolSimulator + Send + Sync>, U256), ProtocolMathError> {
+        let (next, amount_out) = self
+            .after_swap(amount_in, token_in, token_out)
+            .map_err(|e| e.with_protocol(DexProtocol::UniswapV3))?;
+        Ok((Box::new(next), amount_out))
+    }
+}
+
+/// Calculate a pool's spot price regardless of the underlying DEX
+///
+/// # Arguments
+/// * `pool` - Any pool implementing `PoolPricer`
+/// * `token_in` - Index of the token being priced
+/// * `token_out` - Index of the quote token
+///
+/// # Returns
+/// * `Ok(U256)` - Spot price (18-decimal fixed-point)
+/// * `Err(MathError)` - If the indices are invalid or the calculation fails
+pub fn calculate_pool_price_from_reserves(
+    pool: &dyn PoolPricer,
+    token_in: usize,
+    token_out: usize,
+) -> Result<U256, MathError> {
+    pool.spot_price(token_in, token_out)
+}
+
+/// Calculate a liquidity-weighted token0 price across multiple pools
+///
+/// Each pool's spot price (token0 priced in token1, via `PoolPricer`) contributes
+/// with weight `liquidity / total_liquidity`, so a single thin or manipulated pool
+/// cannot dominate the price used for profitability checks.
+///
+/// # Arguments
+/// * `pools` - `(pool, liquidity)` pairs; liquidity is the pool's weight
+///
+/// # Returns
+/// * `Ok(U256)` - Weighted price (18-decimal fixed-point)
+/// * `Err(MathError)` - If no pool has liquidity, a pool can't be priced, or on overflow
+pub fn calculate_liquidity_weighted_price(
+    pools: &[(&dyn PoolPricer, u128)],
+) -> Result<U256, MathError> {
+    let mut weighted_sum = U256::zero();
+    let mut total_liquidity = U256::zero();
+
+    for (idx, (pool, liquidity)) in pools.iter().enumerate() {
+        if *liquidity == 0 {
+            continue;
+        }
+
+        let price = pool.spot_price(0, 1)?;
+        let weight = U256::from(*liquidity);
+        let contribution = price.checked_mul(weight).ok_or_else(|| MathError::Overflow {
+            operation: "calculate_liquidity_weighted_price".to_string(),
+            inputs: vec![price, weight],
+            context: format!("Weighted price for pool {}", idx),
+        })?;
+
+        weighted_sum = weighted_sum
+            .checked_add(contribution)
+            .ok_or_else(|| MathError::Overflow {
+                operation: "calculate_liquidity_weighted_price".to_string(),
+                inputs: vec![weighted_sum, contribution],
+                context: format!("Accumulating pool {}", idx),
+            })?;
+        total_liquidity = total_liquidity.saturating_add(weight);
+    }
+
+    if total_liquidity.is_zero() {
+        return Err(MathError::InvalidInput {
+            operation: "calculate_liquidity_weighted_price".to_string(),
+            reason: "No pool with non-zero liquidity".to_string(),
+            context: format!("pools={}", pools.len()),
+        });
+    }
+
+    Ok(weighted_sum / total_liquidity)
+}
+
+/// V3 sandwich profit calculation
+pub fn calculate_v3_sandwich_profit(
+    frontrun_amount: U256,
+    victim_amount: U256,
+    sqrt_price_x96: U256,
+    liquidity: u128,
+    tick: i32,
+    fee_bps: BasisPoints,
+    aave_fee_bps: BasisPoints,
+) -> Result<U256, MathError> {
+    // Using Token0ToToken1 as default direction; see calculate_v3_sandwich_profit_symmetric
+    v3_sandwich_profit_in_direction(
+        frontrun_amount,
+        victim_amount,
+        sqrt_price_x96,
+        liquidity,
+        tick,
+        fee_bps,
+        aave_fee_bps,
+        SwapDirection::Token0ToToken1,
+        SwapDirection::Token0ToToken1,
+    )
+}
+
+/// Reverse of a swap direction (the backrun leg of a sandwich)
+fn opposite_direction(direction: SwapDirection) -> SwapDirection {
+    match direction {
+        SwapDirection::Token0ToToken1 => SwapDirection::Token1ToToken0,
+        SwapDirection::Token1ToToken0 => SwapDirection::Token0ToToken1,
+    }
+}
+
+/// V3 sandwich profit with explicit frontrun and victim directions
+///
+/// Profit is denominated in the frontrun input token:
+/// backrun sells the frontrun output in the opposite direction after the victim.
+#[allow(clippy::too_many_arguments)]
+fn v3_sandwich_profit_in_direction(
+    frontrun_amount: U256,
+    victim_amount: U256,
+    sqrt_price_x96: U256,
+    liquidity: u128,
+    tick: i32,
+    fee_bps: BasisPoints,
+    aave_fee_bps: BasisPoints,
+    frontrun_direction: SwapDirection,
+    victim_direction: SwapDirection,
+) -> Result<U256, MathError> {
+    // Calculate reserves after frontrun
+    let (sqrt_price_post_frontrun, _) = calculate_v3_post_frontrun_state(
+        frontrun_amount,
+        sqrt_price_x96,
+        liquidity,
+        tick,
+        fee_bps,
+        frontrun_direction,
+    )?;
+
+    // Calculate reserves after victim
+    let (sqrt_price_post_victim, _) = calculate_v3_post_victim_state(
+        victim_amount,
+        sqrt_price_post_frontrun,
+        liquidity,
+        tick,
+        fee_bps,
+        victim_direction,
+    )?;
+
+    v3_backrun_profit(
+        frontrun_amount,
+        sqrt_price_x96,
+        sqrt_price_post_victim,
+        liquidity,
+        fee_bps,
+        aave_fee_bps,
+        frontrun_direction,
+    )
+}
+
+/// Sandwich profit once the victim has moved the price to `sqrt_price_post_victim`
+///
+/// Prices the frontrun leg at `sqrt_price_x96`, sells its output back at the
+/// post-victim price, and subtracts the frontrun input and flash loan premium.
+fn v3_backrun_profit(
+    frontrun_amount: U256,
+    sqrt_price_x96: U256,
+    sqrt_price_post_victim: U256,
+    liquidity: u128,
+    fee_bps: BasisPoints,
+    aave_fee_bps: BasisPoints,
+    frontrun_direction: SwapDirection,
+) -> Result<U256, MathError> {
+    // Backrun: sell the tokens received in the frontrun back into the frontrun input token
+    // CRITICAL: The backrun input is frontrun_output, not frontrun_amount; the typed
+    // legs make the frontrun output the only value that type-checks as backrun input
+    let backrun_output = match frontrun_direction {
+        SwapDirection::Token0ToToken1 => {
+            let frontrun_amount = Token0Amount::from(frontrun_amount);
+            let backrun_input =
+                calculate_v3_token1_out(frontrun_amount, sqrt_price_x96, liquidity, fee_bps)?;
+            if backrun_input.inner().is_zero() {
+                return Ok(U256::zero());
+            }
+            calculate_v3_token0_out(backrun_input, sqrt_price_post_victim, liquidity, fee_bps)?
+                .inner()
+        }
+        SwapDirection::Token1ToToken0 => {
+            let frontrun_amount = Token1Amount::from(frontrun_amount);
+            let backrun_input =
+                calculate_v3_token0_out(frontrun_amount, sqrt_price_x96, liquidity, fee_bps)?;
+            if backrun_input.inner().is_zero() {
+                return Ok(U256::zero());
+            }
+            calculate_v3_token1_out(backrun_input, sqrt_price_post_victim, liquidity, fee_bps)?
+                .inner()
+        }
+    };
+
+    // Calculate flash loan cost
+    let flash_loan_cost = frontrun_amount
+        .checked_mul(U256::from(aave_fee_bps.as_u32()))
+        .and_then(|v| v.checked_div(U256::from(10000)))
+        .ok_or_else(|| MathError::Overflow {
+            operation: "calculate_v3_sandwich_profit".to_string(),
+            inputs: vec![frontrun_amount],
+            context: "Flash loan cost".to_string(),
+        })?;
+
+    // Profit = backrun_output - frontrun_amount - flash_loan_cost
+    // For optimization purposes, return 0 if profit is negative (no error)
+    // This allows Brent's method to explore the profit landscape
+    let total_cost = frontrun_amount
+        .checked_add(flash_loan_cost)
+        .unwrap_or(U256::MAX);
+
+    if backrun_output >= total_cost {
+        Ok(backrun_output - total_cost)
+    } else {
+        // Negative profit returns 0 instead of error for optimization compatibility
+        Ok(U256::zero())
+    }
+}
+
+/// Golden section search for the best frontrun size in a given direction
+///
+/// Returns `(frontrun_amount, profit)`, or the first simulation error hit
+/// while probing.
+fn optimize_v3_sandwich_in_direction(
+    victim_amount: U256,
+    pool_state: &V3PoolState,
+    aave_fee_bps: BasisPoints,
+    frontrun_direction: SwapDirection,
+    victim_direction: SwapDirection,
+) -> Result<(U256, U256), MathError> {
+    golden_section_maximize(U256::zero(), victim_amount, DEFAULT_ITERATIONS, |amount| {
+        if amount.is_zero() {
+            return Ok(U256::zero());
+        }
+        v3_sandwich_profit_in_direction(
+            amount,
+            victim_amount,
+            pool_state.sqrt_price_x96,
+            pool_state.liquidity,
+            pool_state.tick,
+            pool_state.fee_bps,
+            aave_fee_bps,
+            frontrun_direction,
+            victim_direction,
+        )
+    })
+}
+
+/// Calculate the best V3 sandwich profit across both frontrun directions
+///
+/// Evaluates a frontrun in the victim's direction and in the opposite direction
+/// (each at its own optimal size) and returns the more profitable one. Only a
+/// frontrun in the victim's direction is pushed further by the victim's swap, so
+/// it should always win; this is checked with a debug assertion.
+///
+/// # Arguments
+/// * `victim_direction` - Direction of the victim's swap
+/// * `victim_amount` - Victim swap input amount
+/// * `pool_state` - Pool state before the frontrun
+/// * `aave_fee_bps` - Flash loan fee in basis points
+///
+/// # Returns
+/// * `Ok((U256, SwapDirection))` - Best profit (in the frontrun input token) and frontrun direction
+/// * `Err(MathError)` - If `victim_amount` is zero or a frontrun simulation fails
+pub fn calculate_v3_sandwich_profit_symmetric(
+    victim_direction: SwapDirection,
+    victim_amount: U256,
+    pool_state: &V3PoolState,
+    aave_fee_bps: BasisPoints,
+) -> Result<(U256, SwapDirection), MathError> {
+    if victim_amount.is_zero() {
+        return Err(MathError::InvalidInput {
+            operation: "calculate_v3_sandwich_profit_symmetric".to_string(),
+            reason: "victim_amount cannot be zero".to_string(),
+            context: format!("victim_direction={:?}", victim_direction),
+        });
+    }
+
+    let reverse_direction = opposite_direction(victim_direction);
+    let (_, same_profit) = optimize_v3_sandwich_in_direction(
+        victim_amount,
+        pool_state,
+        aave_fee_bps,
+        victim_direction,
+        victim_direction,
+    )?;
+    let (_, reverse_profit) = optimize_v3_sandwich_in_direction(
+        victim_amount,
+        pool_state,
+        aave_fee_bps,
+        reverse_direction,
+        victim_direction,
+    )?;
+
+    debug_assert!(
+        same_profit >= reverse_profit,
+        "Frontrunning against the victim beat frontrunning with it (same={}, reverse={})",
+        same_profit,
+        reverse_profit
+    );
+
+    if reverse_profit > same_profit {
+        tracing::warn!(
+            "V3 sandwich: reverse-direction frontrun more profitable (same={}, reverse={}, victim_direction={:?})",
+            same_profit,
+            reverse_profit,
+            victim_direction
+        );
+        Ok((reverse_profit, reverse_direction))
+    } else {
+        Ok((same_profit, victim_direction))
+    }
+}
+
+/// Validated V3 pool snapshot with its current tick range precomputed
+///
+/// Batch evaluation of many victims against one pool validates the snapshot up
+/// front, so an invalid pool is rejected before any optimizer iterations run.
+/// The snapshot also caches the bounds of the tick-spacing range holding the
+/// price and the inputs that move the price to either bound: the largest swaps
+/// the single-range sandwich model prices exactly.
+///
+/// Only `precompute_v3_state` constructs this type, so holding one proves the
+/// snapshot was validated.
+#[derive(Debug, Clone)]
+pub struct PrecomputedV3SandwichState {
+    pool_state: V3PoolState,
+    sqrt_price_lower: U256,
+    sqrt_price_upper: U256,
+    amount0_to_lower: U256,
+    amount1_to_upper: U256,
+}
+
+impl PrecomputedV3SandwichState {
+    /// Validated pool snapshot
+    pub fn pool_state(&self) -> &V3PoolState {
+        &self.pool_state
+    }
+
+    /// Sqrt prices (Q64.96) at the lower and upper bound of the current tick-spacing range
+    pub fn tick_range_sqrt_prices(&self) -> (U256, U256) {
+        (self.sqrt_price_lower, self.sqrt_price_upper)
+    }
+
+    /// Input, including fee, that moves the price to the current range's bound
+    ///
+    /// Token0 in moves the price down to the lower bound; token1 in moves it up
+    /// to the upper bound.
+    pub fn amount_to_tick_boundary(&self, direction: SwapDirection) -> U256 {
+        match direction {
+            SwapDirection::Token0ToToken1 => self.amount0_to_lower,
+            SwapDirection::Token1ToToken0 => self.amount1_to_upper,
+        }
+    }
+}
+
+/// Validate a V3 pool snapshot once for batch sandwich evaluation
+///
+/// The tick range is derived from the sqrt price using the fee tier's tick
+/// spacing (spacing 1 for tiers the factory does not enable, since liquidity
+/// is then only known to be constant within a single tick).
+///
+/// # Arguments
+/// * `pool_state` - Pool snapshot
+///
+/// # Returns
+/// * `Ok(PrecomputedV3SandwichState)` - Validated snapshot and cached tick range
+/// * `Err(MathError)` - If the sqrt price is out of range, liquidity is zero or fee >= 100%
+pub fn precompute_v3_state(
+    pool_state: V3PoolState,
+) -> Result<PrecomputedV3SandwichState, MathError> {
+    let sqrt_price = pool_state.sqrt_price_x96;
+    if sqrt_price < U256::from(MIN_SQRT_RATIO) || sqrt_price >= get_max_sqrt_ratio() {
+        return Err(MathError::InvalidInput {
+            operation: "precompute_v3_state".to_string(),
+            reason: format!("sqrt_price_x96 out of valid range: {}", sqrt_price),
+            context: format!("tick={}, liquidity={}", pool_state.tick, pool_state.liquidity),
+        });
+    }
+
+    if pool_state.liquidity == 0 || pool_state.fee_bps.as_u32() >= 10000 {
+        return Err(MathError::InvalidInput {
+            operation: "precompute_v3_state".to_string(),
+            reason: "Liquidity must be non-zero and fee below 10000 bps".to_string(),
+            context: format!(
+                "liquidity={}, fee_bps={}",
+                pool_state.liquidity,
+                pool_state.fee_bps.as_u32()
+            ),
+        });
+    }
+
+    let tick_spacing = tick_spacing_for_fee_bps(pool_state.fee_bps.as_u32()).unwrap_or(1);
+    let current_tick = sqrt_price_to_tick(sqrt_price)?;
+    let tick_lower = (current_tick.div_euclid(tick_spacing) * tick_spacing).max(MIN_TICK);
+    let tick_upper = (tick_lower + tick_spacing).min(MAX_TICK);
+    let sqrt_price_lower = get_sqrt_ratio_at_tick(tick_lower)?;
+    let sqrt_price_upper = get_sqrt_ratio_at_tick(tick_upper)?;
+
+    let amount0_to_lower = amount_in_to_reach_price(
+        sqrt_price,
+        sqrt_price_lower,
+        pool_state.liquidity,
+        pool_state.fee_bps,
+        SwapDirection::Token0ToToken1,
+    )?;
+    let amount1_to_upper = amount_in_to_reach_price(
+        sqrt_price,
+        sqrt_price_upper,
+        pool_state.liquidity,
+        pool_state.fee_bps,
+        SwapDirection::Token1ToToken0,
+    )?;
+
+    Ok(PrecomputedV3SandwichState {
+        pool_state,
+        sqrt_price_lower,
+        sqrt_price_upper,
+        amount0_to_lower,
+        amount1_to_upper,
+    })
+}
+
+/// Sandwich profit against a validated snapshot
+///
+/// The snapshot was checked by `precompute_v3_state`, so the legs step the
+/// sqrt price directly: no per-leg revalidation and no tick conversions, which
+/// the uncached path pays for on every optimizer probe only to discard.
+fn v3_sandwich_profit_validated(
+    frontrun_amount: U256,
+    victim_amount: U256,
+    direction: SwapDirection,
+    precomputed: &PrecomputedV3SandwichState,
+    aave_fee_bps: BasisPoints,
+) -> Result<U256, MathError> {
+    if frontrun_amount.is_zero() {
+        return Ok(U256::zero());
+    }
+
+    let pool = &precomputed.pool_state;
+    let sqrt_price_post_frontrun = next_sqrt_price_from_input(
+        frontrun_amount,
+        pool.sqrt_price_x96,
+        pool.liquidity,
+        pool.fee_bps,
+        direction,
+        "calculate_v3_sandwich_profit_precomputed",
+    )?;
+    let sqrt_price_post_victim = next_sqrt_price_from_input(
+        victim_amount,
+        sqrt_price_post_frontrun,
+        pool.liquidity,
+        pool.fee_bps,
+        direction,
+        "calculate_v3_sandwich_profit_precomputed",
+    )?;
+
+    v3_backrun_profit(
+        frontrun_amount,
+        pool.sqrt_price_x96,
+        sqrt_price_post_victim,
+        pool.liquidity,
+        pool.fee_bps,
+        aave_fee_bps,
+        direction,
+    )
+}
+
+/// Calculate V3 sandwich profit from a validated pool snapshot
+///
+/// Same result as `calculate_v3_sandwich_profit` for a frontrun in the victim's
+/// direction, without revalidating the pool or converting prices to ticks.
+/// While both legs fit within `amount_to_tick_boundary`, the snapshot's
+/// liquidity is exact; larger legs are still priced against it, and are logged
+/// as extrapolated.
+///
+/// # Arguments
+/// * `frontrun_amount` - Frontrun input amount
+/// * `victim_amount` - Victim swap input amount
+/// * `victim_direction` - Direction of the victim's (and the frontrun's) swap
+/// * `precomputed` - Validated pool snapshot
+/// * `aave_fee_bps` - Flash loan fee in basis points
+///
+/// # Returns
+/// * `Ok(U256)` - Profit in the frontrun input token (0 if unprofitable or no frontrun)
+/// * `Err(MathError)` - If the simulation fails
+pub fn calculate_v3_sandwich_profit_precomputed(
+    frontrun_amount: U256,
+    victim_amount: U256,
+    victim_direction: SwapDirection,
+    precomputed: &PrecomputedV3SandwichState,
+    aave_fee_bps: BasisPoints,
+) -> Result<U256, MathError> {
+    let pool = &precomputed.pool_state;
+    let in_range_capacity = precomputed.amount_to_tick_boundary(victim_direction);
+    if frontrun_amount.saturating_add(victim_amount) > in_range_capacity {
+        tracing::debug!(
+            "V3 sandwich legs ({} + {}) leave the current tick range (capacity {}); \
+             extrapolating liquidity {}",
+            frontrun_amount,
+            victim_amount,
+            in_range_capacity,
+            pool.liquidity
+        );
+    }
+
+    v3_sandwich_profit_validated(
+        frontrun_amount,
+        victim_amount,
+        victim_direction,
+        precomputed,
+        aave_fee_bps,
+    )
+}
+
+/// Optimal frontrun for one victim against a validated pool snapshot
+///
+/// # Arguments
+/// * `victim_amount` - Victim swap input amount
+/// * `victim_direction` - Direction of the victim's (and the frontrun's) swap
+/// * `precomputed` - Validated pool snapshot
+/// * `aave_fee_bps` - Flash loan fee in basis points
+///
+/// # Returns
+/// * `Ok((U256, U256))` - Optimal frontrun amount and its profit
+/// * `Err(MathError)` - If `victim_amount` is zero or a probe fails
+pub fn optimize_v3_sandwich_precomputed(
+    victim_amount: U256,
+    victim_direction: SwapDirection,
+    precomputed: &PrecomputedV3SandwichState,
+    aave_fee_bps: BasisPoints,
+) -> Result<(U256, U256), MathError> {
+    if victim_amount.is_zero() {
+        return Err(MathError::InvalidInput {
+            operation: "optimize_v3_sandwich_precomputed".to_string(),
+            reason: "victim_amount cannot be zero".to_string(),
+            context: format!("victim_direction={:?}", victim_direction),
+        });
+    }
+
+    golden_section_maximize(U256::zero(), victim_amount, DEFAULT_ITERATIONS, |amount| {
+        v3_sandwich_profit_validated(
+            amount,
+            victim_amount,
+            victim_direction,
+            precomputed,
+            aave_fee_bps,
+        )
+    })
+}
+
+/// Fixed seed for `expected_sandwich_profit_with_volatility` so estimates are reproducible
+const VOLATILITY_SAMPLE_SEED: u64 = 0x5EED_CAFE_F00D_D00D;
+
+/// Scale of the sampled sqrt price multiplier (1e9 = 1.0)
+const SQRT_PRICE_FACTOR_SCALE: u64 = 1_000_000_000;
+
+/// SplitMix64 step; enough randomness for price sampling without a `rand` dependency
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// Standard normal draw via Box-Muller
+fn sample_standard_normal(state: &mut u64) -> f64 {
+    // 53-bit uniforms; u1 in (0, 1] keeps ln finite
+    let u1 = ((splitmix64(state) >> 11) + 1) as f64 / (1u64 << 53) as f64;
+    let u2 = (splitmix64(state) >> 11) as f64 / (1u64 << 53) as f64;
+    (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+}
+
+/// Expected sandwich profit when the price moves before the bundle lands
+///
+/// The frontrun is sized at the current price, as it would be when the bundle
+/// is built. Each of `n_samples` draws moves the price by a normal return with
+/// standard deviation `volatility_bps_per_block` (floored at -99.99%), and the
+/// sandwich is re-simulated with that frontrun. The result is the mean profit.
+/// Draws are seeded deterministically, so equal inputs give equal estimates.
+///
+/// # Arguments
+/// * `pool_state` - Pool snapshot
+/// * `victim_amount` - Victim swap input amount
+/// * `victim_direction` - Direction of the victim's (and the frontrun's) swap
+/// * `volatility_bps_per_block` - Std dev of the price change over one block
+/// * `n_samples` - Number of Monte Carlo draws
+///
+/// # Returns
+/// * `Ok(U256)` - Mean profit in the frontrun input token (0 if unprofitable at the
+///   current price)
+/// * `Err(MathError)` - If `n_samples` or `victim_amount` is zero, or the pool is invalid
+pub fn expected_sandwich_profit_with_volatility(
+    pool_state: &V3PoolState,
+    victim_amount: U256,
+    victim_direction: SwapDirection,
+    volatility_bps_per_block: u32,
+    n_samples: usize,
+) -> Result<U256, MathError> {
+    if n_samples == 0 {
+        return Err(MathError::InvalidInput {
+            operation: "expected_sandwich_profit_with_volatility".to_string(),
+            reason: "n_samples cannot be zero".to_string(),
+            context: format!(
+                "victim_direction={:?}, volatility_bps_per_block={}",
+                victim_direction, volatility_bps_per_block
+            ),
+        });
+    }
+
+    let no_flash_fee = BasisPoints::new_const(0);
+    let precomputed = precompute_v3_state(pool_state.clone())?;
+    let (frontrun_amount, profit_at_current) = optimize_v3_sandwich_precomputed(
+        victim_amount,
+        victim_direction,
+        &precomputed,
+        no_flash_fee,
+    )?;
+    if frontrun_amount.is_zero() || volatility_bps_per_block == 0 {
+        return Ok(profit_at_current);
+    }
+
+    let volatility = f64::from(volatility_bps_per_block) / 10000.0;
+    let min_sqrt_price = U256::from(MIN_SQRT_RATIO);
+    let max_sqrt_price = get_max_sqrt_ratio() - U256::one();
+    let mut rng_state = VOLATILITY_SAMPLE_SEED;
+    let mut total_profit = U256::zero();
+
+    for _ in 0..n_samples {
+        let price_ratio = (1.0 + volatility * sample_standard_normal(&mut rng_state)).max(1e-4);
+        let factor = (price_ratio.sqrt() * SQRT_PRICE_FACTOR_SCALE as f64) as u64;
+
+        // sqrt price < 2^160 and factor < 2^64, so the product fits in U256
+        let sqrt_price_x96 = (pool_state.sqrt_price_x96 * U256::from(factor)
+            / U256::from(SQRT_PRICE_FACTOR_SCALE))
+        .clamp(min_sqrt_price, max_sqrt_price);
+
+        let sampled = precompute_v3_state(V3PoolState {
+            sqrt_price_x96,
+            tick: sqrt_price_to_tick(sqrt_price_x96)?,
+            ..pool_state.clone()
+        })?;
+        let profit = calculate_v3_sandwich_profit_precomputed(
+            frontrun_amount,
+            victim_amount,
+            victim_direction,
+            &sampled,
+            no_flash_fee,
+        )?;
+        total_profit = total_profit.saturating_add(profit);
+    }
+
+    tracing::debug!(
+        "Expected sandwich profit over {} samples at {} bps/block: {} (deterministic {})",
+        n_samples,
+        volatility_bps_per_block,
+        total_profit / U256::from(n_samples),
+        profit_at_current
+    );
+
+    Ok(total_profit / U256::from(n_samples))
+}
+
+/// Estimate sandwich profit on every fee tier pool of a pair
+///
+/// The victim's own pool is the natural target, but another tier (0.01%,
+/// 0.05%, 0.3%, 1%) may have deeper liquidity around the current price or a
+/// lower fee on our two legs. Each pool is simulated as if the victim's swap
+/// executed there, at its own optimal frontrun size. A pool where the
+/// optimal frontrun would push the victim below `min_amount_out` scores zero,
+/// since the bundle would revert.
+///
+/// # Arguments
+/// * `victim_params` - Victim swap parameters
+/// * `pools` - `(fee tier, pool state)` for each pool of the pair; the tier fee is used for simulation
+/// * `flash_loan_fee_bps` - Premium of the flash loan funding the frontrun, e.g. the
+///   provider picked by `flash_loan::select_cheapest_flash_loan`
+///
+/// # Returns
+/// * `Ok(Vec<(BasisPoints, U256)>)` - Estimated profit per fee tier, most profitable first
+/// * `Err(MathError)` - If the victim amount is zero or a pool simulation fails
+pub fn sweep_fee_tiers(
+    victim_params: &V3SwapParams,
+    pools: &[(BasisPoints, V3PoolState)],
+    flash_loan_fee_bps: BasisPoints,
+) -> Result<Vec<(BasisPoints, U256)>, MathError> {
+    if victim_params.amount_in.is_zero() {
+        return Err(MathError::InvalidInput {
+            operation: "sweep_fee_tiers".to_string(),
+            reason: "victim amount_in cannot be zero".to_string(),
+            context: format!("pools={}", pools.len()),
+        });
+    }
+
+    let direction = victim_params.direction;
+
+    let mut results: Vec<(BasisPoints, U256)> = pools
+        .iter()
+        .map(|(fee_tier, pool_state)| {
+            let pool_state = V3PoolState {
+                fee_bps: *fee_tier,
+                ..pool_state.clone()
+            };
+            let (frontrun_amount, profit) = optimize_v3_sandwich_in_direction(
+                victim_params.amount_in,
+                &pool_state,
+                flash_loan_fee_bps,
+                direction,
+                direction,
+            )?;
+            if profit.is_zero() {
+                return Ok((*fee_tier, profit));
+            }
+
+            // Victim output after our frontrun must still clear their slippage check
+            let victim_output = calculate_v3_post_frontrun_state(
+                frontrun_amount,
+                pool_state.sqrt_price_x96,
+                pool_state.liquidity,
+                pool_state.tick,
+                pool_state.fee_bps,
+                direction,
+            )
+            .and_then(|(sqrt_price_after, _)| {
+                calculate_v3_amount_out(
+                    victim_params.amount_in,
+                    sqrt_price_after,
+                    pool_state.liquidity,
+                    pool_state.fee_bps,
+                    direction,
+                )
+            })?;
+
+            if victim_output < victim_params.min_amount_out {
+                tracing::debug!(
+                    "Fee tier {} bps: optimal frontrun {} breaks victim slippage",
+                    fee_tier.as_u32(),
+                    frontrun_amount
+                );
+                return Ok((*fee_tier, U256::zero()));
+            }
+            Ok((*fee_tier, profit))
+        })
+        .collect::<Result<_, MathError>>()?;
+
+    results.sort_by(|a, b| b.1.cmp(&a.1));
+    Ok(results)
+}
+
+/// Calculate V3 swap output using correct Uniswap V3 SwapMath formulas
+/// Implements exact formulas from SwapMath.sol for both swap directions
+///
+/// # Arguments
+/// * `amount_in` - Input amount (after fee will be calculated)
+/// * `sqrt_price_x96` - Current sqrt price in Q64.96 format
+/// * `liquidity` - Active liquidity in the current tick range
+/// * `fee_bps` - Fee in basis points (e.g., 300 for 0.3%)
+/// * `direction` - Swap direction (Token0ToToken1 or Token1ToToken0)
+///
+/// # Returns
+/// * `Ok(U256)` - Output amount
+/// * `Err(MathError)` - If calculation fails or inputs invalid
+///
+/// # Examples
+/// ```
+/// use ethers::types::U256;
+/// use rust_sidecar::core::BasisPoints;
+/// use rust_sidecar::dex::adapter::SwapDirection;
+/// use rust_sidecar::dex::uniswap_v3::math::calculate_v3_amount_out;
+///
+/// // Swap 1 token0 into a 30 bps pool at price 1.0 with 1000 tokens of liquidity
+/// let amount_in = U256::from(1_000_000_000_000_000_000u128);
+/// let amount_out = calculate_v3_amount_out(
+///     amount_in,
+///     U256::from(79228162514264337593543950336u128),
+///     1_000_000_000_000_000_000_000u128,
+///     BasisPoints::new_const(30),
+///     SwapDirection::Token0ToToken1,
+/// )
+/// .unwrap();
+///
+/// // Fee and price impact reduce the output below the input
+/// assert!(amount_out < amount_in);
+/// assert!(amount_out > amount_in * U256::from(99) / U256::from(100));
+/// ```
+pub fn calculate_v3_amount_out(
+    amount_in: U256,
+    sqrt_price_x96: U256,
+    liquidity: u128,
+    fee_bps: BasisPoints,
+    direction: SwapDirection,
+) -> Result<U256, MathError> {
+    // Input validation
+    if amount_in.is_zero() {
+        return Err(MathError::InvalidInput {
+            operation: "calculate_v3_amount_out".to_string(),
+            reason: "amount_in cannot be zero".to_string(),
+            context: format!(
+                "direction={:?}, sqrt_price={}, liquidity={}",
+                direction, sqrt_price_x96, liquidity
+            ),
+        });
+    }
+
+    if sqrt_price_x96.is_zero() || sqrt_price_x96 < U256::from(MIN_SQRT_RATIO) {
+        return Err(MathError::InvalidInput {
+            operation: "calculate_v3_amount_out".to_string(),
+            reason: format!("sqrt_price_x96 out of valid range: {}", sqrt_price_x96),
+            context: format!(
+                "direction={:?}, amount_in={}, liquidity={}",
+                direction, amount_in, liquidity
+            ),
+        });
+    }
+
+    let liquidity_u256 = U256::from(liquidity);
+    if liquidity_u256.is_zero() {
+        return Err(MathError::InvalidInput {
+            operation: "calculate_v3_amount_out".to_string(),
+            reason: "Liquidity cannot be zero".to_string(),
+            context: format!(
+                "direction={:?}, amount_in={}, sqrt_price={}",
+                direction, amount_in, sqrt_price_x96
+            ),
+        });
+    }
+
+    // Apply fee: amount_in_after_fee = amount_in * (10000 - fee_bps) / 10000
+    let fee_multiplier = U256::from(10000 - fee_bps.as_u32());
+    let amount_in_after_fee = amount_in
+        .checked_mul(fee_multiplier)
+        .and_then(|v| v.checked_div(U256::from(10000)))
+        .ok_or_else(|| MathError::Overflow {
+            operation: "calculate_v3_amount_out".to_string(),
+            inputs: vec![amount_in, U256::from(fee_bps.as_u32())],
+            context: format!(
+                "Fee calculation failed (direction={:?}, amount_in={})",
+                direction, amount_in
+            ),
+        })?;
+
+    if amount_in_after_fee.is_zero() {
+        return Ok(U256::zero());
+    }
+
+    let q96 = U256::from(1u128 << 96);
+
+    // Implement correct V3 SwapMath formulas based on direction
+    match direction {
+        SwapDirection::Token0ToToken1 => {
+            // zeroForOne: Swapping token0 for token1
+            // Formula from SwapMath.getNextSqrtPriceFromAmount0RoundingUp
+            // numerator = L * Q96
+            // product = amount_in_after_fee * sqrtPrice
+            // denominator = numerator + product
+            // new_sqrtPrice = (numerator * sqrtPrice) / denominator = (L * Q96 * sqrtPrice) / (L * Q96 + amount_in_after_fee * sqrtPrice)
+
+            let numerator = liquidity_u256
+                .checked_mul(q96)
+                .ok_or_else(|| MathError::Overflow {
+                    operation: "calculate_v3_amount_out".to_string(),
+                    inputs: vec![liquidity_u256, q96],
+                    context: format!(
+                        "zeroForOne numerator calculation (direction={:?}, liquidity={})",
+                        direction, liquidity
+                    ),
+                })?;
+
+            let product = amount_in_after_fee
+                .checked_mul(sqrt_price_x96)
+                .ok_or_else(|| MathError::Overflow {
+                    operation: "calculate_v3_amount_out".to_string(),
+                    inputs: vec![amount_in_after_fee, sqrt_price_x96],
+                    context: format!("zeroForOne product calculation (direction={:?})", direction),
+                })?;
+
+            let denominator = numerator
+                .checked_add(product)
+                .ok_or_else(|| MathError::Overflow {
+                    operation: "calculate_v3_amount_out".to_string(),
+                    inputs: vec![numerator, product],
+                    context: format!("zeroForOne denominator calculation (direction={:?}, amount_in={}, sqrt_price={}, liquidity={})", direction, amount_in, sqrt_price_x96, liquidity),
+                })?;
+
+            // new_sqrtPrice = (numerator * sqrtPrice) / denominator
+            let new_sqrt_price = mul_div(numerator, sqrt_price_x96, denominator)?;
+
+            // Calculate amount_out using getAmount1Delta formula
+            // PROTOCOL PARITY: Use explicit get_amount1_delta with round_down (pool pays)
+            // amount_out = L * (sqrtPrice - new_sqrtPrice) / Q96
+            if new_sqrt_price >= sqrt_price_x96 {
+                return Err(MathError::InvalidInput {
+                    operation: "calculate_v3_amount_out".to_string(),
+                    reason: "New sqrt price must be less than current for zeroForOne swap".to_string(),
+                    context: format!("direction={:?}, sqrt_price={}, new_sqrt_price={}, amount_in={}, liquidity={}", direction, sqrt_price_x96, new_sqrt_price, amount_in, liquidity),
+                });
+            }
+
+            // Use explicit get_amount1_delta with round_down (pool output, favor pool)
+            let amount_out = get_amount1_delta(
+                new_sqrt_price,
+                sqrt_price_x96,
+                liquidity,
+                false, // round_down: pool pays, underpay trader
+            )?;
+            Ok(amount_out)
+        }
+        SwapDirection::Token1ToToken0 => {
+            // oneForZero: Swapping token1 for token0
+            // Formula from SwapMath.getNextSqrtPriceFromInput (oneForZero case)
+            // new_sqrtPrice = sqrtPrice + (amount_in_after_fee * Q96) / L
+
+            let sqrt_price_delta = mul_div(amount_in_after_fee, q96, liquidity_u256)?;
+            let new_sqrt_price = sqrt_price_x96
+                .checked_add(sqrt_price_delta)
+        .ok_or_else(|| MathError::Overflow {
+            operation: "calculate_v3_amount_out".to_string(),
+            inputs: vec![sqrt_price_x96, sqrt_price_delta],
+                    context: format!("oneForZero new sqrt price calculation (direction={:?}, amount_in={}, liquidity={})", direction, amount_in, liquidity),
+                })?;
+
+            // Calculate amount_out using getAmount0Delta formula
+            // PROTOCOL PARITY: Use explicit get_amount0_delta with round_down (pool pays)
+            // amount_out = L * Q96 * (new_sqrtPrice - sqrtPrice) / (sqrtPrice * new_sqrtPrice)
+            let sqrt_price_diff =
+                new_sqrt_price
+                    .checked_sub(sqrt_price_x96)
+                    .ok_or_else(|| MathError::Underflow {
+                        operation: "calculate_v3_amount_out".to_string(),
+                        inputs: vec![new_sqrt_price, sqrt_price_x96],
+                        context: format!(
+                            "oneForZero sqrt price difference (direction={:?})",
+                            direction
+                        ),
+                    })?;
+
+            // Use explicit get_amount0_delta with round_down (pool output, favor pool)
+            let amount_out = get_amount0_delta(
+                sqrt_price_x96,
+                new_sqrt_price,
+                liquidity,
+                false, // round_down: pool pays, underpay trader
+            )?;
+            Ok(amount_out)
+        }
+    }
+}
+
+/// Calculate the exact input needed to move a V3 pool to a target sqrt price
+///
+/// Inverts the single-range swap math, then grosses up for the fee:
+/// - Token0ToToken1: `amount_in = L * (1/sqrt_target - 1/sqrt_current) / (1 - fee)`
+/// - Token1ToToken0: `amount_in = L * (sqrt_target - sqrt_current) / Q96 / (1 - fee)`
+///
+/// Both steps round up, so swapping the returned amount reaches at least the target.
+/// Liquidity is assumed constant between the two prices.
+///
+/// # Arguments
+/// * `sqrt_price_current` - Current sqrt price (Q64.96)
+/// * `sqrt_price_target` - Sqrt price the swap should end at (Q64.96)
+/// * `liquidity` - Active liquidity
+/// * `fee_bps` - Pool fee in basis points
+/// * `direction` - Swap direction (token0 in lowers the price, token1 in raises it)
+///
+/// # Returns
+/// * `Ok(U256)` - Input amount including fee (0 if the target is the current price)
+/// * `Err(MathError)` - If the target lies on the wrong side for `direction`, the fee is
+///   100% or more, or the calculation overflows
+pub fn amount_in_to_reach_price(
+    sqrt_price_current: U256,
+    sqrt_price_target: U256,
+    liquidity: u128,
+    fee_bps: BasisPoints,
+    direction: SwapDirection,
+) -> Result<U256, MathError> {
+    if fee_bps.as_u32() >= 10000 {
+        return Err(MathError::InvalidInput {
+            operation: "amount_in_to_reach_price".to_string(),
+            reason: "Fee must be below 10000 bps".to_string(),
+            context: format!("fee_bps={}", fee_bps.as_u32()),
+        });
+    }
+    if sqrt_price_target == sqrt_price_current {
+        return Ok(U256::zero());
+    }
+
+    let amount_in_after_fee = match direction {
+        SwapDirection::Token0ToToken1 if sqrt_price_target < sqrt_price_current => {
+            get_amount0_delta(sqrt_price_target, sqrt_price_current, liquidity, true)?
+        }
+        SwapDirection::Token1ToToken0 if sqrt_price_target > sqrt_price_current => {
+            get_amount1_delta(sqrt_price_current, sqrt_price_target, liquidity, true)?
+        }
+        _ => {
+            return Err(MathError::InvalidInput {
+                operation: "amount_in_to_reach_price".to_string(),
+                reason: "Target price is not reachable in this swap direction".to_string(),
+                context: format!(
+                    "direction={:?}, sqrt_price_current={}, sqrt_price_target={}",
+                    direction, sqrt_price_current, sqrt_price_target
+                ),
+            })
+        }
+    };
+
+    mul_div_rounding_up(
+        amount_in_after_fee,
+        U256::from(10000),
+        U256::from(10000 - fee_bps.as_u32()),
+    )
+}
+
+/// Amount of a V3 pool's token0
+///
+/// Typed amounts make the swap direction part of the signature, so token0 and
+/// token1 amounts cannot be passed in each other's place.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct Token0Amount(U256);
+
+/// Amount of a V3 pool's token1
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct Token1Amount(U256);
+
+impl From<U256> for Token0Amount {
+    fn from(amount: U256) -> Self {
+        Token0Amount(amount)
+    }
+}
+
+impl From<U256> for Token1Amount {
+    fn from(amount: U256) -> Self {
+        Token1Amount(amount)
+    }
+}
+
+impl Token0Amount {
+    /// Raw token0 amount
+    pub fn inner(self) -> U256 {
+        self.0
+    }
+}
+
+impl Token1Amount {
+    /// Raw token1 amount
+    pub fn inner(self) -> U256 {
+        self.0
+    }
+}
+
+/// Calculate the token1 output of a token0 -> token1 swap (typed)
+///
+/// Same math as `calculate_v3_amount_out` with `Token0ToToken1`; the direction
+/// follows from the argument type.
+///
+/// # Arguments
+/// * `amount_in` - Exact token0 input
+/// * `sqrt_price_x96` - Current sqrt price in Q64.96 format
+/// * `liquidity` - Active liquidity in the current tick range
+/// * `fee_bps` - Fee in basis points
+///
+/// # Returns
+/// * `Ok(Token1Amount)` - Token1 output
+/// * `Err(MathError)` - If calculation fails or inputs invalid
+pub fn calculate_v3_token1_out(
+    amount_in: Token0Amount,
+    sqrt_price_x96: U256,
+    liquidity: u128,
+    fee_bps: BasisPoints,
+) -> Result<Token1Amount, MathError> {
+    calculate_v3_amount_out(
+        amount_in.inner(),
+        sqrt_price_x96,
+        liquidity,
+        fee_bps,
+        SwapDirection::Token0ToToken1,
+    )
+    .map(Token1Amount::from)
+}
+
+/// Calculate the token0 output of a token1 -> token0 swap (typed)
+///
+/// Same math as `calculate_v3_amount_out` with `Token1ToToken0`; the direction
+/// follows from the argument type.
+///
+/// # Arguments
+/// * `amount_in` - Exact token1 input
+/// * `sqrt_price_x96` - Current sqrt price in Q64.96 format
+/// * `liquidity` - Active liquidity in the current tick range
+/// * `fee_bps` - Fee in basis points
+///
+/// # Returns
+/// * `Ok(Token0Amount)` - Token0 output
+/// * `Err(MathError)` - If calculation fails or inputs invalid
+pub fn calculate_v3_token0_out(
+    amount_in: Token1Amount,
+    sqrt_price_x96: U256,
+    liquidity: u128,
+    fee_bps: BasisPoints,
+) -> Result<Token0Amount, MathError> {
+    calculate_v3_amount_out(
+        amount_in.inner(),
+        sqrt_price_x96,
+        liquidity,
+        fee_bps,
+        SwapDirection::Token1ToToken0,
+    )
+    .map(Token0Amount::from)
+}
+
+/// Amount left after a fee-on-transfer token deducts `transfer_fee_bps`
+///
+/// The token's fee is rounded down, so the recipient gets the remainder.
+fn amount_after_transfer_fee(
+    amount: U256,
+    transfer_fee_bps: BasisPoints,
+) -> Result<U256, MathError> {
+    if transfer_fee_bps.as_u32() > BasisPoints::MAX {
+        return Err(MathError::InvalidInput {
+            operation: "amount_after_transfer_fee".to_string(),
+            reason: "Transfer fee exceeds 10000 bps".to_string(),
+            context: format!("transfer_fee_bps={}", transfer_fee_bps.as_u32()),
+        });
+    }
+
+    let fee_bps = U256::from(transfer_fee_bps.as_u32());
+    let fee = mul_div(amount, fee_bps, U256::from(BasisPoints::MAX))?;
+    Ok(amount - fee)
+}
+
+/// Calculate V3 swap output when the input token charges a transfer fee
+///
+/// Fee-on-transfer tokens (e.g. STA, PERI) deliver less than `amount_in` to the
+/// pool, so the swap runs on `amount_in * (1 - transfer_fee)`.
+///
+/// # Arguments
+/// * `amount_in` - Amount the trader sends
+/// * `transfer_fee_bps` - Input token's transfer fee in basis points
+/// * `sqrt_price_x96` - Current sqrt price in Q64.96 format
+/// * `liquidity` - Active liquidity in the current tick range
+/// * `swap_fee_bps` - Pool fee in basis points
+/// * `direction` - Swap direction
+///
+/// # Returns
+/// * `Ok(U256)` - Output amount sent by the pool
+/// * `Err(MathError)` - If the transfer fee exceeds 100% or the swap fails
+pub fn calculate_v3_amount_out_with_transfer_fee(
+    amount_in: U256,
+    transfer_fee_bps: BasisPoints,
+    sqrt_price_x96: U256,
+    liquidity: u128,
+    swap_fee_bps: BasisPoints,
+    direction: SwapDirection,
+) -> Result<U256, MathError> {
+    calculate_v3_amount_out_with_transfer_fees(
+        amount_in,
+        transfer_fee_bps,
+        BasisPoints::new_const(0),
+        sqrt_price_x96,
+        liquidity,
+        swap_fee_bps,
+        direction,
+    )
+}
+
+/// Calculate V3 swap output when either token charges a transfer fee
+///
+/// The input fee reduces what the pool receives; the output fee reduces what
+/// the recipient receives from the pool.
+///
+/// # Arguments
+/// * `amount_in` - Amount the trader sends
+/// * `input_transfer_fee_bps` - Input token's transfer fee in basis points
+/// * `output_transfer_fee_bps` - Output token's transfer fee in basis points
+/// * `sqrt_price_x96` - Current sqrt price in Q64.96 format
+/// * `liquidity` - Active liquidity in the current tick range
+/// * `swap_fee_bps` - Pool fee in basis points
+/// * `direction` - Swap direction
+///
+/// # Returns
+/// * `Ok(U256)` - Output amount credited to the recipient
+/// * `Err(MathError)` - If a transfer fee exceeds 100% or the swap fails
+pub fn calculate_v3_amount_out_with_transfer_fees(
+    amount_in: U256,
+    input_transfer_fee_bps: BasisPoints,
+    output_transfer_fee_bps: BasisPoints,
+    sqrt_price_x96: U256,
+    liquidity: u128,
+    swap_fee_bps: BasisPoints,
+    direction: SwapDirection,
+) -> Result<U256, MathError> {
+    let amount_received_by_pool = amount_after_transfer_fee(amount_in, input_transfer_fee_bps)?;
+    if amount_received_by_pool.is_zero() {
+        return Ok(U256::zero());
+    }
+
+    let amount_out = calculate_v3_amount_out(
+        amount_received_by_pool,
+        sqrt_price_x96,
+        liquidity,
+        swap_fee_bps,
+        direction,
+    )?;
+    amount_after_transfer_fee(amount_out, output_transfer_fee_bps)
+}
+
+/// Sqrt price after swapping `amount_in` (fee included) within one liquidity range
+///
+/// Shared by `calculate_v3_post_frontrun_state` and the precomputed sandwich
+/// path; callers validate the price and liquidity.
+fn next_sqrt_price_from_input(
+    amount_in: U256,
+    sqrt_price_x96: U256,
+    liquidity: u128,
+    fee_bps: BasisPoints,
+    direction: SwapDirection,
+    operation: &str,
+) -> Result<U256, MathError> {
+    let liquidity_u256 = U256::from(liquidity);
+
+    // Apply fee: amount_in_after_fee = amount_in * (10000 - fee_bps) / 10000
+    let fee_multiplier = U256::from(10000 - fee_bps.as_u32());
+    let amount_in_after_fee = amount_in
+        .checked_mul(fee_multiplier)
+        .and_then(|v| v.checked_div(U256::from(10000)))
+        .ok_or_else(|| MathError::Overflow {
+            operation: operation.to_string(),
+            inputs: vec![amount_in, U256::from(fee_bps.as_u32())],
+            context: format!(
+                "Fee calculation failed (direction={:?}, amount_in={})",
+                direction, amount_in
+            ),
+        })?;
+
+    if amount_in_after_fee.is_zero() {
+        // If amount after fee is zero, price doesn't change
+        return Ok(sqrt_price_x96);
+    }
+
+    let q96 = U256::from(1u128 << 96);
+
+    // Calculate new sqrt price using EXACT same formulas as calculate_v3_amount_out
+    let new_sqrt_price = match direction {
+        SwapDirection::Token0ToToken1 => {
+            // zeroForOne: Swapping token0 for token1
+            // Formula: new_sqrtPrice = (L * Q96 * sqrtPrice) / (L * Q96 + amount_in_after_fee * sqrtPrice)
+
+            let numerator = liquidity_u256
+                .checked_mul(q96)
+                .ok_or_else(|| MathError::Overflow {
+                    operation: operation.to_string(),
+                    inputs: vec![liquidity_u256, q96],
+                    context: format!(
+                        "zeroForOne numerator calculation (direction={:?}, liquidity={})",
+                        direction, liquidity
+                    ),
+                })?;
+
+            let product = amount_in_after_fee
+                .checked_mul(sqrt_price_x96)
+                .ok_or_else(|| MathError::Overflow {
+                    operation: operation.to_string(),
+                    inputs: vec![amount_in_after_fee, sqrt_price_x96],
+                    context: format!("zeroForOne product calculation (direction={:?})", direction),
+                })?;
+
+            let denominator = numerator
+                .checked_add(product)
+                .ok_or_else(|| MathError::Overflow {
+                    operation: operation.to_string(),
+                    inputs: vec![numerator, product],
+                    context: format!(
+                        "zeroForOne denominator calculation \
+                         (direction={:?}, amount_in={}, sqrt_price={}, liquidity={})",
+                        direction, amount_in, sqrt_price_x96, liquidity
+                    ),
+                })?;
+
+            // new_sqrtPrice = (numerator * sqrtPrice) / denominator
+            mul_div(numerator, sqrt_price_x96, denominator)?
+        }
+        SwapDirection::Token1ToToken0 => {
+            // oneForZero: Swapping token1 for token0
+            // Formula: new_sqrtPrice = sqrtPrice + (amount_in_after_fee * Q96) / L
+
+            let sqrt_price_delta = mul_div(amount_in_after_fee, q96, liquidity_u256)?;
+            sqrt_price_x96
+                .checked_add(sqrt_price_delta)
+                .ok_or_else(|| MathError::Overflow {
+                    operation: operation.to_string(),
+                    inputs: vec![sqrt_price_x96, sqrt_price_delta],
+                    context: format!(
+                        "oneForZero new sqrt price calculation \
+                         (direction={:?}, amount_in={}, liquidity={})",
+                        direction, amount_in, liquidity
+                    ),
+                })?
+        }
+    };
+
+    Ok(new_sqrt_price)
+}
+
+/// Calculate V3 pool state after a frontrun swap
+/// Uses correct V3 sqrt price calculation formulas matching calculate_v3_amount_out
+///
+/// # Arguments
+/// * `frontrun_amount` - Amount of input token for the frontrun swap
+/// * `sqrt_price_x96` - Current sqrt price in Q64.96 format
+/// * `liquidity` - Active liquidity in the current tick range
+/// * `tick` - Current tick (will be recalculated from new sqrt price)
+/// * `fee_bps` - Fee in basis points (e.g., 300 for 0.3%)
+/// * `direction` - Swap direction (Token0ToToken1 or Token1ToToken0)
+///
+/// # Returns
+/// * `Ok((U256, i32))` - New sqrt price and new tick after the swap
+/// * `Err(MathError)` - If calculation fails or inputs invalid
+pub fn calculate_v3_post_frontrun_state(
+    frontrun_amount: U256,
+    sqrt_price_x96: U256,
+    liquidity: u128,
+    tick: i32,
+    fee_bps: BasisPoints,
+    direction: SwapDirection,
+) -> Result<(U256, i32), MathError> {
+    // Input validation
+    if frontrun_amount.is_zero() {
+        return Err(MathError::InvalidInput {
+            operation: "calculate_v3_post_frontrun_state".to_string(),
+            reason: "frontrun_amount cannot be zero".to_string(),
+            context: format!(
+                "direction={:?}, sqrt_price={}, liquidity={}",
+                direction, sqrt_price_x96, liquidity
+            ),
+        });
+    }
+
+    if sqrt_price_x96.is_zero() || sqrt_price_x96 < U256::from(MIN_SQRT_RATIO) {
+        return Err(MathError::InvalidInput {
+            operation: "calculate_v3_post_frontrun_state".to_string(),
+            reason: format!("sqrt_price_x96 out of valid range: {}", sqrt_price_x96),
+            context: format!(
+                "direction={:?}, frontrun_amount={}, liquidity={}",
+                direction, frontrun_amount, liquidity
+            ),
+        });
+    }
+
+    let liquidity_u256 = U256::from(liquidity);
+    if liquidity_u256.is_zero() {
+        return Err(MathError::InvalidInput {
+            operation: "calculate_v3_post_frontrun_state".to_string(),
+            reason: "Liquidity cannot be zero".to_string(),
+            context: format!(
+                "direction={:?}, frontrun_amount={}, sqrt_price={}",
+                direction, frontrun_amount, sqrt_price_x96
+            ),
+        });
+    }
+
+    let new_sqrt_price = next_sqrt_price_from_input(
+        frontrun_amount,
+        sqrt_price_x96,
+        liquidity,
+        fee_bps,
+        direction,
+        "calculate_v3_post_frontrun_state",
+    )?;
+    if new_sqrt_price == sqrt_price_x96 {
+        // Input too small to move the price after the fee
+        return Ok((sqrt_price_x96, tick));
+    }
+
+    // Calculate tick delta using logarithmic formula
+    let ratio = calculate_price_ratio_fast(new_sqrt_price, sqrt_price_x96)?;
+    let tick_delta = calculate_tick_delta_from_ratio(ratio)?;
+    let new_tick = tick
+        .checked_add(tick_delta)
+        .ok_or_else(|| MathError::Overflow {
+            operation: "calculate_v3_post_frontrun_state".to_string(),
+            inputs: vec![U256::from(tick as u128), U256::from(tick_delta as u128)],
+            context: format!(
+                "Tick delta addition: old_tick={}, tick_delta={}",
+                tick, tick_delta
+            ),
+        })?;
+    let new_tick = new_tick.max(MIN_TICK).min(MAX_TICK);
+
+    Ok((new_sqrt_price, new_tick))
+}
+
+/// Round a frontrun amount so the swap ends exactly on a tick boundary
+///
+/// Ending the frontrun on an initialized-tick boundary leaves the victim
+/// starting from a clean tick (no partially consumed range) and lets the
+/// bundle drop the `sqrtPriceLimitX96` check. The post-frontrun tick is
+/// bracketed by spacing-aligned boundaries; the boundary whose required input
+/// (`amount_in_to_reach_price`) is closest to `frontrun_amount` wins. Liquidity
+/// is assumed constant across the move.
+///
+/// # Arguments
+/// * `frontrun_amount` - Unrounded frontrun input amount
+/// * `pool_state` - Pool state before the frontrun
+/// * `tick_spacing` - Pool tick spacing
+/// * `direction` - Frontrun swap direction
+///
+/// # Returns
+/// * `Ok(U256)` - Input amount (including fee) that moves the price to the chosen boundary
+/// * `Err(MathError)` - If `tick_spacing` is not positive, the fee is 100% or more,
+///   the frontrun cannot be simulated, or no boundary lies in the swap direction
+pub fn round_frontrun_amount_to_tick_boundary(
+    frontrun_amount: U256,
+    pool_state: &V3PoolState,
+    tick_spacing: i32,
+    direction: SwapDirection,
+) -> Result<U256, MathError> {
+    if tick_spacing <= 0 {
+        return Err(MathError::InvalidInput {
+            operation: "round_frontrun_amount_to_tick_boundary".to_string(),
+            reason: "tick_spacing must be positive".to_string(),
+            context: format!("tick_spacing={}", tick_spacing),
+        });
+    }
+
+    let (_, new_tick) = calculate_v3_post_frontrun_state(
+        frontrun_amount,
+        pool_state.sqrt_price_x96,
+        pool_state.liquidity,
+        pool_state.tick,
+        pool_state.fee_bps,
+        direction,
+    )?;
+
+    // Boundaries around the post-frontrun tick, plus one further in the swap
+    // direction in case the frontrun doesn't leave the current spacing interval
+    let lower = new_tick.div_euclid(tick_spacing) * tick_spacing;
+    let upper = lower + tick_spacing;
+    let candidates = match direction {
+        SwapDirection::Token0ToToken1 => [lower - tick_spacing, lower, upper],
+        SwapDirection::Token1ToToken0 => [lower, upper, upper + tick_spacing],
+    };
+    let min_usable_tick = (MIN_TICK / tick_spacing) * tick_spacing;
+    let max_usable_tick = (MAX_TICK / tick_spacing) * tick_spacing;
+
+    let mut best: Option<(U256, U256)> = None; // (distance, amount)
+
+    for boundary in candidates {
+        if boundary < min_usable_tick || boundary > max_usable_tick {
+            continue;
+        }
+        let boundary_sqrt = get_sqrt_ratio_at_tick(boundary)?;
+        let is_ahead = match direction {
+            SwapDirection::Token0ToToken1 => boundary_sqrt < pool_state.sqrt_price_x96,
+            SwapDirection::Token1ToToken0 => boundary_sqrt > pool_state.sqrt_price_x96,
+        };
+        if !is_ahead {
+            continue;
+        }
+
+        let amount = amount_in_to_reach_price(
+            pool_state.sqrt_price_x96,
+            boundary_sqrt,
+            pool_state.liquidity,
+            pool_state.fee_bps,
+            direction,
+        )?;
+
+        let distance = amount.abs_diff(frontrun_amount);
+        let is_closer = match best {
+            Some((best_distance, _)) => distance < best_distance,
+            None => true,
+        };
+        if is_closer {
+            best = Some((distance, amount));
+        }
+    }
+
+    best.map(|(_, amount)| amount)
+        .ok_or_else(|| MathError::InvalidInput {
+            operation: "round_frontrun_amount_to_tick_boundary".to_string(),
+            reason: "No usable tick boundary in the swap direction".to_string(),
+            context: format!(
+                "direction={:?}, tick={}, new_tick={}, tick_spacing={}",
+                direction, pool_state.tick, new_tick, tick_spacing
+            ),
+        })
+}
+
+/// Calculate V3 pool state after a victim swap
+/// Uses same logic as calculate_v3_post_frontrun_state
+///
+/// # Arguments
+/// * `victim_amount` - Amount of input token for the victim swap
+/// * `sqrt_price_x96` - Current sqrt price in Q64.96 format
+/// * `liquidity` - Active liquidity in the current tick range
+/// * `tick` - Current tick (will be recalculated from new sqrt price)
+/// * `fee_bps` - Fee in basis points (e.g., 300 for 0.3%)
+/// * `direction` - Swap direction (Token0ToToken1 or Token1ToToken0)
+///
+/// # Returns
+/// * `Ok((U256, i32))` - New sqrt price and new tick after the swap
+/// * `Err(MathError)` - If calculation fails or inputs invalid
+pub fn calculate_v3_post_victim_state(
+    victim_amount: U256,
+    sqrt_price_x96: U256,
+    liquidity: u128,
+    tick: i32,
+    fee_bps: BasisPoints,
+    direction: SwapDirection,
+) -> Result<(U256, i32), MathError> {
+    calculate_v3_post_frontrun_state(
+        victim_amount,
+        sqrt_price_x96,
+        liquidity,
+        tick,
+        fee_bps,
+        direction,
+    )
+}
+
+pub fn simulate_victim_execution(
+    victim_amount: U256,
+    sqrt_price_x96: U256,
+    liquidity: u128,
+    tick: i32,
+    fee_bps: BasisPoints,
+    direction: SwapDirection,
+) -> Result<(U256, i32), MathError> {
+    calculate_v3_post_victim_state(
+        victim_amount,
+        sqrt_price_x96,
+        liquidity,
+        tick,
+        fee_bps,
+        direction,
+    )
+}
+
+/// Brent's Method for V3 sandwich optimization
+///
+/// Searches `[0.001 ETH, victim_amount]` for the frontrun size that maximizes
+/// `calculate_v3_sandwich_profit`.
+///
+/// # Examples
+/// ```
+/// use ethers::types::U256;
+/// use rust_sidecar::core::BasisPoints;
+/// use rust_sidecar::dex::uniswap_v3::math::brents_method_v3_sandwich_optimization;
+///
+/// // 10 ETH victim in a 30 bps pool at tick 0 with 1000 ETH of liquidity
+/// let victim_amount = U256::from(10_000_000_000_000_000_000u128);
+/// let optimal = brents_method_v3_sandwich_optimization(
+///     victim_amount,
+///     U256::from(79228162514264337593543950336u128),
+///     1_000_000_000_000_000_000_000u128,
+///     0,
+///     BasisPoints::new_const(30),
+///     BasisPoints::new_const(5),
+/// )
+/// .unwrap();
+///
+/// assert!(optimal >= U256::from(1_000_000_000_000_000u128));
+/// assert!(optimal <= victim_amount);
+/// ```
+pub fn brents_method_v3_sandwich_optimization(
+    victim_amount: U256,
+    sqrt_price_x96: U256,
+    liquidity: u128,
+    tick: i32,
+    fee_bps: BasisPoints,
+    aave_fee_bps: BasisPoints,
+) -> Result<U256, MathError> {
+    brents_method_v3_sandwich_optimization_detailed(
+        victim_amount,
+        sqrt_price_x96,
+        liquidity,
+        tick,
+        fee_bps,
+        aave_fee_bps,
+    )
+    .map(|result| result.optimal_amount)
+}
+
+/// Floor for `adaptive_tolerance` (0.000001 ETH)
+const ADAPTIVE_TOLERANCE_ABSOLUTE_MIN: u128 = 1_000_000_000_000;
+
+/// Outcome of a sandwich frontrun optimization
+#[derive(Debug, Clone)]
+pub struct OptimizationResult {
+    /// Frontrun amount that maximizes profit
+    pub optimal_amount: U256,
+    /// Sandwich profit at `optimal_amount`
+    pub expected_profit: U256,
+    /// Iterations run before convergence (or the iteration cap)
+    pub convergence_iterations: usize,
+}
+
+/// Convergence tolerance for the frontrun search, scaled to the victim size
+///
+/// A fixed 0.001 ETH tolerance wastes iterations on tiny victims and stops too
+/// early on large ones; `victim_amount / 100_000` keeps the relative precision
+/// constant, floored at `ADAPTIVE_TOLERANCE_ABSOLUTE_MIN`.
+///
+/// # Arguments
+/// * `victim_amount` - Victim swap amount (upper search bound)
+///
+/// # Returns
+/// * `max(ADAPTIVE_TOLERANCE_ABSOLUTE_MIN, victim_amount / 100_000)`
+fn adaptive_tolerance(victim_amount: U256) -> U256 {
+    (victim_amount / U256::from(100_000u64)).max(U256::from(ADAPTIVE_TOLERANCE_ABSOLUTE_MIN))
+}
+
+/// Brent's Method for V3 sandwich optimization, with profit and iteration count
+///
+/// Same search as `brents_method_v3_sandwich_optimization`, converging within
+/// `adaptive_tolerance` of the optimum.
+///
+/// # Returns
+/// * `Ok(OptimizationResult)` - Best frontrun amount, its profit and the iterations used
+/// * `Err(MathError)` - If inputs are invalid or a profit evaluation fails
+pub fn brents_method_v3_sandwich_optimization_detailed(
+    victim_amount: U256,
+    sqrt_price_x96: U256,
+    liquidity: u128,
+    tick: i32,
+    fee_bps: BasisPoints,
+    aave_fee_bps: BasisPoints,
+) -> Result<OptimizationResult, MathError> {
+    const MAX_ITERATIONS: usize = 50;
+    const GOLDEN_RATIO: u128 = 1618; // φ = 1.618... * 1000
+    const GOLDEN_RATIO_INV: u128 = 618; // (φ - 1) = 0.618... * 1000
+
+    // Search bounds: [min_flash_loan, victim_amount]
+    // Flash loans require minimum 1 token, but since we don't know decimals here,
+    // use a conservative minimum that works for most tokens
+    let min_flash_loan = U256::from(1000000000000000u128); // 0.001 ETH equivalent
+    let mut a = min_flash_loan;
+    let mut b = victim_amount;
+
+    // Initialize with golden section point
+    // CRITICAL: Use 1/φ ≈ 0.618, NOT φ ≈ 1.618
+    // c = b - (1/φ) * (b - a) = b - 0.618 * (b - a)
+    // Or equivalently: c = a + (1 - 1/φ) * (b - a) = a + 0.382 * (b - a)
+    let b_minus_a = b.checked_sub(a).ok_or_else(|| MathError::Underflow {
+        operation: "brents_method_v3_sandwich_optimization".to_string(),
+        inputs: vec![b, a],
+        context: "Calculating b - a: victim_amount must be >= min_flash_loan".to_string(),
+    })?;
+
+    // c = b - (b-a) * 618 / 1000 (using 1/φ ≈ 0.618)
+    let golden_section_step = b_minus_a
+        .checked_mul(U256::from(GOLDEN_RATIO_INV))
+        .and_then(|v| v.checked_div(U256::from(1000)))
+        .ok_or_else(|| MathError::Overflow {
+            operation: "brents_method_v3_sandwich_optimization".to_string(),
+            inputs: vec![b_minus_a, U256::from(GOLDEN_RATIO_INV)],
+            context: "Calculating (b-a) * 0.618".to_string(),
+        })?;
+
+    let c = b
+        .checked_sub(golden_section_step)
+        .ok_or_else(|| MathError::Underflow {
+            operation: "brents_method_v3_sandwich_optimization".to_string(),
+            inputs: vec![b, golden_section_step],
+            context: "Calculating c = b - (b-a)*0.618".to_string(),
+        })?;
+
+    // Ensure c is within bounds [a, b]
+    let c = if c < a {
+        a
+    } else if c > b {
+        b
+    } else {
+        c
+    };
+    let mut x = c;
+    let mut w = c;
+    let mut v = c;
+
+    // Input validation
+    if victim_amount.is_zero() {
+        return Err(MathError::InvalidInput {
+            operation: "brents_method_v3_sandwich_optimization".to_string(),
+            reason: "victim_amount cannot be zero".to_string(),
+            context: format!(
+                "sqrt_price={}, liquidity={}, tick={}",
+                sqrt_price_x96, liquidity, tick
+            ),
+        });
+    }
+
+    if sqrt_price_x96.is_zero() || sqrt_price_x96 < U256::from(MIN_SQRT_RATIO) {
+        return Err(MathError::InvalidInput {
+            operation: "brents_method_v3_sandwich_optimization".to_string(),
+            reason: format!("sqrt_price_x96 out of valid range: {}", sqrt_price_x96),
+            context: format!(
+                "victim_amount={}, liquidity={}, tick={}",
+                victim_amount, liquidity, tick
+            ),
+        });
+    }
+
+    let liquidity_u256 = U256::from(liquidity);
+    if liquidity_u256.is_zero() {
+        return Err(MathError::InvalidInput {
+            operation: "brents_method_v3_sandwich_optimization".to_string(),
+            reason: "Liquidity cannot be zero".to_string(),
+            context: format!(
+                "victim_amount={}, sqrt_price={}, tick={}",
+                victim_amount, sqrt_price_x96, tick
+            ),
+        });
+    }
+
+    if b <= a {
+        return Err(MathError::InvalidInput {
+            operation: "brents_method_v3_sandwich_optimization".to_string(),
+            reason: format!("Invalid search bounds: a={} must be < b={}", a, b),
+            context: format!(
+                "victim_amount={}, min_flash_loan={}",
+                victim_amount, min_flash_loan
+            ),
+        });
+    }
+
+    // Function evaluations
+    let mut fx = calculate_v3_sandwich_profit(
+        x,
+        victim_amount,
+        sqrt_price_x96,
+        liquidity,
+        tick,
+        fee_bps,
+        aave_fee_bps,
+    )
+    .map_err(|e| MathError::InvalidInput {
+        operation: "brents_method_v3_sandwich_optimization".to_string(),
+        reason: format!("Function evaluation failed at initial point: {:?}", e),
+        context: format!(
+            "x={}, victim_amount={}, sqrt_price={}, liquidity={}, tick={}, iteration=0",
+            x, victim_amount, sqrt_price_x96, liquidity, tick
+        ),
+    })?;
+    let mut fw = fx;
+    let mut fv = fx;
+
+    // Brent's method state
+    let mut d = U256::zero();
+    let mut e = U256::zero();
+
+    let tol = adaptive_tolerance(victim_amount);
+
+    for iteration in 0..MAX_ITERATIONS {
+        let midpoint = (a + b) / U256::from(2);
+
+        // Standard Brent's method convergence: interval is small enough
+        // Converge when (b - a) <= 2 * tolerance
+        if iteration > 0 {
+            let two_tol = tol
+                .checked_mul(U256::from(2))
+                .ok_or_else(|| MathError::Overflow {
+                    operation: "brents_method_v3_sandwich_optimization".to_string(),
+                    inputs: vec![tol],
+                    context: "Convergence check: 2 * tolerance calculation".to_string(),
+                })?;
+
+            if (b - a) <= two_tol {
+                tracing::debug!(
+                    "Brent's method converged after {} iterations (interval size: {})",
+                    iteration,
+                    b - a
+                );
+                return Ok(OptimizationResult {
+                    optimal_amount: x,
+                    expected_profit: fx,
+                    convergence_iterations: iteration,
+                });
+            }
+        }
+
+        let mut use_golden_section = true;
+
+        // Try parabolic interpolation if points are distinct
+        if e > tol {
+            // Compute parabolic fit through (v, fv), (w, fw), (x, fx)
+            // Formula: u = x - [(x-w)²(fx-fv) - (x-v)²(fx-fw)] / [2((x-w)(fx-fv) - (x-v)(fx-fw))]
+
+            let r = if x > w { x - w } else { w - x };
+            let q = if x > v { x - v } else { v - x };
+
+            // Calculate numerator and denominator for parabolic step
+            let r_sq_fxfv = r
+                .checked_mul(r)
+                .and_then(|v| v.checked_mul(fx.abs_diff(fv)))
+                .unwrap_or(U256::zero());
+
+            let q_sq_fxfw = q
+                .checked_mul(q)
+                .and_then(|v| v.checked_mul(fx.abs_diff(fw)))
+                .unwrap_or(U256::zero());
+
+            let r_fxfv = r.checked_mul(fx.abs_diff(fv)).unwrap_or(U256::zero());
+            let q_fxfw = q.checked_mul(fx.abs_diff(fw)).unwrap_or(U256::zero());
+
+            // p = r²(fx-fv) - q²(fx-fw)
+            let p = if r_sq_fxfv >= q_sq_fxfw {
+                r_sq_fxfv - q_sq_fxfw
+            } else {
+                q_sq_fxfw - r_sq_fxfv
+            };
+
+            // q = 2(r(fx-fv) - q(fx-fw))
+            let denominator = if r_fxfv >= q_fxfw {
+                (r_fxfv - q_fxfw)
+                    .checked_mul(U256::from(2))
+                    .unwrap_or(U256::zero())
+            } else {
+                (q_fxfw - r_fxfv)
+                    .checked_mul(U256::from(2))
+                    .unwrap_or(U256::zero())
+            };
+
+            if !denominator.is_zero() && p < denominator.checked_mul(b - a).unwrap_or(U256::MAX) {
+                // Parabolic step is acceptable
+                let parabolic_step = p / denominator;
+                let u = if r_sq_fxfv >= q_sq_fxfw {
+                    x.checked_sub(parabolic_step).unwrap_or(a)
+                } else {
+                    x.checked_add(parabolic_step).unwrap_or(b)
+                };
+
+                // Accept parabolic step if within bounds and reasonable
+                if u >= a + tol && u <= b - tol && parabolic_step < (e / U256::from(2)) {
+                    d = parabolic_step;
+                    use_golden_section = false;
+                }
+            }
+        }
+
+        // Use golden section if parabolic interpolation failed
+        // Track whether we're searching left or right
+        let search_left = x >= midpoint;
+
+        if use_golden_section {
+            // Golden section: d is the STEP size
+            // For x >= midpoint: search left, d = (x - a) * 0.382 (step toward a)
+            // For x < midpoint: search right, d = (b - x) * 0.382 (step toward b)
+            if search_left {
+                // Search toward 'a' (left)
+                let range = x.saturating_sub(a);
+                d = range
+                    .checked_mul(U256::from(382))
+                    .unwrap_or(U256::zero())
+                    .checked_div(U256::from(1000))
+                    .unwrap_or(U256::zero());
+                e = range; // Remember the range for next iteration
+            } else {
+                // Search toward 'b' (right)
+                let range = b.saturating_sub(x);
+                d = range
+                    .checked_mul(U256::from(382))
+                    .unwrap_or(U256::zero())
+                    .checked_div(U256::from(1000))
+                    .unwrap_or(U256::zero());
+                e = range; // Remember the range for next iteration
+            }
+        }
+
+        // Calculate next point u
+        // Use saturating arithmetic to avoid panics
+        let u = if d >= tol {
+            if search_left {
+                // Step left: u = x - d
+                x.saturating_sub(d).max(a)
+            } else {
+                // Step right: u = x + d
+                x.saturating_add(d).min(b)
+            }
+        } else {
+            // Minimum step in search direction
+            if search_left {
+                x.saturating_sub(tol).max(a)
+            } else {
+                x.saturating_add(tol).min(b)
+            }
+        };
+
+        // Evaluate function at new point
+        let fu = calculate_v3_sandwich_profit(u, victim_amount, sqrt_price_x96, liquidity, tick, fee_bps, aave_fee_bps)
+            .map_err(|e| MathError::InvalidInput {
+                operation: "brents_method_v3_sandwich_optimization".to_string(),
+                reason: format!("Function evaluation failed: {:?}", e),
+                context: format!("u={}, victim_amount={}, sqrt_price={}, liquidity={}, tick={}, iteration={}, bounds=[{}, {}]", u, victim_amount, sqrt_price_x96, liquidity, tick, iteration, a, b),
+            })?;
+
+        // Update points based on new evaluation
+        if fu >= fx {
+            if u >= x {
+                a = u;
+            } else {
+                b = u;
+            }
+
+            if fu >= fw || w == x {
+                v = w;
+                fv = fw;
+                w = u;
+                fw = fu;
+            } else if fu >= fv || v == x || v == w {
+                v = u;
+                fv = fu;
+            }
+        } else {
+            if u < x {
+                a = u;
+            } else {
+                b = u;
+            }
+
+            v = w;
+            fv = fw;
+            w = x;
+            fw = fx;
+            x = u;
+            fx = fu;
+        }
+    }
+
+    // Maximum iterations reached - return best point found
+    tracing::warn!(
+        "Brent's method reached maximum iterations ({}), returning best point found. Final interval: [{}, {}], size: {}",
+        MAX_ITERATIONS, a, b, b - a
+    );
+    Ok(OptimizationResult {
+        optimal_amount: x,
+        expected_profit: fx,
+        convergence_iterations: MAX_ITERATIONS,
+    })
+}
+
+/// Calculate profit from a Uniswap V3 flash swap
+///
+/// `flash(recipient, amount0, amount1, data)` sends tokens before the callback, so
+/// the borrowed tokens can be sold on another venue and the debt repaid in either
+/// token. The debt is the borrowed value at the pool price plus the pool fee:
+/// `repayment = amount_borrowed / pool_price * (1 + fee_rate)` in the repayment token.
+///
+/// # Arguments
+/// * `token0_borrowed` - Amount of token0 taken from the pool
+/// * `token1_borrowed` - Amount of token1 taken from the pool
+/// * `pool_state` - Pool being flashed
+/// * `repay_in_token0` - Repay the whole debt in token0 (otherwise token1)
+/// * `external_price` - Price of token0 in token1 on the external venue (18-decimal)
+///
+/// # Returns
+/// * `Ok(U256)` - Profit in the repayment token (0 if the flash swap loses money)
+/// * `Err(MathError)` - If inputs are invalid or calculation overflows
+pub fn calculate_v3_flash_swap_profit(
+    token0_borrowed: U256,
+    token1_borrowed: U256,
+    pool_state: &V3PoolState,
+    repay_in_token0: bool,
+    external_price: U256,
+) -> Result<U256, MathError> {
+    if token0_borrowed.is_zero() && token1_borrowed.is_zero() {
+        return Err(MathError::InvalidInput {
+            operation: "calculate_v3_flash_swap_profit".to_string(),
+            reason: "Must borrow at least one token".to_string(),
+            context: "".to_string(),
+        });
+    }
+    if external_price.is_zero() {
+        return Err(MathError::InvalidInput {
+            operation: "calculate_v3_flash_swap_profit".to_string(),
+            reason: "external_price cannot be zero".to_string(),
+            context: format!("sqrt_price_x96={}", pool_state.sqrt_price_x96),
+        });
+    }
+
+    let scale = U256::from(10).pow(U256::from(18));
+    let pool_price = pool_state.spot_price(0, 1)?;
+    if pool_price.is_zero() {
+        return Err(MathError::DivisionByZero {
+            operation: "calculate_v3_flash_swap_profit".to_string(),
+            context: format!(
+                "Pool price rounds to zero (sqrt_price_x96={})",
+                pool_state.sqrt_price_x96
+            ),
+        });
+    }
+
+    // Convert the borrowed basket into the repayment token at a given price
+    let value_in_repayment_token = |price: U256| -> Result<U256, MathError> {
+        let converted = if repay_in_token0 {
+            mul_div(token1_borrowed, scale, price)?
+        } else {
+            mul_div(token0_borrowed, price, scale)?
+        };
+        let native = if repay_in_token0 {
+            token0_borrowed
+        } else {
+            token1_borrowed
+        };
+        native
+            .checked_add(converted)
+            .ok_or_else(|| MathError::Overflow {
+                operation: "calculate_v3_flash_swap_profit".to_string(),
+                inputs: vec![native, converted],
+                context: "Borrowed value in repayment token".to_string(),
+            })
+    };
+
+    // Debt: borrowed value at pool price, plus fee
+    let debt_principal = value_in_repayment_token(pool_price)?;
+    let fee_multiplier = U256::from(10000 + pool_state.fee_bps.as_u32());
+    let repayment = mul_div_rounding_up(debt_principal, fee_multiplier, U256::from(10000))?;
+
+    // Proceeds: borrowed tokens sold on the external venue
+    let proceeds = value_in_repayment_token(external_price)?;
+
+    Ok(proceeds.saturating_sub(repayment))
+}
+
+/// Outcome of a V3 range order
+#[derive(Debug, Clone)]
+pub struct RangeOrderResult {
+    /// Token1 received from converting the position's token0
+    pub token1_received: U256,
+    /// Swap fees earned by the position while the price swept the range
+    pub fees_earned_token1: U256,
+    /// Token1 received plus fees, minus the value of the token0 consumed at the
+    /// pre-swap price (0 if the order lost value)
+    pub net_profit: U256,
+}
+
+/// Calculate profit from a V3 range order (single-sided liquidity provision)
+///
+/// A token0-only position sits entirely above the current price. As the price
+/// rises through `[tick_lower, tick_upper]` the position is converted into token1
+/// and earns the swap fee on every token1 input it absorbs. The order is fully
+/// filled once `sqrt_price_after` reaches the upper tick.
+///
+/// Fee accrual mirrors `feeGrowthGlobal`: each unit of token1 swapped through the
+/// range pays `fee_bps`, split pro-rata between the position and the existing
+/// pool liquidity. With constant external liquidity the per-tick integral collapses
+/// to a single range calculation.
+///
+/// # Arguments
+/// * `tick_lower` - Lower tick of the range order
+/// * `tick_upper` - Upper tick of the range order
+/// * `token0_amount` - Token0 deposited into the range
+/// * `sqrt_price_current` - Sqrt price when the order is placed (Q64.96)
+/// * `sqrt_price_after` - Sqrt price after the price movement (Q64.96)
+/// * `pool_liquidity_before` - Active pool liquidity excluding the range order
+/// * `fee_bps` - Pool fee in basis points
+///
+/// # Returns
+/// * `Ok(RangeOrderResult)` - Token1 received, fees earned and net profit
+/// * `Err(MathError)` - If the range is invalid or not above the current price
+pub fn calculate_range_order_profit(
+    tick_lower: i32,
+    tick_upper: i32,
+    token0_amount: U256,
+    sqrt_price_current: U256,
+    sqrt_price_after: U256,
+    pool_liquidity_before: u128,
+    fee_bps: BasisPoints,
+) -> Result<RangeOrderResult, MathError> {
+    if tick_lower >= tick_upper {
+        return Err(MathError::InvalidInput {
+            operation: "calculate_range_order_profit".to_string(),
+            reason: "tick_lower must be less than tick_upper".to_string(),
+            context: format!("tick_lower={}, tick_upper={}", tick_lower, tick_upper),
+        });
+    }
+
+    let sqrt_lower = get_sqrt_ratio_at_tick(tick_lower)?;
+    let sqrt_upper = get_sqrt_ratio_at_tick(tick_upper)?;
+
+    if sqrt_price_current > sqrt_lower {
+        return Err(MathError::InvalidInput {
+            operation: "calculate_range_order_profit".to_string(),
+            reason: "Token0 range order must sit above the current price".to_string(),
+            context: format!(
+                "sqrt_price_current={}, sqrt_lower={}",
+                sqrt_price_current, sqrt_lower
+            ),
+        });
+    }
+
+    let zero_result = RangeOrderResult {
+        token1_received: U256::zero(),
+        fees_earned_token1: U256::zero(),
+        net_profit: U256::zero(),
+    };
+
+    if token0_amount.is_zero() || sqrt_price_after <= sqrt_lower {
+        // Price never entered the range - nothing converted
+        return Ok(zero_result);
+    }
+
+    let q96 = U256::from(1u128 << 96);
+
+    // Position liquidity from amount0 = L * Q96 * (sqrtU - sqrtL) / (sqrtL * sqrtU)
+    let sqrt_range = sqrt_upper - sqrt_lower;
+    let position_liquidity_u256 =
+        mul_div(mul_div(token0_amount, sqrt_lower, q96)?, sqrt_upper, sqrt_range)?;
+    if position_liquidity_u256 > U256::from(u128::MAX) {
+        return Err(MathError::Overflow {
+            operation: "calculate_range_order_profit".to_string(),
+            inputs: vec![token0_amount, position_liquidity_u256],
+            context: "Position liquidity exceeds u128".to_string(),
+        });
+    }
+    let position_liquidity = position_liquidity_u256.as_u128();
+    if position_liquidity == 0 {
+        return Ok(zero_result);
+    }
+
+    // Portion of the range the price actually crossed
+    let sqrt_filled = sqrt_price_after.min(sqrt_upper);
+
+    let token0_consumed = get_amount0_delta(sqrt_lower, sqrt_filled, position_liquidity, true)?;
+    let token1_received = get_amount1_delta(sqrt_lower, sqrt_filled, position_liquidity, false)?;
+
+    // Total token1 swapped through the filled range (net of fee) across all liquidity
+    let total_liquidity = pool_liquidity_before
+        .checked_add(position_liquidity)
+        .ok_or_else(|| MathError::Overflow {
+            operation: "calculate_range_order_profit".to_string(),
+            inputs: vec![
+                U256::from(pool_liquidity_before),
+                U256::from(position_liquidity),
+            ],
+            context: "Total liquidity".to_string(),
+        })?;
+    let swapped_net = get_amount1_delta(sqrt_lower, sqrt_filled, total_liquidity, true)?;
+
+    // Fee paid on top of the net input: gross = net * 10000 / (10000 - fee)
+    let fee = fee_bps.as_u32();
+    if fee >= 10000 {
+        return Err(MathError::InvalidInput {
+            operation: "calculate_range_order_profit".to_string(),
+            reason: "fee_bps must be below 10000".to_string(),
+            context: format!("fee_bps={}", fee),
+        });
+    }
+    let total_fees = mul_div(swapped_net, U256::from(fee), U256::from(10000 - fee))?;
+
+    // Position's pro-rata share of fees
+    let fees_earned_token1 = mul_div(
+        total_fees,
+        U256::from(position_liquidity),
+        U256::from(total_liquidity),
+    )?;
+
+    // Value of consumed token0 at the pre-swap price: amount0 * sqrtP^2 / Q192
+    let consumed_value =
+        mul_div(mul_div(token0_consumed, sqrt_price_current, q96)?, sqrt_price_current, q96)?;
+
+    let net_profit = token1_received
+        .saturating_add(fees_earned_token1)
+        .saturating_sub(consumed_value);
+
+    Ok(RangeOrderResult {
+        token1_received,
+        fees_earned_token1,
+        net_profit,
+    })
+}
+
+/// Calculate the token reserves backing a V3 liquidity position
+///
+/// Expresses concentrated liquidity in V2-style reserve terms so V3 pools can be
+/// compared with constant-product pools for routing, and the implied
+/// `reserve1 / reserve0` price can be sanity-checked against `sqrt_price_x96`.
+/// - amount0 = get_amount0_delta(max(sqrt_price, sqrt_lower), sqrt_upper, L)
+/// - amount1 = get_amount1_delta(sqrt_lower, min(sqrt_price, sqrt_upper), L)
+///
+/// # Arguments
+/// * `sqrt_price_x96` - Current sqrt price in Q64.96 format
+/// * `liquidity` - Liquidity over the range
+/// * `tick_lower` - Lower tick of the range
+/// * `tick_upper` - Upper tick of the range
+///
+/// # Returns
+/// * `Ok((amount0, amount1))` - Token reserves (rounded down)
+/// * `Err(MathError)` - If the tick range is invalid or the calculation fails
+pub fn calculate_v3_virtual_reserves(
+    sqrt_price_x96: U256,
+    liquidity: u128,
+    tick_lower: i32,
+    tick_upper: i32,
+) -> Result<(U256, U256), MathError> {
+    position_token_amounts(
+        sqrt_price_x96,
+        liquidity,
+        tick_lower,
+        tick_upper,
+        false,
+        "calculate_v3_virtual_reserves",
+    )
+}
+
+/// Token amounts backing `liquidity` over `[tick_lower, tick_upper)` at the current price
+///
+/// `operation` names the public caller in errors.
+fn position_token_amounts(
+    sqrt_price_x96: U256,
+    liquidity: u128,
+    tick_lower: i32,
+    tick_upper: i32,
+    round_up: bool,
+    operation: &str,
+) -> Result<(U256, U256), MathError> {
+    if tick_lower >= tick_upper {
+        return Err(MathError::InvalidInput {
+            operation: operation.to_string(),
+            reason: "tick_lower must be below tick_upper".to_string(),
+            context: format!("tick_lower={}, tick_upper={}", tick_lower, tick_upper),
+        });
+    }
+
+    let sqrt_lower = get_sqrt_ratio_at_tick(tick_lower)?;
+    let sqrt_upper = get_sqrt_ratio_at_tick(tick_upper)?;
+
+    // Clamp the current price into the range: below it the position is all token0,
+    // above it all token1
+    let sqrt_price = sqrt_price_x96.max(sqrt_lower).min(sqrt_upper);
+
+    let amount0 = if sqrt_price < sqrt_upper {
+        get_amount0_delta(sqrt_price, sqrt_upper, liquidity, round_up)?
+    } else {
+        U256::zero()
+    };
+    let amount1 = if sqrt_price > sqrt_lower {
+        get_amount1_delta(sqrt_lower, sqrt_price, liquidity, round_up)?
+    } else {
+        U256::zero()
+    };
+
+    Ok((amount0, amount1))
+}
+
+/// Capital efficiency of a V3 range relative to full-range (V2-style) liquidity
+///
+/// V2 spreads liquidity over every tick, so concentrating the same capital into
+/// a narrower range multiplies its in-range depth by the ratio of tick widths:
+/// `efficiency = total_v2_range_ticks / (tick_upper - tick_lower)`
+/// (pass `2 * MAX_TICK` for the full V2 range).
+///
+/// # Arguments
+/// * `tick_lower` - Lower tick of the V3 range
+/// * `tick_upper` - Upper tick of the V3 range
+/// * `total_v2_range_ticks` - Tick width the V2 liquidity is spread over
+///
+/// # Returns
+/// * `f64` - Efficiency multiple (0.0 if the range is empty or inverted)
+pub fn calculate_capital_efficiency_ratio(
+    tick_lower: i32,
+    tick_upper: i32,
+    total_v2_range_ticks: i32,
+) -> f64 {
+    let range_ticks = i64::from(tick_upper) - i64::from(tick_lower);
+    if range_ticks <= 0 {
+        return 0.0;
+    }
+
+    f64::from(total_v2_range_ticks) / range_ticks as f64
+}
+
+/// Convert a U256 to f64 (lossy above 2^53)
+fn u256_to_f64(value: U256) -> f64 {
+    value
+        .0
+        .iter()
+        .rev()
+        .fold(0.0, |acc, word| acc * 18_446_744_073_709_551_616.0 + *word as f64)
+}
+
+/// Calculate the USD capital needed to mint a target liquidity over a range
+///
+/// Token amounts are the `calculate_v3_virtual_reserves` formulas rounded up,
+/// as the LP pays on mint.
+///
+/// # Arguments
+/// * `target_liquidity` - Liquidity to mint
+/// * `tick_lower` - Lower tick of the range
+/// * `tick_upper` - Upper tick of the range
+/// * `sqrt_price_x96` - Current sqrt price in Q64.96 format
+/// * `token_prices` - USD price of 10^18 raw units of (token0, token1)
+///
+/// # Returns
+/// * `(f64, f64)` - USD value of token0 and token1 required ((0.0, 0.0) if the range is invalid)
+pub fn calculate_required_capital_for_liquidity_target(
+    target_liquidity: u128,
+    tick_lower: i32,
+    tick_upper: i32,
+    sqrt_price_x96: U256,
+    token_prices: (f64, f64),
+) -> (f64, f64) {
+    const SCALE: f64 = 1e18;
+
+    let amounts = position_token_amounts(
+        sqrt_price_x96,
+        target_liquidity,
+        tick_lower,
+        tick_upper,
+        true,
+        "calculate_required_capital_for_liquidity_target",
+    );
+
+    match amounts {
+        Ok((amount0, amount1)) => (
+            u256_to_f64(amount0) / SCALE * token_prices.0,
+            u256_to_f64(amount1) / SCALE * token_prices.1,
+        ),
+        Err(e) => {
+            tracing::warn!("Required capital calculation failed: {:?}", e);
+            (0.0, 0.0)
+        }
+    }
+}
+
+/// Concentrated liquidity position over `[tick_lower, tick_upper)`
+#[derive(Debug, Clone)]
+pub struct V3Position {
+    /// Lower tick of the position
+    pub tick_lower: i32,
+    /// Upper tick of the position
+    pub tick_upper: i32,
+    /// Position liquidity
+    pub liquidity: u128,
+}
+
+/// Concentrated liquidity position minted just before a victim swap
+pub type V3JitPosition = V3Position;
+
+/// Gas used by each JIT lifecycle step
+#[derive(Debug, Clone)]
+pub struct GasCosts {
+    /// Gas for `mint()`
+    pub mint_gas: u64,
+    /// Gas for `burn()`
+    pub burn_gas: u64,
+    /// Gas for `collect()`
+    pub collect_gas: u64,
+    /// Effective gas price (base fee + priority fee) in wei
+    pub gas_price: U256,
+}
+
+impl GasCosts {
+    /// Total gas cost of mint + burn + collect in wei
+    pub fn total_cost_wei(&self) -> U256 {
+        let total_gas = self
+            .mint_gas
+            .saturating_add(self.burn_gas)
+            .saturating_add(self.collect_gas);
+        self.gas_price.saturating_mul(U256::from(total_gas))
+    }
+}
+
+/// Outcome of a simulated V3 JIT mint -> victim swap -> burn/collect sequence
+#[derive(Debug, Clone)]
+pub struct JitLifecycleResult {
+    /// Token amounts deposited by `mint()` (token0, token1)
+    pub capital_deployed: (U256, U256),
+    /// Fees collected by `collect()` (token0, token1)
+    pub fees_collected: (U256, U256),
+    /// Value lost vs. holding the deposited tokens, in token1 at the post-swap price
+    pub impermanent_loss: U256,
+    /// Fees - impermanent loss - gas, in token1 (saturates at 0)
+    pub net_profit: U256,
+}
+
+/// Value a token0 amount in token1 at the given sqrt price (amount0 * P)
+fn token0_value_in_token1(
+    amount0: Token0Amount,
+    sqrt_price_x96: U256,
+) -> Result<Token1Amount, MathError> {
+    let q96 = U256::from(1u128) << 96;
+    mul_div(mul_div(amount0.inner(), sqrt_price_x96, q96)?, sqrt_price_x96, q96)
+        .map(Token1Amount::from)
+}
+
+/// Value a `(token0, token1)` holding in token1 at the given sqrt price (saturating)
+fn holding_value_in_token1(holding: (U256, U256), sqrt_price_x96: U256) -> Result<U256, MathError> {
+    let (amount0, amount1) = (Token0Amount::from(holding.0), Token1Amount::from(holding.1));
+    let token0_value = token0_value_in_token1(amount0, sqrt_price_x96)?;
+    Ok(token0_value.inner().saturating_add(amount1.inner()))
+}
+
+/// Simulate the full V3 JIT liquidity lifecycle
+///
+/// 1. `mint()`: deposit the position's reserves at the current price
+/// 2. Victim swap executes against pool + JIT liquidity; the JIT share of the
+///    fee is `fee * L_jit / (L_pool + L_jit)`, paid in the victim's input token
+/// 3. `burn()` + `collect()`: withdraw the position's reserves at the post-swap price
+///
+/// Values are expressed in token1 at the post-swap price, and gas is subtracted
+/// as token1, so token1 is assumed to be the gas token (WETH).
+///
+/// # Arguments
+/// * `position` - JIT position to mint
+/// * `victim_swap` - Victim swap parameters
+/// * `pool_state` - Pool state before the mint
+/// * `gas_costs` - Gas usage and price for the three steps
+///
+/// # Returns
+/// * `Ok(JitLifecycleResult)` - Capital, fees, impermanent loss and net profit
+/// * `Err(MathError)` - If the position is invalid or any step fails
+pub fn simulate_v3_jit_full(
+    position: V3JitPosition,
+    victim_swap: V3SwapParams,
+    pool_state: &V3PoolState,
+    gas_costs: &GasCosts,
+) -> Result<JitLifecycleResult, MathError> {
+    if position.liquidity == 0 {
+        return Err(MathError::InvalidInput {
+            operation: "simulate_v3_jit_full".to_string(),
+            reason: "JIT position liquidity cannot be zero".to_string(),
+            context: format!(
+                "tick_lower={}, tick_upper={}",
+                position.tick_lower, position.tick_upper
+            ),
+        });
+    }
+
+    // Step 1: mint
+    let capital_deployed = calculate_v3_virtual_reserves(
+        pool_state.sqrt_price_x96,
+        position.liquidity,
+        position.tick_lower,
+        position.tick_upper,
+    )?;
+
+    // JIT liquidity only deepens the pool if the position is in range
+    let in_range =
+        pool_state.tick >= position.tick_lower && pool_state.tick < position.tick_upper;
+    let jit_liquidity = if in_range { position.liquidity } else { 0 };
+    let total_liquidity = pool_state
+        .liquidity
+        .checked_add(jit_liquidity)
+        .ok_or_else(|| MathError::Overflow {
+            operation: "simulate_v3_jit_full".to_string(),
+            inputs: vec![U256::from(pool_state.liquidity), U256::from(jit_liquidity)],
+            context: "Pool liquidity after mint".to_string(),
+        })?;
+
+    // Step 2: victim swap
+    let (sqrt_price_after, _) = calculate_v3_post_victim_state(
+        victim_swap.amount_in,
+        pool_state.sqrt_price_x96,
+        total_liquidity,
+        pool_state.tick,
+        pool_state.fee_bps,
+        victim_swap.direction,
+    )?;
+
+    let swap_fee = victim_swap
+        .amount_in
+        .checked_mul(U256::from(pool_state.fee_bps.as_u32()))
+        .ok_or_else(|| MathError::Overflow {
+            operation: "simulate_v3_jit_full".to_string(),
+            inputs: vec![victim_swap.amount_in],
+            context: "Victim swap fee".to_string(),
+        })?
+        / U256::from(10000);
+    let jit_fee = mul_div(swap_fee, U256::from(jit_liquidity), U256::from(total_liquidity))?;
+
+    let fees_collected = match victim_swap.direction {
+        SwapDirection::Token0ToToken1 => (jit_fee, U256::zero()),
+        SwapDirection::Token1ToToken0 => (U256::zero(), jit_fee),
+    };
+
+    // Step 3: burn + collect
+    let withdrawn = calculate_v3_virtual_reserves(
+        sqrt_price_after,
+        position.liquidity,
+        position.tick_lower,
+        position.tick_upper,
+    )?;
+
+    let hold_value = holding_value_in_token1(capital_deployed, sqrt_price_after)?;
+    let withdrawn_value = holding_value_in_token1(withdrawn, sqrt_price_after)?;
+    let fees_value = holding_value_in_token1(fees_collected, sqrt_price_after)?;
+
+    let impermanent_loss = hold_value.saturating_sub(withdrawn_value);
+    let net_profit = fees_value
+        .saturating_sub(impermanent_loss)
+        .saturating_sub(gas_costs.total_cost_wei());
+
+    Ok(JitLifecycleResult {
+        capital_deployed,
+        fees_collected,
+        impermanent_loss,
+        net_profit,
+    })
+}
+
+/// A historical swap against a V3 pool
+#[derive(Debug, Clone)]
+pub struct SwapEvent {
+    /// Sqrt price before the swap (Q64.96)
+    pub sqrt_price_before: U256,
+    /// Sqrt price after the swap (Q64.96)
+    pub sqrt_price_after: U256,
+    /// Swap input amount (token0 if price fell, token1 if it rose)
+    pub amount_in: U256,
+    /// Pool fee in basis points
+    pub fee_bps: BasisPoints,
+}
+
+/// Calculate the fees a V3 position earned over a sequence of swaps
+///
+/// For each swap, the part of its price path inside the position's range earns
+/// `fee_total * overlap_fraction * position.liquidity / pool_liquidity`.
+/// The overlap fraction is measured in input-token terms (amount0 for price
+/// moving down, amount1 for price moving up), so it is exact while liquidity is
+/// constant along the swap.
+///
+/// # Arguments
+/// * `position` - Position whose fees are being computed
+/// * `swaps` - Swaps in execution order
+/// * `pool_liquidity_per_swap` - Active pool liquidity during each swap (includes the position)
+///
+/// # Returns
+/// * `Ok((fees_token0, fees_token1))` - Total fees earned
+/// * `Err(MathError)` - If inputs are inconsistent or the calculation fails
+pub fn calculate_fees_earned(
+    position: &V3Position,
+    swaps: &[SwapEvent],
+    pool_liquidity_per_swap: &[u128],
+) -> Result<(U256, U256), MathError> {
+    if swaps.len() != pool_liquidity_per_swap.len() || position.tick_lower >= position.tick_upper
+    {
+        return Err(MathError::InvalidInput {
+            operation: "calculate_fees_earned".to_string(),
+            reason: "Liquidity per swap must match swaps and tick range must be valid".to_string(),
+            context: format!(
+                "swaps={}, liquidities={}, tick_lower={}, tick_upper={}",
+                swaps.len(),
+                pool_liquidity_per_swap.len(),
+                position.tick_lower,
+                position.tick_upper
+            ),
+        });
+    }
+
+    let sqrt_lower = get_sqrt_ratio_at_tick(position.tick_lower)?;
+    let sqrt_upper = get_sqrt_ratio_at_tick(position.tick_upper)?;
+
+    let mut fees_token0 = U256::zero();
+    let mut fees_token1 = U256::zero();
+
+    for (idx, (swap, &pool_liquidity)) in swaps.iter().zip(pool_liquidity_per_swap).enumerate() {
+        let zero_for_one = swap.sqrt_price_after < swap.sqrt_price_before;
+        let (path_low, path_high) = if zero_for_one {
+            (swap.sqrt_price_after, swap.sqrt_price_before)
+        } else {
+            (swap.sqrt_price_before, swap.sqrt_price_after)
+        };
+
+        // Overlap of the swap's price path with the position range
+        let overlap_low = path_low.max(sqrt_lower);
+        let overlap_high = path_high.min(sqrt_upper);
+        if overlap_low >= overlap_high || pool_liquidity == 0 {
+            continue;
+        }
+
+        // Input consumed inside the range vs. along the whole path
+        let (overlap_input, path_input) = if zero_for_one {
+            (
+                get_amount0_delta(overlap_low, overlap_high, pool_liquidity, false)?,
+                get_amount0_delta(path_low, path_high, pool_liquidity, false)?,
+            )
+        } else {
+            (
+                get_amount1_delta(overlap_low, overlap_high, pool_liquidity, false)?,
+                get_amount1_delta(path_low, path_high, pool_liquidity, false)?,
+            )
+        };
+        if path_input.is_zero() {
+            continue;
+        }
+
+        let swap_fee = swap
+            .amount_in
+            .checked_mul(U256::from(swap.fee_bps.as_u32()))
+            .ok_or_else(|| MathError::Overflow {
+                operation: "calculate_fees_earned".to_string(),
+                inputs: vec![swap.amount_in],
+                context: format!("Fee for swap {}", idx),
+            })?
+            / U256::from(10000);
+
+        let fee_in_range = mul_div(swap_fee, overlap_input, path_input)?;
+        let position_fee = mul_div(
+            fee_in_range,
+            U256::from(position.liquidity),
+            U256::from(pool_liquidity),
+        )?;
+
+        if zero_for_one {
+            fees_token0 = fees_token0.saturating_add(position_fee);
+        } else {
+            fees_token1 = fees_token1.saturating_add(position_fee);
+        }
+    }
+
+    Ok((fees_token0, fees_token1))
+}
+
+/// Swap execution segment (within one tick range)
+#[derive(Debug, Clone)]
+pub struct SwapSegment {
+    /// Starting sqrt_price for this segment
+    pub sqrt_price_start: U256,
+    /// Ending sqrt_price for this segment
+    pub sqrt_price_end: U256,
+    /// Tick at start of segment
+    pub tick_start: i32,
+    /// Tick at end of segment
+    pub tick_end: i32,
+    /// Liquidity active in this segment
+    pub liquidity: u128,
+    /// Amount swapped in this segment
+    pub amount_in: U256,
+    /// Fee generated in this segment
+    pub fee_amount: U256,
+}
+
+/// Maximum number of tick segments `simulate_swap_with_ticks` will walk
+///
+/// Callers can pre-check large swaps by estimating the ticks crossed (tick delta
+/// from the expected price ratio divided by `tick_spacing`) before simulating.
+pub const MAX_SEGMENTS: usize = 1000;
+
+/// Simulate V3 swap with tick-level details
+/// CRITICAL: Returns exact execution path for fee calculations
+///
+/// Returns an error instead of a partial path if the swap would need more than
+/// `MAX_SEGMENTS` segments, so unconsumed input is never silently dropped.
+///
+/// # Arguments
+/// * `amount_in` - Input amount
+/// * `sqrt_price_start` - Starting sqrt_price  
+/// * `current_liquidity` - Starting active liquidity
+/// * `fee_bps` - Fee in basis points
+/// * `tick_spacing` - Tick spacing for the pool
+///
+/// # Returns
+/// * Vector of swap segments showing tick-by-tick execution
+///
+/// # Examples
+/// ```
+/// use ethers::types::U256;
+/// use rust_sidecar::core::BasisPoints;
+/// use rust_sidecar::dex::uniswap_v3::math::simulate_swap_with_ticks;
+///
+/// // 1 ETH at tick 0 with 1000 ETH of liquidity stays inside the [-600, 600] range
+/// let amount_in = U256::from(1_000_000_000_000_000_000u128);
+/// let segments = simulate_swap_with_ticks(
+///     amount_in,
+///     U256::from(79228162514264337593543950336u128),
+///     1_000_000_000_000_000_000_000u128,
+///     BasisPoints::new_const(30),
+///     60,
+///     &[-600, 600],
+/// )
+/// .unwrap();
+///
+/// assert_eq!(segments.len(), 1);
+/// assert_eq!(segments[0].amount_in, amount_in);
+/// assert_eq!(segments[0].fee_amount, U256::from(3_000_000_000_000_000u128));
+/// ```
+pub fn simulate_swap_with_ticks(
+    amount_in: U256,
+    sqrt_price_start: U256,
+    current_liquidity: u128,
+    fee_bps: BasisPoints,
+    tick_spacing: i32,
+    initialized_ticks: &[i32], // Real initialized tick boundaries
+) -> Result<Vec<SwapSegment>, MathError> {
+    let mut segments = Vec::new();
+    let mut remaining_amount = amount_in;
+    let mut current_sqrt_price = sqrt_price_start;
+    let mut current_tick = sqrt_price_to_tick(current_sqrt_price)?;
+
+    // Simulate swap step-by-step
+    while !remaining_amount.is_zero() {
+        if segments.len() >= MAX_SEGMENTS {
+            return Err(MathError::InvalidInput {
+                operation: "simulate_swap_with_ticks".to_string(),
+                reason: "Swap exceeds max segments".to_string(),
+                context: format!(
+                    "max_segments={}, amount_in={}, remaining_amount={}, tick_spacing={}",
+                    MAX_SEGMENTS, amount_in, remaining_amount, tick_spacing
+                ),
+            });
+        }
+
+        // Find next initialized tick boundary
+        let next_tick = find_next_initialized_tick(current_tick, initialized_ticks, tick_spacing)?;
+        // 0.3% tier pools (tick_spacing = 60) use the precomputed table
+        let next_tick_sqrt_price = if tick_spacing == 60 {
+            get_sqrt_ratio_at_tick_spacing_60(next_tick).ok_or_else(|| MathError::InvalidInput {
+                operation: "simulate_swap_with_ticks".to_string(),
+                reason: "Next tick out of bounds".to_string(),
+                context: format!("next_tick={}", next_tick),
+            })?
+        } else {
+            get_sqrt_ratio_at_tick(next_tick)?
+        };
+
+        // Calculate max amount we can swap before hitting next tick
+        let liquidity_u256 = U256::from(current_liquidity);
+        let sqrt_price_delta = next_tick_sqrt_price
+            .checked_sub(current_sqrt_price)
+            .ok_or_else(|| MathError::Underflow {
+                operation: "simulate_swap_with_ticks".to_string(),
+                inputs: vec![next_tick_sqrt_price, current_sqrt_price],
+                context: "sqrt_price_delta".to_string(),
+            })?;
+
+        let max_amount_to_next_tick = liquidity_u256
+            .checked_mul(sqrt_price_delta)
+            .ok_or_else(|| MathError::Overflow {
+                operation: "simulate_swap_with_ticks".to_string(),
+                inputs: vec![liquidity_u256, sqrt_price_delta],
+                context: "max_amount".to_string(),
+            })?
+            .checked_div(U256::from(1u128 << 96))
+            .ok_or_else(|| MathError::DivisionByZero {
+                operation: "simulate_swap_with_ticks".to_string(),
+                context: "max_amount division".to_string(),
+            })?;
+
+        // Determine how much we actually swap in this segment
+        let segment_amount = remaining_amount.min(max_amount_to_next_tick);
+
+        // Calculate fee for this segment
+        let segment_fee = segment_amount
+            .checked_mul(U256::from(fee_bps.as_u32()))
+            .ok_or_else(|| MathError::Overflow {
+                operation: "simulate_swap_with_ticks".to_string(),
+                inputs: vec![segment_amount],
+                context: "fee calculation".to_string(),
+            })?
+            .checked_div(U256::from(10000))
+            .ok_or_else(|| MathError::DivisionByZero {
+                operation: "simulate_swap_with_ticks".to_string(),
+                context: "fee division".to_string(),
+            })?;
+
+        // Calculate new sqrt_price after this segment
+        let amount_after_fee =
+            segment_amount
+                .checked_sub(segment_fee)
+                .ok_or_else(|| MathError::Underflow {
+                    operation: "simulate_swap_with_ticks".to_string(),
+                    inputs: vec![segment_amount, segment_fee],
+                    context: "amount after fee".to_string(),
+                })?;
+
+        let price_impact = amount_after_fee
+            .checked_mul(U256::from(1u128 << 96))
+            .ok_or_else(|| MathError::Overflow {
+                operation: "simulate_swap_with_ticks".to_string(),
+                inputs: vec![amount_after_fee],
+                context: "price impact".to_string(),
+            })?
+            .checked_div(liquidity_u256)
+            .ok_or_else(|| MathError::DivisionByZero {
+                operation: "simulate_swap_with_ticks".to_string(),
+                context: "price impact division".to_string(),
+            })?;
+
+        let new_sqrt_price = current_sqrt_price
+            .checked_add(price_impact)
+            .ok_or_else(|| MathError::Overflow {
+                operation: "simulate_swap_with_ticks".to_string(),
+                inputs: vec![current_sqrt_price, price_impact],
+                context: "new sqrt_price".to_string(),
+            })?;
+
+        let new_tick = sqrt_price_to_tick(new_sqrt_price)?;
+
+        // Record this segment
+        segments.push(SwapSegment {
+            sqrt_price_start: current_sqrt_price,
+            sqrt_price_end: new_sqrt_price,
+            tick_start: current_tick,
+            tick_end: new_tick,
+            liquidity: current_liquidity,
+            amount_in: segment_amount,
+            fee_amount: segment_fee,
+        });
+
+        // Update for next iteration
+        remaining_amount = remaining_amount
+            .checked_sub(segment_amount)
+            .ok_or_else(|| MathError::Underflow {
+                operation: "simulate_swap_with_ticks".to_string(),
+                inputs: vec![remaining_amount, segment_amount],
+                context: "remaining amount".to_string(),
+            })?;
+        current_sqrt_price = new_sqrt_price;
+        current_tick = new_tick;
+
+        // If we've fully consumed this segment, break
+        if segment_amount < max_amount_to_next_tick {
+            break;
+        }
+    }
+
+    Ok(segments)
+}
+
+/// Find next initialized tick boundary
+fn find_next_initialized_tick(
+    current_tick: i32,
+    initialized_ticks: &[i32],
+    tick_spacing: i32,
+) -> Result<i32, MathError> {
+    // Binary search for next tick after current_tick
+    let mut left = 0;
+    let mut right = initialized_ticks.len();
+
+    while left < right {
+        let mid = (left + right) / 2;
+        if initialized_ticks[mid] <= current_tick {
+            left = mid + 1;
+        } else {
+            right = mid;
+        }
+    }
+
+    if left < initialized_ticks.len() {
+        Ok(initialized_ticks[left])
+    } else {
+        // Beyond last tick - calculate next tick boundary manually
+        let next_spaced_tick = ((current_tick / tick_spacing) + 1) * tick_spacing;
+        Ok(next_spaced_tick)
+    }
+}
+
+/// Initialized tick state for a Uniswap V3 pool (subset of `Tick.Info`)
+#[derive(Debug, Clone, Default)]
+pub struct TickData {
+    /// Total position liquidity referencing this tick
+    pub liquidity_gross: u128,
+    /// Liquidity added (positive) or removed (negative) when crossing left to right
+    pub liquidity_net: i128,
+}
+
+/// Apply a tick's `liquidity_net` to the active liquidity when crossing it
+///
+/// Some V3 forks initialize `liquidityNet` incorrectly, so a crossing can try
+/// to remove more liquidity than is active. Instead of wrapping or panicking,
+/// such pools are reported as invalid. Callers crossing right to left pass the
+/// negated `liquidity_net`, as in `Pool.swap`.
+///
+/// # Arguments
+/// * `current_liquidity` - Active liquidity before the crossing
+/// * `liquidity_net` - Signed liquidity change at the crossed tick
+///
+/// # Returns
+/// * `Ok(u128)` - Active liquidity after the crossing
+/// * `Err(MathError)` - If the result would underflow zero or overflow u128
+pub fn apply_liquidity_net_safe(
+    current_liquidity: u128,
+    liquidity_net: i128,
+) -> Result<u128, MathError> {
+    let magnitude = liquidity_net.unsigned_abs();
+    if liquidity_net < 0 {
+        current_liquidity
+            .checked_sub(magnitude)
+            .ok_or_else(|| MathError::InvalidInput {
+                operation: "apply_liquidity_net_safe".to_string(),
+                reason: "liquidityNet would underflow active liquidity".to_string(),
+                context: format!(
+                    "current_liquidity={}, liquidity_net={}",
+                    current_liquidity, liquidity_net
+                ),
+            })
+    } else {
+        current_liquidity
+            .checked_add(magnitude)
+            .ok_or_else(|| MathError::Overflow {
+                operation: "apply_liquidity_net_safe".to_string(),
+                inputs: vec![U256::from(current_liquidity), U256::from(magnitude)],
+                context: "liquidityNet would overflow active liquidity".to_string(),
+            })
+    }
+}
+
+/// Sum the liquidity of all positions overlapping `[tick_lower, tick_upper]`
+///
+/// Positions overlapping the range are those active at `tick_lower` plus those
+/// whose lower tick lies inside the range. Active liquidity at `tick_lower` is
+/// found by crossing the initialized ticks between the current tick and
+/// `tick_lower` with `apply_liquidity_net_safe`, in the order `Pool.swap` would.
+/// The liquidity starting at a tick is `(liquidity_gross + liquidity_net) / 2`,
+/// since gross counts every position referencing the tick and net subtracts
+/// those ending there.
+///
+/// # Arguments
+/// * `pool_state` - Pool snapshot (current tick and active liquidity)
+/// * `tick_lower` - Lower tick of the range
+/// * `tick_upper` - Upper tick of the range
+/// * `tick_data` - Initialized ticks keyed by tick index
+///
+/// # Returns
+/// * `Ok(u128)` - Liquidity of overlapping positions (0 for an empty range)
+/// * `Err(MathError)` - If the tick data is inconsistent with the active liquidity
+///   (a crossing underflows, or a tick's net exceeds its gross) or the sum overflows
+pub fn calculate_v3_liquidity_in_range(
+    pool_state: &V3PoolState,
+    tick_lower: i32,
+    tick_upper: i32,
+    tick_data: &HashMap<i32, TickData>,
+) -> Result<u128, MathError> {
+    if tick_lower > tick_upper {
+        return Ok(0);
+    }
+
+    // Active liquidity includes liquidity_net of every initialized tick <= current tick
+    let current = pool_state.tick;
+    let mut crossed: Vec<(i32, i128)> = tick_data
+        .iter()
+        .filter(|(tick, _)| {
+            if tick_lower >= current {
+                **tick > current && **tick <= tick_lower
+            } else {
+                **tick > tick_lower && **tick <= current
+            }
+        })
+        .map(|(tick, data)| (*tick, data.liquidity_net))
+        .collect();
+
+    let mut active_at_lower = pool_state.liquidity;
+    if tick_lower >= current {
+        crossed.sort_by_key(|(tick, _)| *tick);
+        for (_, liquidity_net) in crossed {
+            active_at_lower = apply_liquidity_net_safe(active_at_lower, liquidity_net)?;
+        }
+    } else {
+        // Crossing right to left removes what crossing left to right adds
+        crossed.sort_by_key(|(tick, _)| std::cmp::Reverse(*tick));
+        for (tick, liquidity_net) in crossed {
+            let negated = liquidity_net.checked_neg().ok_or_else(|| MathError::Overflow {
+                operation: "calculate_v3_liquidity_in_range".to_string(),
+                inputs: vec![U256::from(liquidity_net.unsigned_abs())],
+                context: format!("liquidity_net at tick {} cannot be negated", tick),
+            })?;
+            active_at_lower = apply_liquidity_net_safe(active_at_lower, negated)?;
+        }
+    }
+
+    let mut total = active_at_lower;
+    for (tick, data) in tick_data {
+        if *tick <= tick_lower || *tick > tick_upper {
+            continue;
+        }
+        let starting = apply_liquidity_net_safe(data.liquidity_gross, data.liquidity_net)
+            .map_err(|_| MathError::InvalidInput {
+                operation: "calculate_v3_liquidity_in_range".to_string(),
+                reason: "liquidity_net exceeds liquidity_gross".to_string(),
+                context: format!(
+                    "tick={}, liquidity_gross={}, liquidity_net={}",
+                    tick, data.liquidity_gross, data.liquidity_net
+                ),
+            })?
+            / 2;
+        total = total.checked_add(starting).ok_or_else(|| MathError::Overflow {
+            operation: "calculate_v3_liquidity_in_range".to_string(),
+            inputs: vec![U256::from(total), U256::from(starting)],
+            context: format!("tick_lower={}, tick_upper={}", tick_lower, tick_upper),
+        })?;
+    }
+
+    Ok(total)
+}
+
+/// Rank pools by the liquidity of positions overlapping a tick range
+///
+/// # Arguments
+/// * `pools` - Pool snapshots
+/// * `tick_lower` - Lower tick of the range
+/// * `tick_upper` - Upper tick of the range
+/// * `tick_datas` - Initialized ticks for each pool, in the same order as `pools`
+///
+/// # Returns
+/// * `Ok(Vec<usize>)` - Pool indices, deepest first (ties keep input order)
+/// * `Err(MathError)` - If any pool's tick data is inconsistent
+pub fn rank_pools_by_liquidity_in_range(
+    pools: &[&V3PoolState],
+    tick_lower: i32,
+    tick_upper: i32,
+    tick_datas: &[&HashMap<i32, TickData>],
+) -> Result<Vec<usize>, MathError> {
+    if pools.len() != tick_datas.len() {
+        tracing::warn!(
+            "Ranking {} pools with tick data for {}; extra entries ignored",
+            pools.len(),
+            tick_datas.len()
+        );
+    }
+
+    let mut depths: Vec<(usize, u128)> = pools
+        .iter()
+        .zip(tick_datas)
+        .enumerate()
+        .map(|(index, (pool, ticks))| {
+            calculate_v3_liquidity_in_range(pool, tick_lower, tick_upper, ticks)
+                .map(|depth| (index, depth))
+        })
+        .collect::<Result<_, _>>()?;
+    depths.sort_by(|a, b| b.1.cmp(&a.1));
+
+    Ok(depths.into_iter().map(|(index, _)| index).collect())
+}
+
+/// Where the current price sits relative to a position's tick range
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RangePosition {
+    /// Current tick below `tick_lower`: the position holds only token0
+    Below,
+    /// Current tick in `[tick_lower, tick_upper)`, with its fraction of the way
+    /// through the range in `[0.0, 1.0)`
+    InRange(f64),
+    /// Current tick at or above `tick_upper`: the position holds only token1
+    Above,
+}
+
+/// Where the current price sits within a position's tick range
+///
+/// A position is active while `tick_lower <= current_tick < tick_upper`, as in
+/// `Pool.swap`. Inside the range the fraction is linear in ticks,
+/// `(current_tick - tick_lower) / (tick_upper - tick_lower)`: 0.0 exactly at
+/// the lower tick, and values close to either end mean the position is about
+/// to leave the range. Outside it, the side is reported instead of a fraction.
+///
+/// # Arguments
+/// * `tick_lower` - Lower tick of the position
+/// * `tick_upper` - Upper tick of the position
+/// * `sqrt_price_x96` - Current sqrt price (Q64.96)
+///
+/// # Returns
+/// * `Ok(RangePosition)` - Side of the range, or the fraction through it
+/// * `Err(MathError)` - If the range is empty or the sqrt price is invalid
+pub fn position_in_range_fraction(
+    tick_lower: i32,
+    tick_upper: i32,
+    sqrt_price_x96: U256,
+) -> Result<RangePosition, MathError> {
+    if tick_lower >= tick_upper {
+        return Err(MathError::InvalidInput {
+            operation: "position_in_range_fraction".to_string(),
+            reason: "tick_lower must be below tick_upper".to_string(),
+            context: format!("tick_lower={}, tick_upper={}", tick_lower, tick_upper),
+        });
+    }
+
+    let current_tick = sqrt_price_to_tick(sqrt_price_x96)?;
+    if current_tick < tick_lower {
+        return Ok(RangePosition::Below);
+    }
+    if current_tick >= tick_upper {
+        return Ok(RangePosition::Above);
+    }
+
+    let fraction = (f64::from(current_tick) - f64::from(tick_lower))
+        / (f64::from(tick_upper) - f64::from(tick_lower));
+    Ok(RangePosition::InRange(fraction))
+}
+
+/// Time steps used to average the in-range probability over the holding period
+const COVERAGE_TIME_STEPS: u32 = 100;
+
+/// Error function (Abramowitz & Stegun 7.1.26, |error| < 1.5e-7)
+fn erf(x: f64) -> f64 {
+    let sign = x.signum();
+    let x = x.abs();
+    let t = 1.0 / (1.0 + 0.3275911 * x);
+    let poly = t
+        * (0.254829592
+            + t * (-0.284496736 + t * (1.421413741 + t * (-1.453152027 + t * 1.061405429))));
+    sign * (1.0 - poly * (-x * x).exp())
+}
+
+/// Expected fraction of a holding period a centered position spends in range
+///
+/// The log price follows a driftless random walk starting at the middle of the
+/// range, with one tick per basis point. At time `t` the price is in range with
+/// probability `erf(h / (sigma * sqrt(2t)))`, where `h` is the range half-width in
+/// ticks; the result averages this over the holding period. The price may
+/// leave and re-enter, so this is time in range, not survival probability.
+///
+/// # Arguments
+/// * `tick_lower` - Lower tick of the position
+/// * `tick_upper` - Upper tick of the position
+/// * `price_volatility_bps_per_day` - Daily price volatility (std dev) in basis points
+/// * `holding_period_days` - How long the position is held
+///
+/// # Returns
+/// * `f64` - Expected in-range fraction in `[0.0, 1.0]` (0.0 for an empty range)
+pub fn tick_range_coverage_probability(
+    tick_lower: i32,
+    tick_upper: i32,
+    price_volatility_bps_per_day: u32,
+    holding_period_days: u32,
+) -> f64 {
+    if tick_lower >= tick_upper {
+        return 0.0;
+    }
+    if price_volatility_bps_per_day == 0 || holding_period_days == 0 {
+        return 1.0;
+    }
+
+    let half_width = (f64::from(tick_upper) - f64::from(tick_lower)) / 2.0;
+    let sigma = f64::from(price_volatility_bps_per_day);
+    let period = f64::from(holding_period_days);
+
+    // Midpoint rule over (0, holding_period_days]
+    let total: f64 = (0..COVERAGE_TIME_STEPS)
+        .map(|step| {
+            let t = period * (f64::from(step) + 0.5) / f64::from(COVERAGE_TIME_STEPS);
+            erf(half_width / (sigma * (2.0 * t).sqrt()))
+        })
+        .sum();
+
+    (total / f64::from(COVERAGE_TIME_STEPS)).clamp(0.0, 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Pool at price 1.0 (tick 0) with the given active liquidity and fee
+    fn pool_at_price_1(liquidity: u128, fee_bps: u32) -> V3PoolState {
+        V3PoolState {
+            sqrt_price_x96: U256::from(79228162514264337593543950336u128),
+            liquidity,
+            tick: 0,
+            fee_bps: BasisPoints::new_const(fee_bps),
+        }
+    }
+
+    #[test]
+    fn test_tick_at_zero() {
+        let sqrt_ratio = get_sqrt_ratio_at_tick(0).unwrap();
+        assert_eq!(sqrt_ratio, U256::from(79228162514264337593543950336u128));
+    }
+
+    #[test]
+    fn test_tick_bounds() {
+        let min = get_sqrt_ratio_at_tick(MIN_TICK).unwrap();
+        let max = get_sqrt_ratio_at_tick(MAX_TICK).unwrap();
+
+        assert_eq!(min, U256::from(MIN_SQRT_RATIO));
+        assert_eq!(max, get_max_sqrt_ratio());
+        assert!(max > U256::zero());
+    }
+
+    #[test]
+    fn test_tick_out_of_bounds() {
+        let result = get_sqrt_ratio_at_tick(MIN_TICK - 1);
+        assert!(result.is_err());
+
+        let result = get_sqrt_ratio_at_tick(MAX_TICK + 1);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_mul_div_rounding_up_exact_division() {
+        // Test cases where division is exact (no rounding needed)
+        // 100 * 200 / 100 = 200 (exact)
+        let result =
+            mul_div_rounding_up(U256::from(100), U256::from(200), U256::from(100)).unwrap();
+        assert_eq!(result, U256::from(200));
+
+        // 50 * 60 / 10 = 300 (exact)
+        let result = mul_div_rounding_up(U256::from(50), U256::from(60), U256::from(10)).unwrap();
+        assert_eq!(result, U256::from(300));
+    }
+
+    #[test]
+    fn test_mul_div_rounding_up_requires_rounding() {
+        // Test cases where rounding up is required
+        // 100 * 201 / 100 = 201 (exact, but test rounding logic)
+        // 100 * 199 / 100 = 199 (exact)
+        // 100 * 201 / 200 = 100.5 -> rounds up to 101
+        let result =
+            mul_div_rounding_up(U256::from(100), U256::from(201), U256::from(200)).unwrap();
+        assert_eq!(result, U256::from(101));
+
+        // 7 * 3 / 2 = 10.5 -> rounds up to 11
+        let result = mul_div_rounding_up(U256::from(7), U256::from(3), U256::from(2)).unwrap();
+        assert_eq!(result, U256::from(11));
+
+        // 1 * 1 / 3 = 0.333... -> rounds up to 1
+        let result = mul_div_rounding_up(U256::from(1), U256::from(1), U256::from(3)).unwrap();
+        assert_eq!(result, U256::from(1));
+    }
+
+    #[test]
+    fn test_mul_div_rounding_up_edge_cases() {
+        // Zero multiplicand
+        let result = mul_div_rounding_up(U256::from(0), U256::from(100), U256::from(10)).unwrap();
+        assert_eq!(result, U256::from(0));
+
+        // Zero multiplicand (other direction)
+        let result = mul_div_rounding_up(U256::from(100), U256::from(0), U256::from(10)).unwrap();
+        assert_eq!(result, U256::from(0));
+
+        // Division by zero should error
+        let result = mul_div_rounding_up(U256::from(100), U256::from(200), U256::from(0));
+        assert!(result.is_err());
+        match result.unwrap_err() {
+            MathError::DivisionByZero { .. } => {}
+            _ => panic!("Expected DivisionByZero error"),
+        }
+    }
+
+    #[test]
+    fn test_mul_div_rounding_up_large_values() {
+        // Test with large values to ensure U512 arithmetic works
+        let large_a = U256::from_dec_str("1000000000000000000000000").unwrap(); // 1e21
+        let large_b = U256::from_dec_str("2000000000000000000000000").unwrap(); // 2e21
+        let denom = U256::from_dec_str("1000000000000000000000").unwrap(); // 1e18
+
+        // Result should be: (1e21 * 2e21) / 1e18 = 2e24
+        let result = mul_div_rounding_up(large_a, large_b, denom).unwrap();
+        let expected = U256::from_dec_str("2000000000000000000000000000").unwrap(); // 2e24
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_mul_div_rounding_up_vs_mul_div() {
+        // Compare rounding_up with regular mul_div
+        // For exact divisions, they should be the same
+        let a = U256::from(100);
+        let b = U256::from(200);
+        let denom = U256::from(100);
+
+        let regular = mul_div(a, b, denom).unwrap();
+        let rounded = mul_div_rounding_up(a, b, denom).unwrap();
+        assert_eq!(regular, rounded);
+
+        // For non-exact divisions, rounded should be >= regular
+        let a = U256::from(100);
+        let b = U256::from(201);
+        let denom = U256::from(200);
+
+        let regular = mul_div(a, b, denom).unwrap();
+        let rounded = mul_div_rounding_up(a, b, denom).unwrap();
+        assert!(rounded >= regular);
+        // In this case: regular = 100, rounded = 101
+        assert_eq!(regular, U256::from(100));
+        assert_eq!(rounded, U256::from(101));
+    }
+
+    #[test]
+    fn test_calculate_v3_amount_out_token0_to_token1_small() {
+        // Test Token0→Token1 with small amounts
+        let amount_in = U256::from(1000_000_000_000_000_000u128); // 0.001 ETH (18 decimals)
+        let sqrt_price_x96 = U256::from(79228162514264337593543950336u128); // Price = 1.0 (tick = 0)
+        let liquidity = 1_000_000_000_000_000_000_000u128; // 1000 tokens
+        let fee_bps = BasisPoints::new_const(300); // 0.3% fee
+
+        let result = calculate_v3_amount_out(
+            amount_in,
+            sqrt_price_x96,
+            liquidity,
+            fee_bps,
+            SwapDirection::Token0ToToken1,
+        )
+        .unwrap();
+
+        // Should get some token1 out (exact value depends on formula)
+        assert!(result > U256::zero());
+        assert!(result < amount_in); // Should be less than input due to fee
+    }
+
+    #[test]
+    fn test_calculate_v3_amount_out_token1_to_token0_small() {
+        // Test Token1→Token0 with small amounts
+        let amount_in = U256::from(1000_000_000_000_000_000u128); // 0.001 token1
+        let sqrt_price_x96 = U256::from(79228162514264337593543950336u128); // Price = 1.0
+        let liquidity = 1_000_000_000_000_000_000_000u128; // 1000 tokens
+        let fee_bps = BasisPoints::new_const(300); // 0.3% fee
+
+        let result = calculate_v3_amount_out(
+            amount_in,
+            sqrt_price_x96,
+            liquidity,
+            fee_bps,
+            SwapDirection::Token1ToToken0,
+        )
+        .unwrap();
+
+        // Should get some token0 out
+        assert!(result > U256::zero());
+        assert!(result < amount_in); // Should be less than input due to fee
+    }
+
+    #[test]
+    fn test_calculate_v3_amount_out_token0_to_token1_large() {
+        // Test Token0→Token1 with larger amounts
+        let amount_in = U256::from(100_000_000_000_000_000_000u128); // 100 tokens
+        let sqrt_price_x96 = U256::from(79228162514264337593543950336u128); // Price = 1.0
+        let liquidity = 10_000_000_000_000_000_000_000u128; // 10000 tokens
+        let fee_bps = BasisPoints::new_const(300); // 0.3% fee
+
+        let result = calculate_v3_amount_out(
+            amount_in,
+            sqrt_price_x96,
+            liquidity,
+            fee_bps,
+            SwapDirection::Token0ToToken1,
+        )
+        .unwrap();
+
+        assert!(result > U256::zero());
+        // With 0.3% fee, should get approximately 99.7% of input (but in token1)
+        // Since price = 1.0, should be close to amount_in_after_fee
+        let amount_after_fee = amount_in * U256::from(9970) / U256::from(10000);
+        // Result should be close to amount_after_fee (within reasonable rounding)
+        assert!(result <= amount_after_fee + U256::from(amount_after_fee.as_u128() / 100));
+        // Within 1%
+    }
+
+    #[test]
+    fn test_calculate_v3_amount_out_zero_input() {
+        // Test that zero input returns error
+        let sqrt_price_x96 = U256::from(79228162514264337593543950336u128);
+        let liquidity = 1_000_000_000_000_000_000_000u128;
+        let fee_bps = BasisPoints::new_const(300);
+
+        let result = calculate_v3_amount_out(
+            U256::zero(),
+            sqrt_price_x96,
+            liquidity,
+            fee_bps,
+            SwapDirection::Token0ToToken1,
+        );
+
+        assert!(result.is_err());
+        match result.unwrap_err() {
+            MathError::InvalidInput { .. } => {}
+            _ => panic!("Expected InvalidInput error"),
+        }
+    }
+
+    #[test]
+    fn test_calculate_v3_amount_out_zero_liquidity() {
+        // Test that zero liquidity returns error
+        let amount_in = U256::from(1000_000_000_000_000_000u128);
+        let sqrt_price_x96 = U256::from(79228162514264337593543950336u128);
+        let fee_bps = BasisPoints::new_const(300);
+
+        let result = calculate_v3_amount_out(
+            amount_in,
+            sqrt_price_x96,
+            0,
+            fee_bps,
+            SwapDirection::Token0ToToken1,
+        );
+
+        assert!(result.is_err());
+        match result.unwrap_err() {
+            MathError::InvalidInput { .. } => {}
+            _ => panic!("Expected InvalidInput error"),
+        }
+    }
+
+    #[test]
+    fn test_calculate_v3_amount_out_direction_consistency() {
+        // Property-based test: Swap token0→token1, then swap result token1→token0
+        // Should return approximately original amount (minus fees)
+        let original_amount = U256::from(1000_000_000_000_000_000u128); // 1 token
+        let sqrt_price_x96 = U256::from(79228162514264337593543950336u128); // Price = 1.0
+        let liquidity = 10_000_000_000_000_000_000_000u128; // 10000 tokens (high liquidity for minimal price impact)
+        let fee_bps = BasisPoints::new_const(300); // 0.3% fee
+
+        // First swap: token0 → token1
+        let token1_received = calculate_v3_amount_out(
+            original_amount,
+            sqrt_price_x96,
+            liquidity,
+            fee_bps,
+            SwapDirection::Token0ToToken1,
+        )
+        .unwrap();
+
+        assert!(token1_received > U256::zero());
+
+        // Get new sqrt price after first swap (simplified - in reality would need to calculate)
+        // For this test, we'll use a slightly different price to simulate the swap
+        // In a real implementation, we'd calculate the new price from the swap
+
+        // Second swap: token1 → token0 (reverse direction)
+        // Note: This is a simplified test - in reality the sqrt_price would have changed
+        // For property testing, we accept that with fees, we won't get exact original back
+        let token0_received = calculate_v3_amount_out(
+            token1_received,
+            sqrt_price_x96, // Using same price (simplified)
+            liquidity,
+            fee_bps,
+            SwapDirection::Token1ToToken0,
+        )
+        .unwrap();
+
+        // Due to fees (0.3% twice = ~0.6% total), we should get back less than original
+        // But should be within reasonable range (e.g., > 99% of original after fees)
+        let min_expected = original_amount * U256::from(9900) / U256::from(10000); // 99% of original
+        assert!(token0_received < original_amount); // Less due to fees
+                                                    // Note: This is a simplified property test - real swaps would have price impact
+    }
+
+    #[test]
+    fn test_calculate_v3_post_frontrun_state_token0_to_token1() {
+        // Test Token0→Token1 direction
+        let frontrun_amount = U256::from(1000_000_000_000_000_000u128); // 0.001 ETH
+        let sqrt_price_x96 = U256::from(79228162514264337593543950336u128); // Price = 1.0
+        let liquidity = 1_000_000_000_000_000_000_000u128; // 1000 tokens
+        let tick = 0;
+        let fee_bps = BasisPoints::new_const(300); // 0.3% fee
+
+        let (new_sqrt_price, new_tick) = calculate_v3_post_frontrun_state(
+            frontrun_amount,
+            sqrt_price_x96,
+            liquidity,
+            tick,
+            fee_bps,
+            SwapDirection::Token0ToToken1,
+        )
+        .unwrap();
+
+        // For zeroForOne, new sqrt price should be less than current
+        assert!(new_sqrt_price < sqrt_price_x96);
+        assert!(new_sqrt_price > U256::zero());
+        // New tick should be calculated correctly
+        assert!(new_tick <= tick); // For zeroForOne, tick decreases (price decreases)
+    }
+
+    #[test]
+    fn test_calculate_v3_post_frontrun_state_token1_to_token0() {
+        // Test Token1→Token0 direction
+        let frontrun_amount = U256::from(1000_000_000_000_000_000u128); // 0.001 token1
+        let sqrt_price_x96 = U256::from(79228162514264337593543950336u128); // Price = 1.0
+        let liquidity = 1_000_000_000_000_000_000_000u128; // 1000 tokens
+        let tick = 0;
+        let fee_bps = BasisPoints::new_const(300); // 0.3% fee
+
+        let (new_sqrt_price, new_tick) = calculate_v3_post_frontrun_state(
+            frontrun_amount,
+            sqrt_price_x96,
+            liquidity,
+            tick,
+            fee_bps,
+            SwapDirection::Token1ToToken0,
+        )
+        .unwrap();
+
+        // For oneForZero, new sqrt price should be greater than current
+        assert!(new_sqrt_price > sqrt_price_x96);
+        // New tick should be calculated correctly
+        assert!(new_tick >= tick); // For oneForZero, tick increases (price increases)
+    }
+
+    #[test]
+    fn test_calculate_v3_post_frontrun_state_consistency_with_amount_out() {
+        // Test that the sqrt price from post_frontrun_state matches what calculate_v3_amount_out would produce
+        let frontrun_amount = U256::from(1000_000_000_000_000_000u128);
+        let sqrt_price_x96 = U256::from(79228162514264337593543950336u128);
+        let liquidity = 1_000_000_000_000_000_000_000u128;
+        let tick = 0;
+        let fee_bps = BasisPoints::new_const(300);
+
+        // Calculate using post_frontrun_state
+        let (new_sqrt_price_from_state, _) = calculate_v3_post_frontrun_state(
+            frontrun_amount,
+            sqrt_price_x96,
+            liquidity,
+            tick,
+            fee_bps,
+            SwapDirection::Token0ToToken1,
+        )
+        .unwrap();
+
+        // Calculate amount_out to verify consistency
+        let amount_out = calculate_v3_amount_out(
+            frontrun_amount,
+            sqrt_price_x96,
+            liquidity,
+            fee_bps,
+            SwapDirection::Token0ToToken1,
+        )
+        .unwrap();
+
+        // Verify amount_out is positive (swap happened)
+        assert!(amount_out > U256::zero());
+
+        // The new sqrt price should be valid
+        assert!(new_sqrt_price_from_state > U256::zero());
+        assert!(new_sqrt_price_from_state < sqrt_price_x96); // For zeroForOne
+    }
+
+    #[test]
+    fn test_calculate_v3_post_frontrun_state_zero_input() {
+        // Test that zero input returns error
+        let sqrt_price_x96 = U256::from(79228162514264337593543950336u128);
+        let liquidity = 1_000_000_000_000_000_000_000u128;
+        let tick = 0;
+        let fee_bps = BasisPoints::new_const(300);
+
+        let result = calculate_v3_post_frontrun_state(
+            U256::zero(),
+            sqrt_price_x96,
+            liquidity,
+            tick,
+            fee_bps,
+            SwapDirection::Token0ToToken1,
+        );
+
+        assert!(result.is_err());
+        match result.unwrap_err() {
+            MathError::InvalidInput { .. } => {}
+            _ => panic!("Expected InvalidInput error"),
+        }
+    }
+
+    #[test]
+    fn test_calculate_v3_post_frontrun_state_zero_liquidity() {
+        // Test that zero liquidity returns error
+        let frontrun_amount = U256::from(1000_000_000_000_000_000u128);
+        let sqrt_price_x96 = U256::from(79228162514264337593543950336u128);
+        let tick = 0;
+        let fee_bps = BasisPoints::new_const(300);
+
+        let result = calculate_v3_post_frontrun_state(
+            frontrun_amount,
+            sqrt_price_x96,
+            0,
+            tick,
+            fee_bps,
+            SwapDirection::Token0ToToken1,
+        );
+
+        assert!(result.is_err());
+        match result.unwrap_err() {
+            MathError::InvalidInput { .. } => {}
+            _ => panic!("Expected InvalidInput error"),
+        }
+    }
+
+    #[test]
+    fn test_calculate_v3_post_frontrun_state_tick_calculation() {
+        // Test that tick is calculated correctly from new sqrt price
+        let frontrun_amount = U256::from(1000_000_000_000_000_000u128);
+        let sqrt_price_x96 = U256::from(79228162514264337593543950336u128); // tick = 0
+        let liquidity = 1_000_000_000_000_000_000_000u128;
+        let tick = 0;
+        let fee_bps = BasisPoints::new_const(300);
+
+        let (new_sqrt_price, new_tick) = calculate_v3_post_frontrun_state(
+            frontrun_amount,
+            sqrt_price_x96,
+            liquidity,
+            tick,
+            fee_bps,
+            SwapDirection::Token0ToToken1,
+        )
+        .unwrap();
+
+        // Verify tick is close to expected (within tolerance due to different calculation methods)
+        // calculate_v3_post_frontrun_state uses logarithmic approximation for speed
+        // sqrt_price_to_tick uses binary search for precision
+        // For MEV, speed > precision for small differences
+        let expected_tick = sqrt_price_to_tick(new_sqrt_price).unwrap();
+        let tick_diff = (new_tick - expected_tick).abs();
+        assert!(
+            tick_diff <= 15,
+            "new_tick {} differs from expected {} by more than 15",
+            new_tick,
+            expected_tick
+        );
+    }
+
+    #[test]
+    fn test_brents_method_convergence() {
+        // Test that Brent's method converges correctly
+        let victim_amount = U256::from(1000_000_000_000_000_000u128); // 1 token
+        let sqrt_price_x96 = U256::from(79228162514264337593543950336u128); // Price = 1.0
+        let liquidity = 10_000_000_000_000_000_000_000u128; // 10000 tokens
+        let tick = 0;
+        let fee_bps = BasisPoints::new_const(300); // 0.3% fee
+        let aave_fee_bps = BasisPoints::new_const(9); // 0.09% AAVE fee
+
+        let result = brents_method_v3_sandwich_optimization(
+            victim_amount,
+            sqrt_price_x96,
+            liquidity,
+            tick,
+            fee_bps,
+            aave_fee_bps,
+        );
+
+        assert!(
+            result.is_ok(),
+            "Brent's method should converge, got: {:?}",
+            result
+        );
+        let optimal_amount = result.unwrap();
+
+        // Optimal amount should be within bounds
+        assert!(optimal_amount >= U256::from(1000000000000000u128)); // >= min_flash_loan
+        assert!(optimal_amount <= victim_amount); // <= victim_amount
+
+        // Should be a reasonable value (not at boundaries)
+        assert!(optimal_amount > U256::from(1000000000000000u128) * U256::from(2));
+        assert!(optimal_amount < victim_amount);
+    }
+
+    #[test]
+    fn test_brents_method_golden_section_step() {
+        // Test that golden section step calculation is correct
+        // This is an indirect test - we verify the algorithm works correctly
+        let victim_amount = U256::from(5000_000_000_000_000_000u128); // 5 tokens
+        let sqrt_price_x96 = U256::from(79228162514264337593543950336u128);
+        let liquidity = 10_000_000_000_000_000_000_000u128;
+        let tick = 0;
+        let fee_bps = BasisPoints::new_const(300);
+        let aave_fee_bps = BasisPoints::new_const(9);
+
+        let result1 = brents_method_v3_sandwich_optimization(
+            victim_amount,
+            sqrt_price_x96,
+            liquidity,
+            tick,
+            fee_bps,
+            aave_fee_bps,
+        );
+
+        assert!(result1.is_ok());
+
+        // Test with different victim amount to verify algorithm adapts
+        let victim_amount2 = U256::from(2000_000_000_000_000_000u128); // 2 tokens
+        let result2 = brents_method_v3_sandwich_optimization(
+            victim_amount2,
+            sqrt_price_x96,
+            liquidity,
+            tick,
+            fee_bps,
+            aave_fee_bps,
+        );
+
+        assert!(result2.is_ok());
+        let optimal1 = result1.unwrap();
+        let optimal2 = result2.unwrap();
+
+        // Optimal amounts should be proportional to victim amounts
+        // (not exactly, but should be in reasonable range)
+        assert!(
+            optimal1 > optimal2,
+            "Larger victim amount should yield larger optimal amount"
+        );
+    }
+
+    #[test]
+    fn test_brents_method_edge_cases() {
+        let sqrt_price_x96 = U256::from(79228162514264337593543950336u128);
+        let liquidity = 10_000_000_000_000_000_000_000u128;
+        let tick = 0;
+        let fee_bps = BasisPoints::new_const(300);
+        let aave_fee_bps = BasisPoints::new_const(9);
+
+        // Test with minimum victim amount (close to min_flash_loan)
+        let min_victim = U256::from(2000000000000000u128); // 0.002 tokens (just above min_flash_loan)
+        let result = brents_method_v3_sandwich_optimization(
+            min_victim,
+            sqrt_price_x96,
+            liquidity,
+            tick,
+            fee_bps,
+            aave_fee_bps,
+        );
+
+        // Should either succeed or return a clear error
+        match result {
+            Ok(optimal) => {
+                assert!(optimal >= U256::from(1000000000000000u128));
+                assert!(optimal <= min_victim);
+            }
+            Err(e) => {
+                // If it fails, should be a clear error (not a panic)
+                match e {
+                    MathError::InvalidInput { .. } => {} // Expected for edge cases
+                    _ => panic!("Unexpected error type: {:?}", e),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_brents_method_input_validation() {
+        let sqrt_price_x96 = U256::from(79228162514264337593543950336u128);
+        let liquidity = 10_000_000_000_000_000_000_000u128;
+        let tick = 0;
+        let fee_bps = BasisPoints::new_const(300);
+        let aave_fee_bps = BasisPoints::new_const(9);
+
+        // Test zero victim amount
+        // Test zero victim amount (causes underflow since min_flash_loan > 0)
+        let result = brents_method_v3_sandwich_optimization(
+            U256::zero(),
+            sqrt_price_x96,
+            liquidity,
+            tick,
+            fee_bps,
+            aave_fee_bps,
+        );
+        assert!(result.is_err(), "Should fail with zero victim amount");
+        // Returns Overflow error due to b - a underflow (mislabeled, but correct behavior)
+
+        // Test very small victim amount (less than min_flash_loan causes underflow)
+        let result = brents_method_v3_sandwich_optimization(
+            U256::from(1), // 1 wei, less than min_flash_loan
+            sqrt_price_x96,
+            liquidity,
+            tick,
+            fee_bps,
+            aave_fee_bps,
+        );
+        assert!(result.is_err(), "Should fail with very small victim amount");
+
+        // Test invalid sqrt_price
+        let result = brents_method_v3_sandwich_optimization(
+            U256::from(1000_000_000_000_000_000u128),
+            U256::zero(),
+            liquidity,
+            tick,
+            fee_bps,
+            aave_fee_bps,
+        );
+        assert!(result.is_err());
+        match result.unwrap_err() {
+            MathError::InvalidInput { .. } => {}
+            _ => panic!("Expected InvalidInput error for invalid sqrt_price"),
+        }
+    }
+
+    #[test]
+    fn test_brents_method_algorithm_correctness() {
+        // Test that algorithm finds a reasonable optimal point
+        // by comparing results for different scenarios
+        let sqrt_price_x96 = U256::from(79228162514264337593543950336u128);
+        let liquidity = 10_000_000_000_000_000_000_000u128;
+        let tick = 0;
+        let fee_bps = BasisPoints::new_const(300);
+        let aave_fee_bps = BasisPoints::new_const(9);
+
+        // Test with moderate victim amount
+        let victim_amount = U256::from(1000_000_000_000_000_000u128); // 1 token
+        let result = brents_method_v3_sandwich_optimization(
+            victim_amount,
+            sqrt_price_x96,
+            liquidity,
+            tick,
+            fee_bps,
+            aave_fee_bps,
+        );
+
+        assert!(result.is_ok());
+        let optimal = result.unwrap();
+
+        // Verify optimal point is within bounds
+        assert!(optimal >= U256::from(1000000000000000u128));
+        assert!(optimal <= victim_amount);
+
+        // Verify that the optimal point produces a profit (or at least doesn't lose money beyond fees)
+        // This is a sanity check - the actual profit calculation is in calculate_v3_sandwich_profit
+        let profit = calculate_v3_sandwich_profit(
+            optimal,
+            victim_amount,
+            sqrt_price_x96,
+            liquidity,
+            tick,
+            fee_bps,
+            aave_fee_bps,
+        );
+
+        // Profit calculation should succeed
+        assert!(profit.is_ok());
+    }
+
+    #[test]
+    fn test_sqrt_price_to_tick_newton_method_correctness() {
+        // Test that Newton's method produces correct results
+        // Test various sqrt_price values and verify against get_sqrt_ratio_at_tick
+
+        // Test tick = 0
+        let sqrt_price_0 = U256::from(79228162514264337593543950336u128); // 2^96
+        let tick_0 = sqrt_price_to_tick(sqrt_price_0).unwrap();
+        assert_eq!(tick_0, 0);
+        let calculated_sqrt_0 = get_sqrt_ratio_at_tick(tick_0).unwrap();
+        assert_eq!(calculated_sqrt_0, sqrt_price_0);
+
+        // Test MIN_TICK
+        let sqrt_price_min = U256::from(MIN_SQRT_RATIO);
+        let tick_min = sqrt_price_to_tick(sqrt_price_min).unwrap();
+        assert_eq!(tick_min, MIN_TICK);
+        let calculated_sqrt_min = get_sqrt_ratio_at_tick(tick_min).unwrap();
+        assert_eq!(calculated_sqrt_min, sqrt_price_min);
+
+        // Test MAX_TICK
+        let sqrt_price_max = get_max_sqrt_ratio();
+        let tick_max = sqrt_price_to_tick(sqrt_price_max).unwrap();
+        assert_eq!(tick_max, MAX_TICK);
+
+        // Test positive ticks
+        for test_tick in [1, 10, 100, 1000, 10000, 100000] {
+            let sqrt_price = get_sqrt_ratio_at_tick(test_tick).unwrap();
+            let calculated_tick = sqrt_price_to_tick(sqrt_price).unwrap();
+            // PROTOCOL PARITY: Exact match required (strict flooring)
+            assert_eq!(
+                calculated_tick, test_tick,
+                "Tick mismatch: expected {}, got {} for sqrt_price={}",
+                test_tick, calculated_tick, sqrt_price
+            );
+            // Verify the calculated tick produces a sqrt_price close to target
+            let calculated_sqrt = get_sqrt_ratio_at_tick(calculated_tick).unwrap();
+            let diff = if calculated_sqrt >= sqrt_price {
+                calculated_sqrt - sqrt_price
+            } else {
+                sqrt_price - calculated_sqrt
+            };
+            // Allow 1 part per million difference
+            assert!(
+                diff < sqrt_price / U256::from(1_000_000),
+                "Sqrt price mismatch: expected {}, got {} (diff={})",
+                sqrt_price,
+                calculated_sqrt,
+                diff
+            );
+        }
+
+        // Test negative ticks
+        for test_tick in [-1, -10, -100, -1000, -10000, -100000] {
+            let sqrt_price = get_sqrt_ratio_at_tick(test_tick).unwrap();
+            let calculated_tick = sqrt_price_to_tick(sqrt_price).unwrap();
+            // PROTOCOL PARITY: Exact match required (strict flooring)
+            assert_eq!(
+                calculated_tick, test_tick,
+                "Tick mismatch: expected {}, got {} for sqrt_price={}",
+                test_tick, calculated_tick, sqrt_price
+            );
+        }
+    }
+
+    #[test]
+    fn test_sqrt_price_to_tick_newton_method_convergence() {
+        // Test that Newton's method converges in reasonable iterations
+        let sqrt_price = U256::from(79228162514264337593543950336u128); // tick = 0
+        let result = sqrt_price_to_tick(sqrt_price);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), 0);
+
+        // Test with various sqrt prices
+        let test_cases = vec![
+            (U256::from(79228162514264337593543950336u128), 0), // tick = 0
+            (U256::from(MIN_SQRT_RATIO), MIN_TICK),
+            (get_max_sqrt_ratio(), MAX_TICK),
+        ];
+
+        for (sqrt_price, expected_tick) in test_cases {
+            let result = sqrt_price_to_tick(sqrt_price);
+            assert!(
+                result.is_ok(),
+                "sqrt_price_to_tick failed for sqrt_price={}",
+                sqrt_price
+            );
+            let tick = result.unwrap();
+            assert_eq!(
+                tick, expected_tick,
+                "Tick mismatch for sqrt_price={}",
+                sqrt_price
+            );
+        }
+    }
+
+    #[test]
+    fn test_sqrt_price_to_tick_newton_method_edge_cases() {
+        // Test edge cases
+        let sqrt_price_0 = U256::from(79228162514264337593543950336u128);
+        let tick_0 = sqrt_price_to_tick(sqrt_price_0).unwrap();
+        assert_eq!(tick_0, 0);
+
+        // Test just above MIN_SQRT_RATIO
+        let sqrt_price_min_plus = U256::from(MIN_SQRT_RATIO)
+            .checked_add(U256::from(1))
+            .unwrap();
+        let tick_min_plus = sqrt_price_to_tick(sqrt_price_min_plus).unwrap();
+        assert!(tick_min_plus >= MIN_TICK);
+        assert!(tick_min_plus <= MIN_TICK + 10); // Should be close to MIN_TICK
+
+        // Test just below MAX_SQRT_RATIO
+        let sqrt_price_max_minus = get_max_sqrt_ratio().checked_sub(U256::from(1)).unwrap();
+        let tick_max_minus = sqrt_price_to_tick(sqrt_price_max_minus).unwrap();
+        assert!(tick_max_minus >= MAX_TICK - 10); // Should be close to MAX_TICK
+        assert!(tick_max_minus <= MAX_TICK);
+    }
+
+    #[test]
+    fn test_sqrt_price_to_tick_newton_method_roundtrip() {
+        // Test roundtrip: tick -> sqrt_price -> tick
+        let test_ticks = vec![
+            0, MIN_TICK, MAX_TICK, 1, -1, 100, -100, 1000, -1000, 10000, -10000,
+        ];
+
+        for original_tick in test_ticks {
+            let sqrt_price = get_sqrt_ratio_at_tick(original_tick).unwrap();
+            let calculated_tick = sqrt_price_to_tick(sqrt_price).unwrap();
+
+            // PROTOCOL PARITY: Exact match required (strict flooring)
+            assert_eq!(
+                calculated_tick, original_tick,
+                "Roundtrip failed: original_tick={}, calculated_tick={}, sqrt_price={}",
+                original_tick, calculated_tick, sqrt_price
+            );
+
+            // Verify the calculated tick produces a sqrt_price close to original
+            let calculated_sqrt = get_sqrt_ratio_at_tick(calculated_tick).unwrap();
+            let diff = if calculated_sqrt >= sqrt_price {
+                calculated_sqrt - sqrt_price
+            } else {
+                sqrt_price - calculated_sqrt
+            };
+            // Allow 1 part per million difference
+            assert!(
+                diff < sqrt_price / U256::from(1_000_000),
+                "Sqrt price mismatch in roundtrip: original_tick={}, calculated_tick={}, original_sqrt={}, calculated_sqrt={}, diff={}",
+                original_tick, calculated_tick, sqrt_price, calculated_sqrt, diff
+            );
+        }
+    }
+
+    #[test]
+    fn test_sqrt_price_to_tick_newton_method_fallback() {
+        // Test that fallback to binary search works if Newton's method fails
+        // This is hard to test directly, but we can verify the function always returns a valid result
+        let sqrt_price = U256::from(79228162514264337593543950336u128);
+        let result = sqrt_price_to_tick(sqrt_price);
+        assert!(result.is_ok());
+        let tick = result.unwrap();
+        assert!(tick >= MIN_TICK);
+        assert!(tick <= MAX_TICK);
+
+        // Verify the result is correct
+        let calculated_sqrt = get_sqrt_ratio_at_tick(tick).unwrap();
+        let diff = if calculated_sqrt >= sqrt_price {
+            calculated_sqrt - sqrt_price
+        } else {
+            sqrt_price - calculated_sqrt
+        };
+        // Should be very close (within 1 part per million)
+        assert!(diff < sqrt_price / U256::from(1_000_000));
+    }
+
+    #[test]
+    fn test_get_amount0_delta_rounding() {
+        // Test rounding direction matters
+        let sqrt_a = U256::from(79228162514264337593543950336u128); // tick 0
+        let sqrt_b = U256::from(79236108205323166380068368726u128); // tick 1
+        let liquidity = 1000000u128;
+
+        let round_down = get_amount0_delta(sqrt_a, sqrt_b, liquidity, false).unwrap();
+        let round_up = get_amount0_delta(sqrt_a, sqrt_b, liquidity, true).unwrap();
+
+        // Round up should be >= round down
+        assert!(round_up >= round_down, "round_up ({}) should be >= round_down ({})", round_up, round_down);
+        
+        // For non-exact divisions, round_up should be exactly 1 more than round_down
+        // (unless division is exact, in which case they're equal)
+        if round_up != round_down {
+            assert_eq!(round_up, round_down + U256::from(1), "round_up should be exactly 1 more than round_down");
+        }
+    }
+
+    #[test]
+    fn test_get_amount1_delta_rounding() {
+        // Test rounding direction matters
+        let sqrt_a = U256::from(79228162514264337593543950336u128); // tick 0
+        let sqrt_b = U256::from(79236108205323166380068368726u128); // tick 1
+        let liquidity = 1000000u128;
+
+        let round_down = get_amount1_delta(sqrt_a, sqrt_b, liquidity, false).unwrap();
+        let round_up = get_amount1_delta(sqrt_a, sqrt_b, liquidity, true).unwrap();
+
+        // Round up should be >= round down
+        assert!(round_up >= round_down, "round_up ({}) should be >= round_down ({})", round_up, round_down);
+        
+        // For non-exact divisions, round_up should be exactly 1 more than round_down
+        if round_up != round_down {
+            assert_eq!(round_up, round_down + U256::from(1), "round_up should be exactly 1 more than round_down");
+        }
+    }
+
+    #[test]
+    fn test_sqrt_price_to_tick_strict_flooring() {
+        // Test that sqrt_price_to_tick uses strict flooring (not nearest)
+        // For a sqrt price exactly at tick boundary, should return that tick
+        let tick_0_sqrt = U256::from(79228162514264337593543950336u128);
+        let tick_1_sqrt = get_sqrt_ratio_at_tick(1).unwrap();
+        
+        // Price exactly at tick 0
+        let tick = sqrt_price_to_tick(tick_0_sqrt).unwrap();
+        assert_eq!(tick, 0, "Price at tick 0 should return tick 0");
+        
+        // Price just below tick 1 should return tick 0 (flooring)
+        let just_below_tick_1 = tick_1_sqrt - U256::from(1);
+        let tick = sqrt_price_to_tick(just_below_tick_1).unwrap();
+        assert_eq!(tick, 0, "Price just below tick 1 should return tick 0 (flooring)");
+        
+        // Price exactly at tick 1 should return tick 1
+        let tick = sqrt_price_to_tick(tick_1_sqrt).unwrap();
+        assert_eq!(tick, 1, "Price at tick 1 should return tick 1");
+    }
+
+    #[test]
+    fn test_calculate_v3_amount_out_different_prices() {
+        // Test with different sqrt prices to verify formula works across price ranges
+        let amount_in = U256::from(1000_000_000_000_000_000u128);
+        let liquidity = 1_000_000_000_000_000_000_000u128;
+        let fee_bps = BasisPoints::new_const(300);
+
+        // Test at different price points (reasonable prices, not extreme boundaries)
+        // Extreme prices (MIN/MAX) can cause overflows or zero outputs due to precision limits
+        let prices = vec![
+            get_sqrt_ratio_at_tick(-50000).unwrap(), // Low price (tick -50000)
+            U256::from(79228162514264337593543950336u128), // Price = 1.0 (tick 0)
+            get_sqrt_ratio_at_tick(50000).unwrap(),  // High price (tick 50000)
+        ];
+
+        for sqrt_price in prices {
+            // Token0→Token1
+            let result0to1 = calculate_v3_amount_out(
+                amount_in,
+                sqrt_price,
+                liquidity,
+                fee_bps,
+                SwapDirection::Token0ToToken1,
+            );
+            assert!(
+                result0to1.is_ok(),
+                "Token0ToToken1 failed at sqrt_price={}: {:?}",
+                sqrt_price,
+                result0to1
+            );
+            assert!(
+                result0to1.unwrap() > U256::zero(),
+                "Token0ToToken1 returned zero at sqrt_price={}",
+                sqrt_price
+            );
+
+            // Token1→Token0
+            let result1to0 = calculate_v3_amount_out(
+                amount_in,
+                sqrt_price,
+                liquidity,
+                fee_bps,
+                SwapDirection::Token1ToToken0,
+            );
+            assert!(
+                result1to0.is_ok(),
+                "Token1ToToken0 failed at sqrt_price={}: {:?}",
+                sqrt_price,
+                result1to0
+            );
+            assert!(
+                result1to0.unwrap() > U256::zero(),
+                "Token1ToToken0 returned zero at sqrt_price={}",
+                sqrt_price
+            );
+        }
+    }
+
+    #[test]
+    fn test_find_msb_u256() {
+        assert_eq!(find_msb_u256(U256::from(1)), 0);
+        assert_eq!(find_msb_u256(U256::from(2)), 1);
+        assert_eq!(find_msb_u256(U256::from(256)), 8);
+        assert_eq!(find_msb_u256(U256::from(1u128) << 96), 96);
+        assert_eq!(find_msb_u256(U256::zero()), 0);
+        assert_eq!(find_msb_u256(U256::MAX), 255);
+    }
+
+    #[test]
+    #[cfg(target_arch = "x86_64")]
+    fn test_find_msb_u256_simd_matches_portable() {
+        if !(is_x86_feature_detected!("bmi1") && is_x86_feature_detected!("lzcnt")) {
+            return;
+        }
+
+        let values = [
+            U256::zero(),
+            U256::one(),
+            U256::from(u64::MAX),
+            U256::from(u64::MAX) + U256::one(),
+            U256::from(MIN_SQRT_RATIO),
+            get_max_sqrt_ratio(),
+            U256::one() << 200,
+            U256::MAX,
+        ];
+        for value in values {
+            // SAFETY: features checked above
+            let simd = unsafe { find_msb_u256_simd(value) };
+            assert_eq!(simd, find_msb_u256_portable(value), "value={}", value);
+        }
+    }
+
+    #[test]
+    fn test_log2_approx() {
+        // In Q64.96 format: 2^96 = 1.0
+        // log2_approx takes a Q64.96 value and returns log2 in Q64.64 format
+
+        // Test log2(2^96) = log2(1.0 in Q64.96) = 0
+        let value_one = U256::from(1u128) << 96;
+        let result = log2_approx(value_one);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), 0, "log2(1.0) should be 0");
+
+        // Test log2(2^97) = log2(2.0 in Q64.96) = 1.0 in Q64.64 = 2^64
+        let value_two = U256::from(1u128) << 97;
+        let result = log2_approx(value_two);
+        assert!(result.is_ok());
+        let log2_val = result.unwrap();
+        assert_eq!(
+            log2_val,
+            1i128 << 64,
+            "log2(2.0) should be 1.0 (2^64 in Q64.64)"
+        );
+
+        // Test log2(2^95) = log2(0.5 in Q64.96) = -1.0 in Q64.64 = -2^64
+        let value_half = U256::from(1u128) << 95;
+        let result = log2_approx(value_half);
+        assert!(result.is_ok());
+        let log2_val = result.unwrap();
+        assert_eq!(
+            log2_val,
+            -(1i128 << 64),
+            "log2(0.5) should be -1.0 (-2^64 in Q64.64)"
+        );
+
+        // Test log2(integer 1) = log2(2^-96 in Q64.96) = -96.0 in Q64.64
+        // This is a very small number: 1/2^96
+        let result = log2_approx(U256::from(1));
+        assert!(result.is_ok());
+        let expected = -96i128 << 64;
+        assert_eq!(
+            result.unwrap(),
+            expected,
+            "log2(2^-96) should be -96.0 in Q64.64"
+        );
+
+        // Test zero returns error
+        let result = log2_approx(U256::zero());
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_log2_precise() {
+        // In Q64.96 format: 2^96 = 1.0
+        // log2_precise takes a Q64.96 value and returns log2 in Q64.64 format
+
+        // Test log2(2^96) = log2(1.0 in Q64.96) = 0
+        let value_one = U256::from(1u128) << 96;
+        let result = log2_precise(value_one);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), 0, "log2(1.0) should be 0");
+
+        // Test log2(2^97) = log2(2.0 in Q64.96) = 1.0 in Q64.64 = 2^64
+        let value_two = U256::from(1u128) << 97;
+        let result = log2_precise(value_two);
+        assert!(result.is_ok());
+        let log2_val = result.unwrap();
+        // Allow small error from iterative refinement
+        let expected = 1i128 << 64;
+        assert!(
+            (log2_val - expected).abs() < (1i128 << 50),
+            "log2(2.0) should be ~1.0 (2^64 in Q64.64), got {}",
+            log2_val
+        );
+
+        // Test log2(2^95) = log2(0.5 in Q64.96) = -1.0 in Q64.64 = -2^64
+        let value_half = U256::from(1u128) << 95;
+        let result = log2_precise(value_half);
+        assert!(result.is_ok());
+        let log2_val = result.unwrap();
+        let expected = -(1i128 << 64);
+        assert!(
+            (log2_val - expected).abs() < (1i128 << 50),
+            "log2(0.5) should be ~-1.0 (-2^64 in Q64.64), got {}",
+            log2_val
+        );
+
+        // Test log2(integer 1) = log2(2^-96 in Q64.96) = -96.0 in Q64.64
+        let result = log2_precise(U256::from(1));
+        assert!(result.is_ok());
+        let expected = -96i128 << 64;
+        let log2_val = result.unwrap();
+        assert!(
+            (log2_val - expected).abs() < (1i128 << 50),
+            "log2(2^-96) should be ~-96.0 in Q64.64, got {}",
+            log2_val
+        );
+
+        // Test zero returns error
+        let result = log2_precise(U256::zero());
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_calculate_price_ratio() {
+        // Test ratio = 1.0 (same price)
+        let sqrt_price = U256::from(79228162514264337593543950336u128); // tick = 0
+        let result = calculate_price_ratio(sqrt_price, sqrt_price);
+        assert!(result.is_ok());
+        // Ratio should be approximately 2^64 (1.0 in Q128.128)
+        assert!(result.unwrap() >= U256::from(1u128) << 63);
+
+        // Test ratio > 1.0 (price increased)
+        let new_price = sqrt_price.checked_mul(U256::from(2)).unwrap();
+        let result = calculate_price_ratio(new_price, sqrt_price);
+        assert!(result.is_ok());
+        // Ratio should be approximately 2 * 2^64
+        assert!(result.unwrap() > U256::from(1u128) << 64);
+
+        // Test zero old_price returns error
+        let result = calculate_price_ratio(sqrt_price, U256::zero());
+        assert!(result.is_err());
+
+        // Test zero new_price returns error
+        let result = calculate_price_ratio(U256::zero(), sqrt_price);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_calculate_price_ratio_fast_matches_u512() {
+        let sqrt_price = U256::from(79228162514264337593543950336u128); // tick = 0
+        let cases = [
+            (sqrt_price, sqrt_price),
+            (sqrt_price * U256::from(2), sqrt_price),
+            (sqrt_price / U256::from(3), sqrt_price),
+            (sqrt_price + U256::from(12345u64), sqrt_price),
+            // Above 2^128 takes the U512 fallback
+            (U256::from(1u128) << 150, U256::from(1u128) << 149),
+        ];
+
+        for (new_price, old_price) in cases {
+            assert_eq!(
+                calculate_price_ratio_fast(new_price, old_price).unwrap(),
+                calculate_price_ratio(new_price, old_price).unwrap()
+            );
+        }
+
+        assert!(calculate_price_ratio_fast(sqrt_price, U256::zero()).is_err());
+        assert!(calculate_price_ratio_fast(U256::zero(), sqrt_price).is_err());
+    }
+
+    #[test]
+    fn test_calculate_tick_delta_from_ratio() {
+        // Test ratio = 1.0 → tick_delta = 0
+        let ratio_1 = U256::from(1u128) << 64; // 1.0 in Q64.64
+        let result = calculate_tick_delta_from_ratio(ratio_1);
+        assert!(result.is_ok(), "ratio=1.0 failed: {:?}", result);
+        // Should be approximately 0 (may have small rounding error)
+        let tick_delta = result.unwrap();
+        assert!(
+            tick_delta.abs() <= 1,
+            "ratio=1.0 should give tick_delta ~0, got {}",
+            tick_delta
+        );
+
+        // Test ratio corresponding to +1 tick
+        // For tick +1: sqrt_price = 1.0001^(1/2) ≈ 1.00005
+        // Ratio ≈ 1.00005, which should give tick_delta ≈ 1
+        let sqrt_price_0 = U256::from(79228162514264337593543950336u128); // tick = 0
+        let sqrt_price_1 = get_sqrt_ratio_at_tick(1).unwrap();
+        let ratio = calculate_price_ratio(sqrt_price_1, sqrt_price_0).unwrap();
+        let result = calculate_tick_delta_from_ratio(ratio);
+        assert!(result.is_ok(), "ratio for +1 tick failed: {:?}", result);
+        let tick_delta = result.unwrap();
+        // Should be 1 (or 0 if rounding down, which is correct behavior)
+        assert!(
+            tick_delta >= 0 && tick_delta <= 1,
+            "tick_delta for +1 should be 0 or 1, got {}",
+            tick_delta
+        );
+
+        // Test ratio corresponding to -1 tick
+        let sqrt_price_minus1 = get_sqrt_ratio_at_tick(-1).unwrap();
+        let ratio = calculate_price_ratio(sqrt_price_minus1, sqrt_price_0).unwrap();
+        let result = calculate_tick_delta_from_ratio(ratio);
+        assert!(result.is_ok(), "ratio for -1 tick failed: {:?}", result);
+        let tick_delta = result.unwrap();
+        // Should be -1 (or 0 if rounding up, which is correct behavior)
+        assert!(
+            tick_delta >= -1 && tick_delta <= 0,
+            "tick_delta for -1 should be -1 or 0, got {}",
+            tick_delta
+        );
+    }
+
+    #[test]
+    fn test_calculate_tick_delta_directional_rounding() {
+        // Test positive tick_delta rounds DOWN (stays on current tick until boundary crossed)
+        // Create a ratio that gives tick_delta = 0.7
+        // This should round DOWN to 0 (haven't crossed next tick)
+        let sqrt_price_0 = U256::from(79228162514264337593543950336u128); // tick = 0
+                                                                          // Use a price between tick 0 and tick 1
+        let sqrt_price_half = sqrt_price_0
+            .checked_add((get_sqrt_ratio_at_tick(1).unwrap() - sqrt_price_0) / U256::from(2))
+            .unwrap();
+        let ratio = calculate_price_ratio(sqrt_price_half, sqrt_price_0).unwrap();
+        let result = calculate_tick_delta_from_ratio(ratio);
+        assert!(result.is_ok());
+        let tick_delta = result.unwrap();
+        // Should round DOWN to 0 (positive delta, haven't crossed boundary)
+        assert_eq!(tick_delta, 0);
+
+        // Test negative tick_delta rounds DOWN (floor toward -infinity)
+        // This matches Uniswap V3's getTickAtSqrtRatio which floors the tick
+        // Create a ratio that gives tick_delta ≈ -0.5
+        let sqrt_price_minus_half = sqrt_price_0
+            .checked_sub((sqrt_price_0 - get_sqrt_ratio_at_tick(-1).unwrap()) / U256::from(2))
+            .unwrap();
+        let ratio = calculate_price_ratio(sqrt_price_minus_half, sqrt_price_0).unwrap();
+        let result = calculate_tick_delta_from_ratio(ratio);
+        assert!(result.is_ok());
+        let tick_delta = result.unwrap();
+        // Floor division: -0.5 floors to -1
+        // This is correct per Uniswap V3 semantics
+        assert!(
+            tick_delta == -1 || tick_delta == 0,
+            "tick_delta should be -1 or 0, got {}",
+            tick_delta
+        );
+    }
+
+    #[test]
+    fn test_calculate_v3_post_frontrun_state_tick_delta() {
+        // Test that tick delta calculation works correctly in calculate_v3_post_frontrun_state
+        let frontrun_amount = U256::from(1000_000_000_000_000_000u128); // 0.001 token
+        let sqrt_price_x96 = U256::from(79228162514264337593543950336u128); // tick = 0
+        let liquidity = 1_000_000_000_000_000_000_000u128; // 1000 tokens
+        let tick = 0;
+        let fee_bps = BasisPoints::new_const(300);
+
+        // Token0ToToken1 direction
+        // Selling token0 for token1 -> more token0 in pool -> price of token0 decreases
+        // -> sqrt_price decreases -> tick decreases
+        let (new_sqrt_price, new_tick) = calculate_v3_post_frontrun_state(
+            frontrun_amount,
+            sqrt_price_x96,
+            liquidity,
+            tick,
+            fee_bps,
+            SwapDirection::Token0ToToken1,
+        )
+        .unwrap();
+
+        // Verify new_tick is calculated correctly
+        // For Token0ToToken1: tick should decrease (or stay same for small swap)
+        assert!(
+            new_tick <= tick,
+            "Token0ToToken1: new_tick {} should be <= tick {}",
+            new_tick,
+            tick
+        );
+        assert!(
+            new_tick >= tick - 10,
+            "new_tick {} too far from tick {}",
+            new_tick,
+            tick
+        );
+
+        // Verify new_sqrt_price < old_sqrt_price (price decreased)
+        assert!(
+            new_sqrt_price < sqrt_price_x96,
+            "Token0ToToken1: sqrt_price should decrease"
+        );
+
+        // Token1ToToken0 direction
+        // Selling token1 for token0 -> more token1 in pool -> price of token0 increases
+        // -> sqrt_price increases -> tick increases
+        let (new_sqrt_price2, new_tick2) = calculate_v3_post_frontrun_state(
+            frontrun_amount,
+            sqrt_price_x96,
+            liquidity,
+            tick,
+            fee_bps,
+            SwapDirection::Token1ToToken0,
+        )
+        .unwrap();
+
+        // Verify new_tick is calculated correctly
+        // For Token1ToToken0: tick should increase (or stay same for small swap)
+        assert!(
+            new_tick2 >= tick,
+            "Token1ToToken0: new_tick {} should be >= tick {}",
+            new_tick2,
+            tick
+        );
+        assert!(
+            new_tick2 <= tick + 10,
+            "new_tick {} too far from tick {}",
+            new_tick2,
+            tick
+        );
+
+        // Verify new_sqrt_price > old_sqrt_price (price increased)
+        assert!(
+            new_sqrt_price2 > sqrt_price_x96,
+            "Token1ToToken0: sqrt_price should increase"
+        );
+    }
+
+    #[test]
+    fn test_calculate_v3_post_frontrun_state_stays_on_tick_until_boundary() {
+        // Test that we stay on current tick until boundary is crossed
+        let sqrt_price_x96 = U256::from(79228162514264337593543950336u128); // tick = 0
+        let liquidity = 1_000_000_000_000_000_000_000_000u128; // Very large liquidity
+        let tick = 0;
+        let fee_bps = BasisPoints::new_const(300);
+
+        // Very small swap that shouldn't cross tick boundary significantly
+        let very_small_amount = U256::from(1_000_000_000u128); // Very small
+        let (new_sqrt_price, new_tick) = calculate_v3_post_frontrun_state(
+            very_small_amount,
+            sqrt_price_x96,
+            liquidity,
+            tick,
+            fee_bps,
+            SwapDirection::Token0ToToken1,
+        )
+        .unwrap();
+
+        // For Token0ToToken1: price decreases, tick may decrease by 0 or 1
+        // Due to floor rounding in tick calculation, even tiny moves can show as -1
+        assert!(
+            new_tick <= tick,
+            "Token0ToToken1: tick should decrease or stay same"
+        );
+        assert!(
+            new_tick >= tick - 1,
+            "tick should not move more than 1 for tiny swap"
+        );
+
+        // Price should have decreased (Token0ToToken1)
+        assert!(
+            new_sqrt_price < sqrt_price_x96,
+            "Token0ToToken1: sqrt_price should decrease"
+        );
+    }
+
+    #[test]
+    fn test_v3_pool_state_pricer() {
+        let pool = pool_at_price_1(1_000_000_000_000_000_000u128, 300);
+        let one = U256::from(10).pow(U256::from(18));
+
+        assert_eq!(pool.spot_price(0, 1).unwrap(), one);
+        assert_eq!(pool.spot_price(1, 0).unwrap(), one);
+        assert_eq!(pool.sqrt_price_x96(), Some(pool.sqrt_price_x96));
+        assert!(pool.spot_price(0, 0).is_err());
+        assert!(pool.spot_price(0, 2).is_err());
+
+        // Generic entry point dispatches through the trait
+        let price = calculate_pool_price_from_reserves(&pool, 0, 1).unwrap();
+        assert_eq!(price, one);
+    }
+
+    #[test]
+    fn test_range_order_full_fill() {
+        let sqrt_price_current = get_sqrt_ratio_at_tick(0).unwrap();
+        let sqrt_price_after = get_sqrt_ratio_at_tick(200).unwrap();
+        let token0_amount = U256::from(10).pow(U256::from(18));
+
+        let result = calculate_range_order_profit(
+            60,
+            120,
+            token0_amount,
+            sqrt_price_current,
+            sqrt_price_after,
+            1_000_000_000_000_000_000_000u128,
+            BasisPoints::new_const(30),
+        )
+        .unwrap();
+
+        // Range is above spot, so 1 token0 converts to slightly more than 1 token1
+        assert!(result.token1_received > token0_amount);
+        assert!(result.fees_earned_token1 > U256::zero());
+        assert!(result.net_profit >= result.fees_earned_token1);
+    }
+
+    #[test]
+    fn test_range_order_not_reached() {
+        let sqrt_price_current = get_sqrt_ratio_at_tick(0).unwrap();
+        let sqrt_price_after = get_sqrt_ratio_at_tick(30).unwrap();
+
+        let result = calculate_range_order_profit(
+            60,
+            120,
+            U256::from(10).pow(U256::from(18)),
+            sqrt_price_current,
+            sqrt_price_after,
+            1_000_000_000_000_000_000u128,
+            BasisPoints::new_const(30),
+        )
+        .unwrap();
+
+        assert!(result.token1_received.is_zero());
+        assert!(result.fees_earned_token1.is_zero());
+        assert!(result.net_profit.is_zero());
+    }
+
+    #[test]
+    fn test_range_order_rejects_range_below_price() {
+        let sqrt_price_current = get_sqrt_ratio_at_tick(100).unwrap();
+        let result = calculate_range_order_profit(
+            60,
+            120,
+            U256::from(1_000_000u64),
+            sqrt_price_current,
+            get_sqrt_ratio_at_tick(200).unwrap(),
+            1_000_000u128,
+            BasisPoints::new_const(30),
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_simulate_swap_with_ticks_max_segments_error() {
+        // Tick spacing 1 with an input far larger than one tick's depth
+        // forces more than MAX_SEGMENTS segments
+        let sqrt_price_start = get_sqrt_ratio_at_tick(0).unwrap();
+        let result = simulate_swap_with_ticks(
+            U256::from(10).pow(U256::from(30)),
+            sqrt_price_start,
+            1_000_000_000_000_000_000u128,
+            BasisPoints::new_const(30),
+            1,
+            &[],
+        );
+
+        match result {
+            Err(MathError::InvalidInput { reason, .. }) => {
+                assert_eq!(reason, "Swap exceeds max segments")
+            }
+            other => panic!("expected max segments error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_v3_flash_swap_profit() {
+        let pool = pool_at_price_1(1_000_000_000_000_000_000u128, 30);
+        let one = U256::from(10).pow(U256::from(18));
+        let borrowed = U256::from(1000) * one;
+
+        // Borrow token0, sell at 1.01 externally, repay in token1
+        let external_price = one * U256::from(101) / U256::from(100);
+        let profit =
+            calculate_v3_flash_swap_profit(borrowed, U256::zero(), &pool, false, external_price)
+                .unwrap();
+        // proceeds 1010, repayment 1000 * 1.003 = 1003
+        assert_eq!(profit, U256::from(7) * one);
+
+        // External price equal to pool price cannot cover the fee
+        let no_profit =
+            calculate_v3_flash_swap_profit(borrowed, U256::zero(), &pool, false, one).unwrap();
+        assert!(no_profit.is_zero());
+    }
+
+    #[test]
+    fn test_v3_flash_swap_profit_rejects_empty_borrow() {
+        let pool = pool_at_price_1(1_000_000u128, 30);
+        let result = calculate_v3_flash_swap_profit(
+            U256::zero(),
+            U256::zero(),
+            &pool,
+            true,
+            U256::from(10).pow(U256::from(18)),
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_v3_total_execution_cost_bps() {
+        let sqrt_price_x96 = U256::from(79228162514264337593543950336u128); // price = 1
+        let liquidity = 1_000_000_000_000_000_000u128;
+        let fee_bps = BasisPoints::new_const(30);
+        let amount_in = U256::from(10_000_000_000_000_000u128); // 1% of liquidity
+
+        let actual_output = calculate_v3_amount_out(
+            amount_in,
+            sqrt_price_x96,
+            liquidity,
+            fee_bps,
+            SwapDirection::Token0ToToken1,
+        )
+        .unwrap();
+
+        // At price 1 the ideal output equals the input
+        let cost =
+            calculate_v3_total_execution_cost_bps(amount_in, amount_in, actual_output, fee_bps);
+
+        assert_eq!(cost.fee_cost_bps.as_u32(), 30);
+        assert!(cost.slippage_cost_bps.as_u32() > 0, "1% swap should move price");
+        assert_eq!(
+            cost.total_cost_bps.as_u32(),
+            cost.fee_cost_bps.as_u32() + cost.slippage_cost_bps.as_u32()
+        );
+    }
+
+    #[test]
+    fn test_v3_total_execution_cost_bps_zero_input() {
+        let cost = calculate_v3_total_execution_cost_bps(
+            U256::zero(),
+            U256::zero(),
+            U256::zero(),
+            BasisPoints::new_const(30),
+        );
+        assert_eq!(cost.total_cost_bps.as_u32(), 0);
+    }
+
+    #[test]
+    fn test_v3_sandwich_backrun_round_trip() {
+        let sqrt_price_x96 = U256::from(79228162514264337593543950336u128); // price = 1
+        let liquidity = 1_000_000_000_000_000_000_000u128;
+        let tick = 0;
+        let fee_bps = BasisPoints::new_const(30);
+        let frontrun_amount = U256::from(10).pow(U256::from(20));
+
+        // Frontrun buys Y of token1
+        let y = calculate_v3_amount_out(
+            frontrun_amount,
+            sqrt_price_x96,
+            liquidity,
+            fee_bps,
+            SwapDirection::Token0ToToken1,
+        )
+        .unwrap();
+
+        // Backrun sells the same Y of token1 from the post-frontrun price
+        let (sqrt_price_post_frontrun, _) = calculate_v3_post_frontrun_state(
+            frontrun_amount,
+            sqrt_price_x96,
+            liquidity,
+            tick,
+            fee_bps,
+            SwapDirection::Token0ToToken1,
+        )
+        .unwrap();
+        let returned = calculate_v3_amount_out(
+            y,
+            sqrt_price_post_frontrun,
+            liquidity,
+            fee_bps,
+            SwapDirection::Token1ToToken0,
+        )
+        .unwrap();
+
+        // Without a victim the round trip only loses the two fees
+        assert!(returned < frontrun_amount);
+        assert!(returned > frontrun_amount * U256::from(99) / U256::from(100));
+    }
+
+    #[test]
+    fn test_v3_protocol_fee_taken() {
+        let amount_in = U256::from(1_000_000_000_000_000_000u128); // 1 ETH
+        let fee_bps = BasisPoints::new_const(30);
+        let total_fee = U256::from(3_000_000_000_000_000u128); // 0.003 ETH
+
+        // Disabled: LPs keep the whole fee
+        let (lp_fee, protocol_fee) = calculate_v3_protocol_fee_taken(amount_in, fee_bps, 0).unwrap();
+        assert_eq!(lp_fee, total_fee);
+        assert!(protocol_fee.is_zero());
+
+        // 1/4 to protocol, 3/4 to LPs
+        let (lp_fee, protocol_fee) = calculate_v3_protocol_fee_taken(amount_in, fee_bps, 4).unwrap();
+        assert_eq!(protocol_fee, total_fee / U256::from(4));
+        assert_eq!(lp_fee + protocol_fee, total_fee);
+
+        // 1/10 to protocol
+        let (lp_fee, protocol_fee) = calculate_v3_protocol_fee_taken(amount_in, fee_bps, 10).unwrap();
+        assert_eq!(protocol_fee, total_fee / U256::from(10));
+        assert_eq!(lp_fee + protocol_fee, total_fee);
+    }
+
+    #[test]
+    fn test_v3_protocol_fee_invalid_fraction() {
+        let amount_in = U256::from(1_000_000_000_000_000_000u128);
+        let fee_bps = BasisPoints::new_const(30);
+
+        for fraction in [1u8, 2, 3, 11, 255] {
+            assert!(calculate_v3_protocol_fee_taken(amount_in, fee_bps, fraction).is_err());
+        }
+    }
+
+    #[test]
+    fn test_liquidity_weighted_price() {
+        let one = U256::from(10).pow(U256::from(18));
+        let pool_at_1 = pool_at_price_1(1_000_000_000_000_000_000u128, 30);
+        let pool_at_4 = V3PoolState {
+            sqrt_price_x96: U256::from(79228162514264337593543950336u128) * U256::from(2), // price = 4
+            liquidity: 1_000_000_000_000_000_000u128,
+            tick: 13863,
+            fee_bps: BasisPoints::new_const(30),
+        };
+
+        // Equal weights: (1 + 4) / 2 = 2.5
+        let price = calculate_liquidity_weighted_price(&[(&pool_at_1, 1000), (&pool_at_4, 1000)])
+            .unwrap();
+        assert_eq!(price, one * U256::from(5) / U256::from(2));
+
+        // Deep pool dominates: (1 * 9 + 4 * 1) / 10 = 1.3
+        let price = calculate_liquidity_weighted_price(&[(&pool_at_1, 9000), (&pool_at_4, 1000)])
+            .unwrap();
+        assert_eq!(price, one * U256::from(13) / U256::from(10));
+
+        // Zero-liquidity pools are ignored; none at all is an error
+        let price = calculate_liquidity_weighted_price(&[(&pool_at_1, 1000), (&pool_at_4, 0)])
+            .unwrap();
+        assert_eq!(price, one);
+        assert!(calculate_liquidity_weighted_price(&[]).is_err());
+    }
+
+    #[test]
+    fn test_v3_virtual_reserves_in_range() {
+        let sqrt_price = get_sqrt_ratio_at_tick(0).unwrap();
+        let liquidity = 1_000_000_000_000_000_000_000u128;
+
+        let (amount0, amount1) =
+            calculate_v3_virtual_reserves(sqrt_price, liquidity, -600, 600).unwrap();
+
+        // Symmetric range around price 1.0 holds ~equal reserves
+        assert!(!amount0.is_zero() && !amount1.is_zero());
+        let diff = if amount0 > amount1 { amount0 - amount1 } else { amount1 - amount0 };
+        assert!(diff < amount0 / U256::from(1000));
+    }
+
+    #[test]
+    fn test_v3_virtual_reserves_out_of_range() {
+        let liquidity = 1_000_000_000_000_000_000_000u128;
+
+        // Price below range: all token0
+        let below = get_sqrt_ratio_at_tick(-1200).unwrap();
+        let (amount0, amount1) = calculate_v3_virtual_reserves(below, liquidity, -600, 600).unwrap();
+        assert!(!amount0.is_zero());
+        assert!(amount1.is_zero());
+
+        // Price above range: all token1
+        let above = get_sqrt_ratio_at_tick(1200).unwrap();
+        let (amount0, amount1) = calculate_v3_virtual_reserves(above, liquidity, -600, 600).unwrap();
+        assert!(amount0.is_zero());
+        assert!(!amount1.is_zero());
+
+        match calculate_v3_virtual_reserves(below, liquidity, 600, -600) {
+            Err(MathError::InvalidInput { operation, .. }) => {
+                assert_eq!(operation, "calculate_v3_virtual_reserves")
+            }
+            other => panic!("expected InvalidInput, got {:?}", other),
+        }
+    }
+
+    fn jit_pool() -> V3PoolState {
+        pool_at_price_1(1_000_000_000_000_000_000_000u128, 30)
+    }
+
+    fn jit_victim() -> V3SwapParams {
+        V3SwapParams {
+            amount_in: U256::from(1_000_000_000_000_000_000u128),
+            min_amount_out: U256::zero(),
+            sqrt_price_limit_x96: U256::zero(),
+            deadline: u64::MAX,
+            direction: SwapDirection::Token0ToToken1,
+        }
+    }
+
+    #[test]
+    fn test_simulate_v3_jit_full_in_range() {
+        let position = V3JitPosition {
+            tick_lower: -600,
+            tick_upper: 600,
+            liquidity: 100_000_000_000_000_000_000u128,
+        };
+        let no_gas = GasCosts {
+            mint_gas: 0,
+            burn_gas: 0,
+            collect_gas: 0,
+            gas_price: U256::zero(),
+        };
+
+        let result = simulate_v3_jit_full(position.clone(), jit_victim(), &jit_pool(), &no_gas)
+            .unwrap();
+
+        assert!(!result.capital_deployed.0.is_zero() && !result.capital_deployed.1.is_zero());
+        // Fees are paid in the victim's input token, pro rata to JIT liquidity (1/11)
+        assert!(!result.fees_collected.0.is_zero());
+        assert!(result.fees_collected.1.is_zero());
+        assert!(result.fees_collected.0 < U256::from(3_000_000_000_000_000u128));
+        assert!(result.net_profit > U256::zero());
+
+        // Gas larger than the fees wipes out the profit
+        let expensive_gas = GasCosts {
+            mint_gas: 300_000,
+            burn_gas: 200_000,
+            collect_gas: 100_000,
+            gas_price: U256::from(100_000_000_000u64),
+        };
+        let result = simulate_v3_jit_full(position, jit_victim(), &jit_pool(), &expensive_gas)
+            .unwrap();
+        assert!(result.net_profit.is_zero());
+    }
+
+    #[test]
+    fn test_simulate_v3_jit_full_out_of_range() {
+        let position = V3JitPosition {
+            tick_lower: 600,
+            tick_upper: 1200,
+            liquidity: 100_000_000_000_000_000_000u128,
+        };
+        let gas = GasCosts {
+            mint_gas: 0,
+            burn_gas: 0,
+            collect_gas: 0,
+            gas_price: U256::zero(),
+        };
+
+        let result = simulate_v3_jit_full(position, jit_victim(), &jit_pool(), &gas).unwrap();
+        assert!(result.fees_collected.0.is_zero());
+        assert!(result.net_profit.is_zero());
+    }
+
+    #[test]
+    fn test_v3_sandwich_profit_symmetric_follows_victim() {
+        let pool = pool_at_price_1(1_000_000_000_000_000_000_000u128, 30);
+        let victim_amount = U256::from(100_000_000_000_000_000_000u128); // 100 tokens
+        let aave_fee_bps = BasisPoints::new_const(5);
+
+        for victim_direction in [SwapDirection::Token0ToToken1, SwapDirection::Token1ToToken0] {
+            let (profit, direction) = calculate_v3_sandwich_profit_symmetric(
+                victim_direction,
+                victim_amount,
+                &pool,
+                aave_fee_bps,
+            )
+            .unwrap();
+
+            assert!(matches!(
+                (direction, victim_direction),
+                (SwapDirection::Token0ToToken1, SwapDirection::Token0ToToken1)
+                    | (SwapDirection::Token1ToToken0, SwapDirection::Token1ToToken0)
+            ));
+            assert!(profit > U256::zero());
+        }
+
+        assert!(calculate_v3_sandwich_profit_symmetric(
+            SwapDirection::Token0ToToken1,
+            U256::zero(),
+            &pool,
+            aave_fee_bps,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_tick_table_60_matches_tick_math() {
+        for tick in [-887220, -600000, -60, 0, 60, 200040, 887220] {
+            assert_eq!(
+                get_sqrt_ratio_at_tick_spacing_60(tick),
+                Some(get_sqrt_ratio_at_tick(tick).unwrap()),
+                "tick={}",
+                tick
+            );
+        }
+        assert_eq!(get_tick_table_60().len(), TICK_TABLE_60_LEN);
+
+        // Non-multiples fall back to TickMath; out of range is None
+        assert_eq!(
+            get_sqrt_ratio_at_tick_spacing_60(887272),
+            Some(get_sqrt_ratio_at_tick(887272).unwrap())
+        );
+        assert_eq!(get_sqrt_ratio_at_tick_spacing_60(-1), Some(get_sqrt_ratio_at_tick(-1).unwrap()));
+        assert_eq!(get_sqrt_ratio_at_tick_spacing_60(887280), None);
+    }
+
+    #[test]
+    fn test_tick_table_60_built_once() {
+        init_tick_table_60();
+        let first = get_tick_table_60().as_ptr();
+
+        // Every lookup reads the same cached allocation
+        assert_eq!(get_sqrt_ratio_at_tick_spacing_60(60), Some(get_tick_table_60()[14788]));
+        assert!(std::ptr::eq(first, get_tick_table_60().as_ptr()));
+        assert!(std::ptr::eq(first, TICK_TABLE_60.get().unwrap().as_ptr()));
+    }
+
+    #[test]
+    fn test_price_to_initial_sqrt_price_x96() {
+        let q96 = U256::from(79228162514264337593543950336u128);
+
+        // Price 1.0 with equal decimals is exactly 2^96
+        assert_eq!(price_to_initial_sqrt_price_x96(1.0, 18, 18).unwrap(), q96);
+
+        // Price 4.0 -> sqrt = 2
+        assert_eq!(price_to_initial_sqrt_price_x96(4.0, 18, 18).unwrap(), q96 * U256::from(2));
+
+        // WETH/USDC-style: 1 ETH (18 dec) = 2500 USDC (6 dec) -> raw price 2500e-12
+        let sqrt_price = price_to_initial_sqrt_price_x96(2500.0, 18, 6).unwrap();
+        let tick = sqrt_price_to_tick(sqrt_price).unwrap();
+        assert!((-198_500..=-197_500).contains(&tick), "tick={}", tick);
+
+        // Extreme prices clamp to the valid range
+        assert_eq!(
+            price_to_initial_sqrt_price_x96(1e-40, 18, 18).unwrap(),
+            U256::from(MIN_SQRT_RATIO)
+        );
+        assert!(price_to_initial_sqrt_price_x96(1e40, 18, 18).unwrap() < get_max_sqrt_ratio());
+
+        assert!(price_to_initial_sqrt_price_x96(0.0, 18, 18).is_err());
+        assert!(price_to_initial_sqrt_price_x96(f64::NAN, 18, 18).is_err());
+    }
+
+    /// Tick of a raw price from floating point, for comparison
+    fn expected_tick(price: f64, token0_decimals: u8, token1_decimals: u8) -> i32 {
+        let shift = token1_decimals as i32 - token0_decimals as i32;
+        let raw_price = price * 10f64.powi(shift);
+        (raw_price.ln() / 1.0001f64.ln()).floor() as i32
+    }
+
+    #[test]
+    fn test_price_to_initial_sqrt_price_x96_low_prices() {
+        // 18-decimal token priced at 1e-8 USDC: raw price 1e-20, tick ~ -460,500
+        let sqrt_price = price_to_initial_sqrt_price_x96(1e-8, 18, 6).unwrap();
+        let tick = sqrt_price_to_tick(sqrt_price).unwrap();
+        assert!((-460_600..=-460_400).contains(&tick), "tick={}", tick);
+        assert!((tick - expected_tick(1e-8, 18, 6)).abs() <= 1, "tick={}", tick);
+
+        // Sub-1e-18 raw prices are still representable and not pinned to MIN_SQRT_RATIO
+        for price in [1e-12, 1e-19, 1e-25, 1e-30] {
+            let sqrt_price = price_to_initial_sqrt_price_x96(price, 18, 18).unwrap();
+            assert!(sqrt_price > U256::from(MIN_SQRT_RATIO), "price={}", price);
+            let tick = sqrt_price_to_tick(sqrt_price).unwrap();
+            assert!(
+                (tick - expected_tick(price, 18, 18)).abs() <= 1,
+                "price={}, tick={}",
+                price,
+                tick
+            );
+        }
+
+        // Distinct low prices map to distinct sqrt prices
+        assert!(
+            price_to_initial_sqrt_price_x96(2e-25, 18, 18).unwrap()
+                > price_to_initial_sqrt_price_x96(1e-25, 18, 18).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_price_to_initial_sqrt_price_x96_mixed_decimals() {
+        // (price, token0 decimals, token1 decimals)
+        let cases = [
+            (60_000.0, 8, 6), // WBTC priced in USDC
+            (0.0004, 6, 18),  // USDC priced in WETH
+            (0.05, 18, 8),    // WETH priced in WBTC
+            (1.0, 6, 18),     // USDC priced in DAI at peg
+            (3.5e-6, 18, 18), // Low-priced 18-decimal token in WETH
+        ];
+        for (price, token0_decimals, token1_decimals) in cases {
+            let sqrt_price =
+                price_to_initial_sqrt_price_x96(price, token0_decimals, token1_decimals).unwrap();
+            let tick = sqrt_price_to_tick(sqrt_price).unwrap();
+            let expected = expected_tick(price, token0_decimals, token1_decimals);
+            assert!(
+                (tick - expected).abs() <= 1,
+                "price={}, decimals={}/{}, tick={}, expected={}",
+                price,
+                token0_decimals,
+                token1_decimals,
+                tick,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_validate_initial_price_for_fee_tier() {
+        let q96 = U256::from(79228162514264337593543950336u128);
+
+        for fee_bps in [1, 5, 30, 100] {
+            assert!(validate_initial_price_for_fee_tier(q96, fee_bps).is_ok());
+        }
+        assert!(validate_initial_price_for_fee_tier(q96, 25).is_err());
+
+        // Price at the minimum leaves no room below for a 200-spacing position
+        assert!(validate_initial_price_for_fee_tier(U256::from(MIN_SQRT_RATIO), 100).is_err());
+        assert!(validate_initial_price_for_fee_tier(U256::zero(), 30).is_err());
+    }
+
+    #[test]
+    fn test_calculate_fees_earned() {
+        let position = V3Position {
+            tick_lower: -600,
+            tick_upper: 600,
+            liquidity: 100_000_000_000_000_000_000u128,
+        };
+        let pool_liquidity = 1_000_000_000_000_000_000_000u128;
+        let one = U256::from(1_000_000_000_000_000_000u128);
+        let fee_bps = BasisPoints::new_const(30);
+
+        let swaps = vec![
+            // Fully inside the range, price down: token0 fee
+            SwapEvent {
+                sqrt_price_before: get_sqrt_ratio_at_tick(0).unwrap(),
+                sqrt_price_after: get_sqrt_ratio_at_tick(-100).unwrap(),
+                amount_in: one,
+                fee_bps,
+            },
+            // Fully inside the range, price up: token1 fee
+            SwapEvent {
+                sqrt_price_before: get_sqrt_ratio_at_tick(-100).unwrap(),
+                sqrt_price_after: get_sqrt_ratio_at_tick(100).unwrap(),
+                amount_in: one,
+                fee_bps,
+            },
+            // Entirely above the range: no fee
+            SwapEvent {
+                sqrt_price_before: get_sqrt_ratio_at_tick(700).unwrap(),
+                sqrt_price_after: get_sqrt_ratio_at_tick(900).unwrap(),
+                amount_in: one,
+                fee_bps,
+            },
+        ];
+
+        let (fees0, fees1) =
+            calculate_fees_earned(&position, &swaps, &[pool_liquidity; 3]).unwrap();
+
+        // 0.003 tokens of fee, 10% liquidity share
+        let expected = U256::from(300_000_000_000_000u128);
+        assert_eq!(fees0, expected);
+        assert_eq!(fees1, expected);
+    }
+
+    #[test]
+    fn test_calculate_fees_earned_partial_overlap() {
+        let position = V3Position {
+            tick_lower: 0,
+            tick_upper: 600,
+            liquidity: 100_000_000_000_000_000_000u128,
+        };
+        let pool_liquidity = 1_000_000_000_000_000_000_000u128;
+
+        // Half of the price path (in token1 terms, roughly) is inside the range
+        let swaps = vec![SwapEvent {
+            sqrt_price_before: get_sqrt_ratio_at_tick(-100).unwrap(),
+            sqrt_price_after: get_sqrt_ratio_at_tick(100).unwrap(),
+            amount_in: U256::from(1_000_000_000_000_000_000u128),
+            fee_bps: BasisPoints::new_const(30),
+        }];
+
+        let (fees0, fees1) = calculate_fees_earned(&position, &swaps, &[pool_liquidity]).unwrap();
+        let full_share = U256::from(300_000_000_000_000u128);
+        assert!(fees0.is_zero());
+        assert!(fees1 > full_share * U256::from(49) / U256::from(100));
+        assert!(fees1 < full_share * U256::from(51) / U256::from(100));
+
+        assert!(calculate_fees_earned(&position, &swaps, &[]).is_err());
+    }
+
+    #[test]
+    fn test_adaptive_tolerance() {
+        // 100 ETH victim: 100e18 / 1e5 = 1e15
+        assert_eq!(
+            adaptive_tolerance(U256::from(100_000_000_000_000_000_000u128)),
+            U256::from(1_000_000_000_000_000u128)
+        );
+        // 0.01 ETH victim would give 1e11, floored at the absolute minimum
+        assert_eq!(
+            adaptive_tolerance(U256::from(10_000_000_000_000_000u128)),
+            U256::from(ADAPTIVE_TOLERANCE_ABSOLUTE_MIN)
+        );
+    }
+
+    #[test]
+    fn test_brents_method_detailed_result() {
+        let victim_amount = U256::from(10_000_000_000_000_000_000u128);
+        let sqrt_price_x96 = U256::from(79228162514264337593543950336u128);
+        let liquidity = 10_000_000_000_000_000_000_000u128;
+        let fee_bps = BasisPoints::new_const(300);
+        let aave_fee_bps = BasisPoints::new_const(9);
+
+        let result = brents_method_v3_sandwich_optimization_detailed(
+            victim_amount,
+            sqrt_price_x96,
+            liquidity,
+            0,
+            fee_bps,
+            aave_fee_bps,
+        )
+        .unwrap();
+
+        assert!(result.convergence_iterations <= 50);
+        assert_eq!(
+            result.expected_profit,
+            calculate_v3_sandwich_profit(
+                result.optimal_amount,
+                victim_amount,
+                sqrt_price_x96,
+                liquidity,
+                0,
+                fee_bps,
+                aave_fee_bps,
+            )
+            .unwrap()
+        );
+
+        // The plain API returns the same optimum
+        let optimal = brents_method_v3_sandwich_optimization(
+            victim_amount,
+            sqrt_price_x96,
+            liquidity,
+            0,
+            fee_bps,
+            aave_fee_bps,
+        )
+        .unwrap();
+        assert_eq!(optimal, result.optimal_amount);
+    }
+
+    #[test]
+    fn test_round_frontrun_amount_to_tick_boundary() {
+        let pool_state = pool_at_price_1(1_000_000_000_000_000_000_000u128, 30);
+
+        // 2.5 tokens moves the price to roughly tick -50; tick -60 is the closest boundary
+        let frontrun = U256::from(2_500_000_000_000_000_000u128);
+        let rounded = round_frontrun_amount_to_tick_boundary(
+            frontrun,
+            &pool_state,
+            60,
+            SwapDirection::Token0ToToken1,
+        )
+        .unwrap();
+        assert!(rounded > frontrun);
+
+        let (new_sqrt_price, _) = calculate_v3_post_frontrun_state(
+            rounded,
+            pool_state.sqrt_price_x96,
+            pool_state.liquidity,
+            pool_state.tick,
+            pool_state.fee_bps,
+            SwapDirection::Token0ToToken1,
+        )
+        .unwrap();
+        let boundary = get_sqrt_ratio_at_tick(-60).unwrap();
+        assert!(new_sqrt_price <= boundary);
+        assert!(boundary - new_sqrt_price < boundary / U256::from(1_000_000_000u64));
+
+        // Same size the other way lands on tick 60
+        let rounded = round_frontrun_amount_to_tick_boundary(
+            frontrun,
+            &pool_state,
+            60,
+            SwapDirection::Token1ToToken0,
+        )
+        .unwrap();
+        let (new_sqrt_price, _) = calculate_v3_post_frontrun_state(
+            rounded,
+            pool_state.sqrt_price_x96,
+            pool_state.liquidity,
+            pool_state.tick,
+            pool_state.fee_bps,
+            SwapDirection::Token1ToToken0,
+        )
+        .unwrap();
+        let boundary = get_sqrt_ratio_at_tick(60).unwrap();
+        assert!(new_sqrt_price >= boundary);
+        assert!(new_sqrt_price - boundary < boundary / U256::from(1_000_000_000u64));
+
+        assert!(round_frontrun_amount_to_tick_boundary(
+            frontrun,
+            &pool_state,
+            0,
+            SwapDirection::Token0ToToken1
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_sweep_fee_tiers() {
+        let q96 = U256::from(79228162514264337593543950336u128);
+        let pool = |fee_bps: u32| {
+            (
+                BasisPoints::new_const(fee_bps),
+                pool_at_price_1(1_000_000_000_000_000_000_000u128, fee_bps),
+            )
+        };
+        let pools = vec![pool(30), pool(5), pool(100)];
+        let mut victim = V3SwapParams {
+            amount_in: U256::from(10_000_000_000_000_000_000u128),
+            min_amount_out: U256::zero(),
+            sqrt_price_limit_x96: U256::zero(),
+            deadline: u64::MAX,
+            direction: SwapDirection::Token0ToToken1,
+        };
+
+        let aave_fee = BasisPoints::new_const(9);
+
+        // Equal depth: the cheapest tier wins and results are sorted by profit
+        let results = sweep_fee_tiers(&victim, &pools, aave_fee).unwrap();
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].0.as_u32(), 5);
+        assert!(results[0].1 > U256::zero());
+        assert!(results.windows(2).all(|pair| pair[0].1 >= pair[1].1));
+
+        // A fee-free flash loan (Balancer) leaves more profit than Aave's premium
+        let free = sweep_fee_tiers(&victim, &pools, BasisPoints::new_const(0)).unwrap();
+        assert_eq!(free[0].0.as_u32(), 5);
+        assert!(free[0].1 > results[0].1);
+
+        // A victim with no slippage room can't be sandwiched anywhere
+        victim.min_amount_out = calculate_v3_amount_out(
+            victim.amount_in,
+            q96,
+            1_000_000_000_000_000_000_000u128,
+            BasisPoints::new_const(5),
+            SwapDirection::Token0ToToken1,
+        )
+        .unwrap();
+        let results = sweep_fee_tiers(&victim, &pools, aave_fee).unwrap();
+        assert!(results.iter().all(|(_, profit)| profit.is_zero()));
+
+        victim.amount_in = U256::zero();
+        assert!(sweep_fee_tiers(&victim, &pools, aave_fee).is_err());
+    }
+
+    #[test]
+    fn test_calculate_capital_efficiency_ratio() {
+        // +/-600 ticks vs the full V2 range: 1_774_544 / 1200
+        let efficiency = calculate_capital_efficiency_ratio(-600, 600, MAX_TICK * 2);
+        assert!((efficiency - 1478.786_666).abs() < 1e-3, "efficiency={}", efficiency);
+
+        assert_eq!(calculate_capital_efficiency_ratio(600, 600, MAX_TICK * 2), 0.0);
+        assert_eq!(calculate_capital_efficiency_ratio(600, -600, MAX_TICK * 2), 0.0);
+    }
+
+    #[test]
+    fn test_calculate_required_capital_for_liquidity_target() {
+        let q96 = U256::from(79228162514264337593543950336u128);
+        let liquidity = 1_000_000_000_000_000_000u128;
+
+        // At price 1.0 a symmetric range needs L * (1 - 1.0001^-300) ≈ 0.029554 of each token
+        let prices = (2000.0, 1.0);
+        let (usd0, usd1) =
+            calculate_required_capital_for_liquidity_target(liquidity, -600, 600, q96, prices);
+        assert!((usd1 - 0.029554).abs() < 1e-5, "usd1={}", usd1);
+        assert!((usd0 / usd1 - 2000.0).abs() < 1e-3);
+
+        // Below the range the position is all token0
+        let below = get_sqrt_ratio_at_tick(-1200).unwrap();
+        let prices = (2000.0, 1.0);
+        let (usd0, usd1) =
+            calculate_required_capital_for_liquidity_target(liquidity, -600, 600, below, prices);
+        assert!(usd0 > 0.0);
+        assert_eq!(usd1, 0.0);
+
+        assert_eq!(
+            calculate_required_capital_for_liquidity_target(liquidity, 600, -600, q96, (1.0, 1.0)),
+            (0.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn test_log2_initial_tick_guess() {
+        for tick in [MIN_TICK + 1, -500_000, -198_000, -10_000, -1, 1, 60, 200_000, MAX_TICK - 1] {
+            let sqrt_price = get_sqrt_ratio_at_tick(tick).unwrap();
+            let guess = log2_initial_tick_guess(sqrt_price);
+            assert!((guess - tick).abs() <= 2, "tick={}, guess={}", tick, guess);
+        }
+    }
+
+    #[test]
+    fn test_precomputed_v3_sandwich_state() {
+        let pool_state = pool_at_price_1(1_000_000_000_000_000_000_000u128, 30);
+        let precomputed = precompute_v3_state(pool_state.clone()).unwrap();
+        assert_eq!(precomputed.pool_state().sqrt_price_x96, pool_state.sqrt_price_x96);
+
+        // Price 1 sits at tick 0, so the 0.3% tier's range is [0, 60)
+        let (sqrt_lower, sqrt_upper) = precomputed.tick_range_sqrt_prices();
+        assert_eq!(sqrt_lower, get_sqrt_ratio_at_tick(0).unwrap());
+        assert_eq!(sqrt_upper, get_sqrt_ratio_at_tick(60).unwrap());
+        assert!(precomputed.amount_to_tick_boundary(SwapDirection::Token0ToToken1).is_zero());
+        let to_upper = precomputed.amount_to_tick_boundary(SwapDirection::Token1ToToken0);
+        let (sqrt_after, _) = calculate_v3_post_frontrun_state(
+            to_upper,
+            pool_state.sqrt_price_x96,
+            pool_state.liquidity,
+            pool_state.tick,
+            pool_state.fee_bps,
+            SwapDirection::Token1ToToken0,
+        )
+        .unwrap();
+        assert!(sqrt_after >= sqrt_upper);
+
+        let aave_fee = BasisPoints::new_const(9);
+        let victim = U256::from(50_000_000_000_000_000_000u128);
+        let frontrun = U256::from(10_000_000_000_000_000_000u128);
+        let direct = calculate_v3_sandwich_profit(
+            frontrun,
+            victim,
+            pool_state.sqrt_price_x96,
+            pool_state.liquidity,
+            pool_state.tick,
+            pool_state.fee_bps,
+            aave_fee,
+        )
+        .unwrap();
+        let cached = calculate_v3_sandwich_profit_precomputed(
+            frontrun,
+            victim,
+            SwapDirection::Token0ToToken1,
+            &precomputed,
+            aave_fee,
+        )
+        .unwrap();
+        assert_eq!(direct, cached);
+        let no_frontrun = calculate_v3_sandwich_profit_precomputed(
+            U256::zero(),
+            victim,
+            SwapDirection::Token0ToToken1,
+            &precomputed,
+            aave_fee,
+        )
+        .unwrap();
+        assert!(no_frontrun.is_zero());
+
+        // The cached optimizer matches the uncached one
+        let uncached = optimize_v3_sandwich_in_direction(
+            victim,
+            &pool_state,
+            aave_fee,
+            SwapDirection::Token0ToToken1,
+            SwapDirection::Token0ToToken1,
+        )
+        .unwrap();
+
+        let (amount, profit) = optimize_v3_sandwich_precomputed(
+            victim,
+            SwapDirection::Token0ToToken1,
+            &precomputed,
+            aave_fee,
+        )
+        .unwrap();
+        assert_eq!((amount, profit), uncached);
+        assert!(amount > U256::zero());
+        assert!(profit >= cached);
+
+        // Invalid snapshots are rejected up front
+        let empty = V3PoolState { liquidity: 0, ..pool_state.clone() };
+        assert!(precompute_v3_state(empty).is_err());
+    }
+
+    #[test]
+    fn test_liquidity_in_range_ranking() {
+        let tick = |gross: u128, net: i128| TickData { liquidity_gross: gross, liquidity_net: net };
+        let pool = |liquidity: u128| pool_at_price_1(liquidity, 30);
+
+        // Pool A: position [-100, 100] with L=1000, position [200, 400] with L=500
+        let mut ticks_a = HashMap::new();
+        ticks_a.insert(-100, tick(1000, 1000));
+        ticks_a.insert(100, tick(1000, -1000));
+        ticks_a.insert(200, tick(500, 500));
+        ticks_a.insert(400, tick(500, -500));
+        let pool_a = pool(1000);
+
+        assert_eq!(calculate_v3_liquidity_in_range(&pool_a, -50, 50, &ticks_a).unwrap(), 1000);
+        assert_eq!(calculate_v3_liquidity_in_range(&pool_a, 50, 250, &ticks_a).unwrap(), 1500);
+        assert_eq!(calculate_v3_liquidity_in_range(&pool_a, 150, 190, &ticks_a).unwrap(), 0);
+        assert_eq!(calculate_v3_liquidity_in_range(&pool_a, -300, -200, &ticks_a).unwrap(), 0);
+        assert_eq!(calculate_v3_liquidity_in_range(&pool_a, 50, -50, &ticks_a).unwrap(), 0);
+
+        // Pool B: one wide position [-1000, 1000] with L=1200
+        let mut ticks_b = HashMap::new();
+        ticks_b.insert(-1000, tick(1200, 1200));
+        ticks_b.insert(1000, tick(1200, -1200));
+        let pool_b = pool(1200);
+
+        assert_eq!(
+            rank_pools_by_liquidity_in_range(&[&pool_a, &pool_b], -50, 50, &[&ticks_a, &ticks_b])
+                .unwrap(),
+            vec![1, 0]
+        );
+        assert_eq!(
+            rank_pools_by_liquidity_in_range(&[&pool_a, &pool_b], 50, 250, &[&ticks_a, &ticks_b])
+                .unwrap(),
+            vec![0, 1]
+        );
+    }
+
+    #[test]
+    fn test_liquidity_in_range_rejects_bad_tick_data() {
+        let tick = |gross: u128, net: i128| TickData { liquidity_gross: gross, liquidity_net: net };
+        let pool = pool_at_price_1(1000, 30);
+
+        // Tick 100 removes more than is active when crossed left to right
+        let mut overdrawn = HashMap::new();
+        overdrawn.insert(-100, tick(1000, 1000));
+        overdrawn.insert(100, tick(5000, -5000));
+        assert!(calculate_v3_liquidity_in_range(&pool, 150, 200, &overdrawn).is_err());
+
+        // Tick -100 adds more than is active, so crossing it right to left underflows
+        let mut overfilled = HashMap::new();
+        overfilled.insert(-100, tick(5000, 5000));
+        overfilled.insert(100, tick(1000, -1000));
+        assert!(calculate_v3_liquidity_in_range(&pool, -200, -150, &overfilled).is_err());
+
+        // A tick whose net removal exceeds its gross references is corrupt
+        let mut corrupt = HashMap::new();
+        corrupt.insert(50, tick(100, -300));
+        assert!(calculate_v3_liquidity_in_range(&pool, 0, 100, &corrupt).is_err());
+
+        let empty = HashMap::new();
+        let result =
+            rank_pools_by_liquidity_in_range(&[&pool, &pool], 150, 200, &[&empty, &overdrawn]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_v3_amount_out_with_transfer_fee() {
+        let sqrt_price = U256::from(79228162514264337593543950336u128);
+        let liquidity = 1_000_000_000_000_000_000_000u128;
+        let swap_fee = BasisPoints::new_const(30);
+        let direction = SwapDirection::Token0ToToken1;
+        let amount_in = U256::from(1_000_000_000_000_000_000u128);
+
+        // 2% transfer fee: the pool swaps 0.98 tokens
+        let with_fee = calculate_v3_amount_out_with_transfer_fee(
+            amount_in,
+            BasisPoints::new_const(200),
+            sqrt_price,
+            liquidity,
+            swap_fee,
+            direction,
+        )
+        .unwrap();
+        let received = amount_in * U256::from(98) / U256::from(100);
+        let expected =
+            calculate_v3_amount_out(received, sqrt_price, liquidity, swap_fee, direction).unwrap();
+        assert_eq!(with_fee, expected);
+
+        // Zero transfer fee matches the plain swap
+        let no_fee = calculate_v3_amount_out_with_transfer_fee(
+            amount_in,
+            BasisPoints::new_const(0),
+            sqrt_price,
+            liquidity,
+            swap_fee,
+            direction,
+        )
+        .unwrap();
+        let plain =
+            calculate_v3_amount_out(amount_in, sqrt_price, liquidity, swap_fee, direction).unwrap();
+        assert_eq!(no_fee, plain);
+
+        // Output token fee is taken from what the pool sends
+        let both = calculate_v3_amount_out_with_transfer_fees(
+            amount_in,
+            BasisPoints::new_const(200),
+            BasisPoints::new_const(100),
+            sqrt_price,
+            liquidity,
+            swap_fee,
+            direction,
+        )
+        .unwrap();
+        assert_eq!(both, expected - expected / U256::from(100));
+    }
+
+    #[test]
+    fn test_verify_v3_swap_invariant() {
+        let before = pool_at_price_1(1_000_000_000_000_000_000_000u128, 30);
+        let amount_in = U256::from(10_000_000_000_000_000_000u128);
+        let fee = before.fee_bps;
+
+        for token_in in [0usize, 1] {
+            let (after, amount_out) = before.after_swap(amount_in, token_in, 1 - token_in).unwrap();
+            assert!(verify_v3_swap_invariant(&before, &after, amount_in, amount_out, fee));
+
+            // Overpaying by 10% of the output (~10 bps of the reserve) breaks it
+            let wrong_out = amount_out + amount_out / U256::from(10);
+            assert!(!verify_v3_swap_invariant(&before, &after, amount_in, wrong_out, fee));
+        }
+    }
+
+    #[test]
+    fn test_typed_v3_amount_out() {
+        let sqrt_price = U256::from(79228162514264337593543950336u128);
+        let liquidity = 1_000_000_000_000_000_000_000u128;
+        let fee = BasisPoints::new_const(30);
+        let amount = U256::from(1_000_000_000_000_000_000u128);
+
+        let token0_in = Token0Amount::from(amount);
+        let token1_out = calculate_v3_token1_out(token0_in, sqrt_price, liquidity, fee).unwrap();
+        let expected = calculate_v3_amount_out(
+            amount,
+            sqrt_price,
+            liquidity,
+            fee,
+            SwapDirection::Token0ToToken1,
+        )
+        .unwrap();
+        assert_eq!(token1_out.inner(), expected);
+
+        let token1_in = Token1Amount::from(amount);
+        let token0_out = calculate_v3_token0_out(token1_in, sqrt_price, liquidity, fee).unwrap();
+        let expected = calculate_v3_amount_out(
+            amount,
+            sqrt_price,
+            liquidity,
+            fee,
+            SwapDirection::Token1ToToken0,
+        )
+        .unwrap();
+        assert_eq!(token0_out.inner(), expected);
+    }
+
+    #[test]
+    fn test_apply_liquidity_net_safe() {
+        assert_eq!(apply_liquidity_net_safe(1_000, 500).unwrap(), 1_500);
+        assert_eq!(apply_liquidity_net_safe(1_000, -1_000).unwrap(), 0);
+
+        // A buggy fork's liquidityNet larger than active liquidity is rejected
+        match apply_liquidity_net_safe(1_000, -1_001) {
+            Err(MathError::InvalidInput { reason, .. }) => {
+                assert_eq!(reason, "liquidityNet would underflow active liquidity")
+            }
+            other => panic!("expected InvalidInput, got {:?}", other),
+        }
+        assert!(apply_liquidity_net_safe(0, i128::MIN).is_err());
+        assert!(apply_liquidity_net_safe(u128::MAX, 1).is_err());
+    }
+
+    #[test]
+    fn test_expected_sandwich_profit_with_volatility() {
+        let pool = pool_at_price_1(1_000_000_000_000_000_000_000u128, 5);
+        let victim = U256::from(100_000_000_000_000_000_000u128);
+
+        let precomputed = precompute_v3_state(pool.clone()).unwrap();
+
+        for direction in [SwapDirection::Token0ToToken1, SwapDirection::Token1ToToken0] {
+            let expected = |volatility_bps: u32, n_samples: usize| {
+                expected_sandwich_profit_with_volatility(
+                    &pool,
+                    victim,
+                    direction,
+                    volatility_bps,
+                    n_samples,
+                )
+            };
+
+            // Zero volatility reduces to the deterministic optimum
+            let (_, deterministic) = optimize_v3_sandwich_precomputed(
+                victim,
+                direction,
+                &precomputed,
+                BasisPoints::new_const(0),
+            )
+            .unwrap();
+            assert!(deterministic > U256::zero(), "direction={:?}", direction);
+            assert_eq!(expected(0, 100).unwrap(), deterministic);
+
+            // Seeded sampling is reproducible, and 0.5% noise moves the mean only slightly
+            let noisy = expected(50, 200).unwrap();
+            assert_eq!(noisy, expected(50, 200).unwrap());
+            assert!(
+                noisy > deterministic * 9 / 10 && noisy < deterministic * 11 / 10,
+                "direction={:?}, noisy={}, deterministic={}",
+                direction,
+                noisy,
+                deterministic
+            );
+
+            assert!(expected(50, 0).is_err());
+        }
+    }
+
+    #[test]
+    fn test_amount_in_to_reach_price() {
+        let sqrt_price = U256::from(79228162514264337593543950336u128);
+        let liquidity = 1_000_000_000_000_000_000_000u128;
+        let fee = BasisPoints::new_const(30);
+
+        // Raising sqrtP by 1% takes ~L * 1% of token1 before fee
+        let target_up = sqrt_price * U256::from(101) / U256::from(100);
+        let no_fee = amount_in_to_reach_price(
+            sqrt_price,
+            target_up,
+            liquidity,
+            BasisPoints::new_const(0),
+            SwapDirection::Token1ToToken0,
+        )
+        .unwrap();
+        assert!(no_fee > U256::from(9_999_999_999_999_999_999u128));
+        assert!(no_fee <= U256::from(10_000_000_000_000_000_000u128));
+
+        // Swapping the returned amount lands at or past the target
+        for (target, direction) in [
+            (target_up, SwapDirection::Token1ToToken0),
+            (sqrt_price * U256::from(99) / U256::from(100), SwapDirection::Token0ToToken1),
+        ] {
+            let amount_in =
+                amount_in_to_reach_price(sqrt_price, target, liquidity, fee, direction).unwrap();
+            let amount_out =
+                calculate_v3_amount_out(amount_in, sqrt_price, liquidity, fee, direction).unwrap();
+            let out_to_target = match direction {
+                SwapDirection::Token0ToToken1 => {
+                    get_amount1_delta(target, sqrt_price, liquidity, false).unwrap()
+                }
+                SwapDirection::Token1ToToken0 => {
+                    get_amount0_delta(sqrt_price, target, liquidity, false).unwrap()
+                }
+            };
+            assert!(amount_out >= out_to_target);
+        }
+
+        // Token0 in cannot raise the price
+        assert!(amount_in_to_reach_price(
+            sqrt_price,
+            target_up,
+            liquidity,
+            fee,
+            SwapDirection::Token0ToToken1
+        )
+        .is_err());
+        assert_eq!(
+            amount_in_to_reach_price(
+                sqrt_price,
+                sqrt_price,
+                liquidity,
+                fee,
+                SwapDirection::Token0ToToken1
+            )
+            .unwrap(),
+            U256::zero()
+        );
+    }
+
+    #[test]
+    fn test_position_in_range_fraction() {
+        let sqrt_price = U256::from(79228162514264337593543950336u128); // tick 0
+
+        assert_eq!(
+            position_in_range_fraction(-100, 100, sqrt_price).unwrap(),
+            RangePosition::InRange(0.5)
+        );
+        assert_eq!(
+            position_in_range_fraction(10, 100, sqrt_price).unwrap(),
+            RangePosition::Below
+        );
+        assert_eq!(
+            position_in_range_fraction(-100, -10, sqrt_price).unwrap(),
+            RangePosition::Above
+        );
+        assert!(position_in_range_fraction(100, 100, sqrt_price).is_err());
+    }
+
+    #[test]
+    fn test_position_in_range_fraction_edges() {
+        let at_tick = |tick: i32| get_sqrt_ratio_at_tick(tick).unwrap();
+
+        // Lower tick is inclusive
+        assert_eq!(
+            position_in_range_fraction(0, 100, at_tick(0)).unwrap(),
+            RangePosition::InRange(0.0)
+        );
+        assert_eq!(
+            position_in_range_fraction(0, 100, at_tick(-1)).unwrap(),
+            RangePosition::Below
+        );
+
+        // Upper tick is exclusive
+        assert_eq!(
+            position_in_range_fraction(0, 100, at_tick(99)).unwrap(),
+            RangePosition::InRange(0.99)
+        );
+        assert_eq!(
+            position_in_range_fraction(0, 100, at_tick(100)).unwrap(),
+            RangePosition::Above
+        );
+    }
+
+    #[test]
+    fn test_tick_range_coverage_probability() {
+        assert_eq!(tick_range_coverage_probability(-100, 100, 0, 30), 1.0);
+        assert_eq!(tick_range_coverage_probability(100, -100, 50, 30), 0.0);
+
+        // A range much wider than the move over the period is almost always in range
+        let wide = tick_range_coverage_probability(-5000, 5000, 100, 7);
+        assert!(wide > 0.999);
+
+        // Narrower ranges and longer holds spend less time in range
+        let narrow = tick_range_coverage_probability(-100, 100, 100, 7);
+        let narrow_long = tick_range_coverage_probability(-100, 100, 100, 70);
+        assert!(narrow < wide);
+        assert!(narrow_long < narrow);
+        assert!(narrow_long > 0.0);
+    }
+}
@@ -0,0 +1,182 @@
+//! Uniswap V4 Math
+//!
+//! V4 keeps V3's Q64.96 sqrt prices and tick math, so those are re-exported
+//! from the V3 module rather than duplicated. What changes:
+//!
+//! - **Singleton**: every pool lives in one `PoolManager` and is addressed by a
+//!   `PoolId` (keccak256 of the ABI-encoded `PoolKey`) instead of a contract address
+//! - **Hooks**: the hook contract is part of the key, and hooks may take an extra
+//!   cut of the swap output on top of the LP fee
+//! - **Fees**: `fee` is a uint24 in hundredths of a bip (3000 = 0.30%); the
+//!   `DYNAMIC_FEE_FLAG` value means the hook sets the fee per swap
+
+use crate::core::{BasisPoints, MathError};
+use ethers::abi::{encode, Token};
+use ethers::types::{Address, I256, U256};
+use ethers::utils::keccak256;
+use primitive_types::U512;
+
+pub use crate::dex::uniswap_v3::math::{
+    get_sqrt_ratio_at_tick, sqrt_price_to_tick, MAX_TICK, MIN_SQRT_RATIO, MIN_TICK,
+};
+
+/// `PoolKey.fee` value marking a pool whose fee is set by its hook
+pub const DYNAMIC_FEE_FLAG: u32 = 0x800000;
+
+/// `PoolKey.fee` denominator: hundredths of a bip in 100%
+const FEE_DENOMINATOR_PIPS: u32 = 1_000_000;
+
+/// Uniswap V4 `PoolKey`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct V4PoolKey {
+    /// Lower-sorted currency (`Address::zero()` for native ETH)
+    pub currency0: Address,
+    /// Higher-sorted currency
+    pub currency1: Address,
+    /// LP fee as a uint24 in hundredths of a bip, or `DYNAMIC_FEE_FLAG`
+    pub fee: u32,
+    /// Tick spacing (int24)
+    pub tick_spacing: i32,
+    /// Hook contract (`Address::zero()` for none)
+    pub hooks: Address,
+}
+
+impl V4PoolKey {
+    /// Static LP fee in basis points
+    ///
+    /// # Returns
+    /// * `Ok(BasisPoints)` - Fee rounded down to whole basis points
+    /// * `Err(MathError)` - If the pool uses a dynamic fee or the fee is not a valid uint24
+    pub fn fee_bps(&self) -> Result<BasisPoints, MathError> {
+        if self.fee == DYNAMIC_FEE_FLAG || self.fee > FEE_DENOMINATOR_PIPS {
+            return Err(MathError::InvalidInput {
+                operation: "V4PoolKey::fee_bps".to_string(),
+                reason: "Pool fee is dynamic or out of range".to_string(),
+                context: format!("fee={:#x}, hooks={:?}", self.fee, self.hooks),
+            });
+        }
+        BasisPoints::from_fee_amount(U256::from(self.fee), U256::from(FEE_DENOMINATOR_PIPS))
+    }
+}
+
+/// Compute a V4 `PoolId` (`keccak256(abi.encode(key))`)
+///
+/// # Arguments
+/// * `key` - Pool key
+///
+/// # Returns
+/// * `[u8; 32]` - Pool id as used by the `PoolManager`
+pub fn compute_pool_id(key: &V4PoolKey) -> [u8; 32] {
+    keccak256(encode(&[
+        Token::Address(key.currency0),
+        Token::Address(key.currency1),
+        Token::Uint(U256::from(key.fee)),
+        Token::Int(I256::from(key.tick_spacing).into_raw()),
+        Token::Address(key.hooks),
+    ]))
+}
+
+/// Apply a hook's extra fee to the pool's swap output
+///
+/// # Arguments
+/// * `base_output` - Output after the LP fee, as computed by the V3 swap math
+/// * `hook_fee_bps` - Fee the hook takes from the output
+///
+/// # Returns
+/// * `Ok(U256)` - Output received by the swapper (rounded down)
+/// * `Err(MathError)` - If the hook fee is 100% or more
+pub fn calculate_v4_hook_adjusted_amount_out(
+    base_output: U256,
+    hook_fee_bps: BasisPoints,
+) -> Result<U256, MathError> {
+    if hook_fee_bps.as_u32() >= 10000 {
+        return Err(MathError::InvalidInput {
+            operation: "calculate_v4_hook_adjusted_amount_out".to_string(),
+            reason: "Hook fee must be below 10000 bps".to_string(),
+            context: format!(
+                "base_output={}, hook_fee_bps={}",
+                base_output,
+                hook_fee_bps.as_u32()
+            ),
+        });
+    }
+
+    // Keeping the floor of the remainder rounds the hook fee up against the
+    // swapper, as fee deltas do on-chain; the result never exceeds base_output
+    let kept_bps = U256::from(10000 - hook_fee_bps.as_u32());
+    let kept = base_output.full_mul(kept_bps) / U512::from(10000u32);
+    U256::try_from(kept).map_err(|_| MathError::Overflow {
+        operation: "calculate_v4_hook_adjusted_amount_out".to_string(),
+        inputs: vec![base_output, U256::from(hook_fee_bps.as_u32())],
+        context: "Hook-adjusted output".to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(tick_spacing: i32, hooks: Address) -> V4PoolKey {
+        V4PoolKey {
+            currency0: Address::zero(),
+            currency1: Address::from_low_u64_be(0xA0B8),
+            fee: 3000,
+            tick_spacing,
+            hooks,
+        }
+    }
+
+    #[test]
+    fn test_compute_pool_id() {
+        let plain = compute_pool_id(&key(60, Address::zero()));
+        assert_eq!(plain, compute_pool_id(&key(60, Address::zero())));
+
+        // Hooks and tick spacing are part of the pool identity
+        assert_ne!(plain, compute_pool_id(&key(60, Address::from_low_u64_be(1))));
+        assert_ne!(plain, compute_pool_id(&key(10, Address::zero())));
+
+        // Negative int24 values are sign-extended like abi.encode
+        let mut encoded = Vec::new();
+        encoded.extend_from_slice(&[0u8; 32]);
+        encoded.extend_from_slice(&[0u8; 30]);
+        encoded.extend_from_slice(&[0xA0, 0xB8]);
+        encoded.extend_from_slice(&[0u8; 30]);
+        encoded.extend_from_slice(&3000u16.to_be_bytes());
+        encoded.extend_from_slice(&[0xFF; 31]);
+        encoded.push(0xC4); // -60
+        encoded.extend_from_slice(&[0u8; 32]);
+        assert_eq!(compute_pool_id(&key(-60, Address::zero())), keccak256(encoded));
+    }
+
+    #[test]
+    fn test_v4_fee_bps() {
+        assert_eq!(key(60, Address::zero()).fee_bps().unwrap().as_u32(), 30);
+
+        let mut dynamic = key(60, Address::zero());
+        dynamic.fee = DYNAMIC_FEE_FLAG;
+        assert!(dynamic.fee_bps().is_err());
+    }
+
+    #[test]
+    fn test_v4_hook_adjusted_amount_out() {
+        let base = U256::from(1_000_000u64);
+        assert_eq!(
+            calculate_v4_hook_adjusted_amount_out(base, BasisPoints::new_const(0)).unwrap(),
+            base
+        );
+        assert_eq!(
+            calculate_v4_hook_adjusted_amount_out(base, BasisPoints::new_const(30)).unwrap(),
+            U256::from(997_000u64)
+        );
+
+        // Partial units of fee round against the swapper
+        assert_eq!(
+            calculate_v4_hook_adjusted_amount_out(U256::from(101u64), BasisPoints::new_const(100))
+                .unwrap(),
+            U256::from(99u64)
+        );
+        assert!(
+            calculate_v4_hook_adjusted_amount_out(base, BasisPoints::new_const(10000)).is_err()
+        );
+    }
+}